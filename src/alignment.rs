@@ -0,0 +1,448 @@
+pub mod data;
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+    process::{Child, Command, Stdio},
+    thread::{self, JoinHandle},
+    vec,
+};
+
+use flate2::read::MultiGzDecoder;
+use noodles_bam as bam;
+use noodles_sam::{self as sam, header::ReferenceSequences};
+
+const BAM_MAGIC: &[u8] = b"BAM\x01";
+const CRAM_MAGIC: &[u8] = b"CRAM";
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+static SAMTOOLS: &str = "samtools";
+static STDIN: &str = "-";
+
+// The number of SAM records converted to BAM records at a time
+const SAM_BATCH_SIZE: usize = 4096;
+
+pub type Records = Box<dyn Iterator<Item = io::Result<bam::Record>>>;
+
+type PeekedReader<R> = io::Chain<io::Cursor<Vec<u8>>, R>;
+
+/// Alignment format
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    /// Sequence Alignment/Map, optionally gzip-compressed
+    Sam,
+    /// Binary Alignment/Map
+    Bam,
+    /// CRAM
+    Cram,
+}
+
+/// Detects the format of an alignment stream from its magic number.
+///
+/// The start of the stream is read until the format is known, e.g., for a
+/// gzip-compressed stream, until its first 4 bytes are decompressed, or EOF. A
+/// single read may only return part of the first block, e.g., from a pipe.
+///
+/// This returns the format and a reader of the whole stream, i.e., including
+/// the data read for detection.
+fn detect_format<R>(mut reader: R) -> io::Result<(Format, PeekedReader<R>)>
+where
+    R: BufRead,
+{
+    let mut buf = Vec::new();
+
+    let format = loop {
+        let src = reader.fill_buf()?;
+        let is_eof = src.is_empty();
+        let len = src.len();
+
+        buf.extend_from_slice(src);
+        reader.consume(len);
+
+        if let Some(format) = detect_format_from_buf(&buf, is_eof)? {
+            break format;
+        }
+    };
+
+    Ok((format, io::Cursor::new(buf).chain(reader)))
+}
+
+/// Detects the format of the start of an alignment stream.
+///
+/// This returns `None` if more data is needed.
+fn detect_format_from_buf(buf: &[u8], is_eof: bool) -> io::Result<Option<Format>> {
+    if buf.starts_with(CRAM_MAGIC) {
+        return Ok(Some(Format::Cram));
+    } else if buf.len() < CRAM_MAGIC.len() && !is_eof {
+        return Ok(None);
+    } else if !buf.starts_with(GZIP_MAGIC) {
+        return Ok(Some(Format::Sam));
+    }
+
+    let mut decoder = MultiGzDecoder::new(buf).take(BAM_MAGIC.len() as u64);
+    let mut magic = Vec::with_capacity(BAM_MAGIC.len());
+
+    // A stream that decompresses to fewer than 4 bytes is a (nearly) empty SAM
+    // file. An incomplete stream is only an error at EOF.
+    match decoder.read_to_end(&mut magic) {
+        Ok(_) if magic == BAM_MAGIC => Ok(Some(Format::Bam)),
+        Ok(_) => Ok(Some(Format::Sam)),
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof && !is_eof => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns whether the source is stdin (`-`).
+pub fn is_stdin<P>(src: P) -> bool
+where
+    P: AsRef<Path>,
+{
+    src.as_ref() == Path::new(STDIN)
+}
+
+/// Opens an alignment file and returns its format, header, and an iterator
+/// over its records.
+///
+/// If `src` is `-`, the alignment is read from stdin. SAM and CRAM records are
+/// converted to BAM records. CRAM files are decoded by `samtools view`, which
+/// must be in the `PATH`. `reference_src` is the reference sequence FASTA used
+/// to decode CRAM records and is ignored for other formats.
+pub fn open<P>(
+    src: P,
+    reference_src: Option<&Path>,
+) -> io::Result<(Format, noodles_sam::Header, Records)>
+where
+    P: AsRef<Path>,
+{
+    let src = src.as_ref();
+
+    let reader: Box<dyn BufRead + Send> = if is_stdin(src) {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        File::open(src).map(BufReader::new).map(Box::new)?
+    };
+
+    let (format, mut reader) = detect_format(reader)?;
+
+    let (header, records) = match format {
+        Format::Sam => {
+            let inner: Box<dyn BufRead> = if reader.fill_buf()?.starts_with(GZIP_MAGIC) {
+                Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+            } else {
+                Box::new(reader)
+            };
+
+            open_sam(inner)?
+        }
+        Format::Bam => {
+            let mut reader = bam::Reader::new(reader);
+            let header = parse_header(&reader.read_header()?)?;
+            reader.read_reference_sequences()?;
+            (header, Box::new(BamRecords { reader }) as Records)
+        }
+        Format::Cram => open_cram(Box::new(reader), reference_src)?,
+    };
+
+    Ok((format, header, records))
+}
+
+fn open_sam<R>(inner: R) -> io::Result<(noodles_sam::Header, Records)>
+where
+    R: BufRead + 'static,
+{
+    let mut reader = sam::Reader::new(inner);
+    let header = parse_header(&reader.read_header()?)?;
+    let reference_sequences = header.reference_sequences().clone();
+
+    let records = SamRecords {
+        reader,
+        reference_sequences,
+        buf: String::new(),
+        batch: Vec::new().into_iter(),
+    };
+
+    Ok((header, Box::new(records)))
+}
+
+fn open_cram(
+    reader: Box<dyn BufRead + Send>,
+    reference_src: Option<&Path>,
+) -> io::Result<(noodles_sam::Header, Records)> {
+    let mut command = Command::new(SAMTOOLS);
+    command.arg("view").arg("-h");
+
+    if let Some(reference_src) = reference_src {
+        command.arg("-T").arg(reference_src);
+    }
+
+    command.arg(STDIN);
+
+    open_with_decoder(reader, command, SAMTOOLS)
+}
+
+/// Opens an alignment stream decoded by a child process.
+///
+/// The child reads the stream from stdin and writes SAM to stdout.
+fn open_with_decoder(
+    mut reader: Box<dyn BufRead + Send>,
+    mut command: Command,
+    name: &'static str,
+) -> io::Result<(noodles_sam::Header, Records)> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("could not run {} to decode CRAM: {}", name, e),
+            )
+        })?;
+
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "missing stdin"))?;
+
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "missing stdout"))?;
+
+    let copier = thread::spawn(move || io::copy(&mut reader, &mut stdin).map(|_| ()));
+
+    let (header, records) = open_sam(BufReader::new(stdout))?;
+
+    Ok((
+        header,
+        Box::new(ChildRecords {
+            records,
+            child,
+            name,
+            copier: Some(copier),
+        }),
+    ))
+}
+
+fn parse_header(s: &str) -> io::Result<noodles_sam::Header> {
+    s.parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+struct BamRecords<R> {
+    reader: bam::Reader<R>,
+}
+
+impl<R> Iterator for BamRecords<R>
+where
+    R: Read,
+{
+    type Item = io::Result<bam::Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = bam::Record::default();
+
+        match self.reader.read_record(&mut record) {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(record)),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// SAM records converted to BAM records.
+///
+/// Records are parsed by the SAM reader and encoded by the BAM writer in
+/// batches, which are then read back as BAM records.
+struct SamRecords<R> {
+    reader: sam::Reader<R>,
+    reference_sequences: ReferenceSequences,
+    buf: String,
+    batch: vec::IntoIter<bam::Record>,
+}
+
+impl<R> SamRecords<R>
+where
+    R: BufRead,
+{
+    /// Reads the next batch of records.
+    ///
+    /// This returns `false` at EOF.
+    fn read_batch(&mut self) -> io::Result<bool> {
+        let mut writer = bam::Writer::new(Vec::new());
+        let mut n = 0;
+
+        while n < SAM_BATCH_SIZE {
+            self.buf.clear();
+
+            if self.reader.read_record(&mut self.buf)? == 0 {
+                break;
+            }
+
+            let record: sam::Record = self
+                .buf
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+            writer.write_sam_record(&self.reference_sequences, &record)?;
+
+            n += 1;
+        }
+
+        if n == 0 {
+            return Ok(false);
+        }
+
+        writer.try_finish()?;
+
+        let reader = bam::Reader::new(&writer.get_ref()[..]);
+        let records: Vec<_> = BamRecords { reader }.collect::<io::Result<_>>()?;
+        self.batch = records.into_iter();
+
+        Ok(true)
+    }
+}
+
+impl<R> Iterator for SamRecords<R>
+where
+    R: BufRead,
+{
+    type Item = io::Result<bam::Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.batch.next() {
+                return Some(Ok(record));
+            }
+
+            match self.read_batch() {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Records read from the output of a child process.
+///
+/// The input is written to the child by a separate thread. Both the result of
+/// the copy and the exit status of the child are checked when the records are
+/// exhausted.
+struct ChildRecords {
+    records: Records,
+    child: Child,
+    name: &'static str,
+    copier: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl Iterator for ChildRecords {
+    type Item = io::Result<bam::Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(result) = self.records.next() {
+            return Some(result);
+        }
+
+        if let Some(copier) = self.copier.take() {
+            match copier.join() {
+                Ok(Ok(())) => {}
+                Ok(Err(ref e)) if e.kind() == io::ErrorKind::BrokenPipe => {}
+                Ok(Err(e)) => return Some(Err(e)),
+                Err(_) => {
+                    return Some(Err(io::Error::new(
+                        io::ErrorKind::Other,
+                        "could not write input to child process",
+                    )))
+                }
+            }
+        }
+
+        match self.child.wait() {
+            Ok(status) if status.success() => None,
+            Ok(status) => Some(Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("{} exited with {}", self.name, status),
+            ))),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::*;
+
+    #[test]
+    fn test_detect_format() -> io::Result<()> {
+        fn t(data: &[u8], capacity: usize) -> io::Result<Format> {
+            let reader = BufReader::with_capacity(capacity, data);
+            let (format, mut reader) = detect_format(reader)?;
+
+            let mut buf = Vec::new();
+            reader.read_to_end(&mut buf)?;
+            assert_eq!(buf, data);
+
+            Ok(format)
+        }
+
+        let data = b"CRAM\x03\x00";
+        assert_eq!(t(data, 8192)?, Format::Cram);
+        assert_eq!(t(data, 1)?, Format::Cram);
+
+        let data = b"@HD\tVN:1.6\n";
+        assert_eq!(t(data, 8192)?, Format::Sam);
+        assert_eq!(t(data, 1)?, Format::Sam);
+
+        let data = b"";
+        assert_eq!(t(data, 8192)?, Format::Sam);
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"BAM\x01\x00\x00\x00\x00")?;
+        let data = encoder.finish()?;
+        assert_eq!(t(&data, 8192)?, Format::Bam);
+        assert_eq!(t(&data, 4)?, Format::Bam);
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"@HD\tVN:1.6\n")?;
+        let data = encoder.finish()?;
+        assert_eq!(t(&data, 8192)?, Format::Sam);
+        assert_eq!(t(&data, 4)?, Format::Sam);
+
+        assert!(t(&data[..6], 4).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_with_decoder() -> io::Result<()> {
+        // The decoder stands in for `samtools view -h -`, stripping the magic
+        // number from the input and passing through the rest as SAM.
+        let data = b"CRAM@HD\tVN:1.6\n@SQ\tSN:sq0\tLN:8\nr0\t0\tsq0\t1\t60\t4M\t*\t0\t0\tACGT\tNDLS\nr1\t16\tsq0\t5\t60\t4M\t*\t0\t0\tTGCA\tSLDN\n";
+        let reader: Box<dyn BufRead + Send> = Box::new(io::Cursor::new(data.to_vec()));
+
+        let mut command = Command::new("tail");
+        command.arg("-c").arg("+5");
+
+        let (header, records) = open_with_decoder(reader, command, "tail")?;
+        assert_eq!(header.reference_sequences().len(), 1);
+
+        let records: Vec<_> = records.collect::<io::Result<_>>()?;
+        assert_eq!(records.len(), 2);
+        assert!(!records[0].flags().is_reverse_complemented());
+        assert!(records[1].flags().is_reverse_complemented());
+
+        let reader: Box<dyn BufRead + Send> = Box::new(io::Cursor::new(data.to_vec()));
+        let command = Command::new("false");
+        let result = open_with_decoder(reader, command, "false")
+            .and_then(|(_, records)| records.collect::<io::Result<Vec<_>>>());
+        assert!(result.is_err());
+
+        Ok(())
+    }
+}
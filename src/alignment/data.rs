@@ -0,0 +1,155 @@
+use std::{io, ops::Range};
+
+use noodles_bam::{self as bam, record::data::field::Value};
+
+/// Sets a string (`Z`) data field of a BAM record.
+///
+/// Any existing field with the same tag is replaced. The new field is
+/// appended to the end of the data.
+pub fn set_string_field(record: &mut bam::Record, tag: &[u8; 2], value: &str) -> io::Result<()> {
+    if let Some(range) = find_field(record, tag)? {
+        let len = record.len();
+        record.copy_within(range.end.., range.start);
+        record.resize(len - range.len());
+    }
+
+    let start = record.len();
+    let field_len = tag.len() + 1 + value.len() + 1;
+    record.resize(start + field_len);
+
+    let buf = &mut record[start..];
+    buf[..2].copy_from_slice(tag);
+    buf[2] = b'Z';
+    buf[3..3 + value.len()].copy_from_slice(value.as_bytes());
+    buf[3 + value.len()] = 0;
+
+    Ok(())
+}
+
+/// Returns the value of a string (`Z`) data field of a BAM record.
+///
+/// This returns `None` if the field is missing or is not a string.
+pub fn get_string_field(record: &bam::Record, tag: &[u8; 2]) -> io::Result<Option<String>> {
+    for result in record.data().fields() {
+        let field = result?;
+
+        if field.tag().as_ref().as_bytes() == tag {
+            return match field.value() {
+                Value::String(s) => Ok(Some(s.clone())),
+                _ => Ok(None),
+            };
+        }
+    }
+
+    Ok(None)
+}
+
+/// Finds the byte range of the field with the given tag in the record.
+fn find_field(record: &bam::Record, tag: &[u8; 2]) -> io::Result<Option<Range<usize>>> {
+    let data = record.data();
+    let mut start = record.len() - data.len();
+
+    for result in data.fields() {
+        let field = result?;
+        let end = start + 3 + value_len(field.value());
+
+        if field.tag().as_ref().as_bytes() == tag {
+            return Ok(Some(start..end));
+        }
+
+        start = end;
+    }
+
+    Ok(None)
+}
+
+/// Returns the encoded length of a field value, excluding its type.
+fn value_len(value: &Value) -> usize {
+    match value {
+        Value::Char(_) | Value::Int8(_) | Value::UInt8(_) => 1,
+        Value::Int16(_) | Value::UInt16(_) => 2,
+        Value::Int32(_) | Value::UInt32(_) | Value::Float(_) => 4,
+        Value::String(s) | Value::Hex(s) => s.len() + 1,
+        // subtype and count
+        Value::Int8Array(v) => 5 + v.len(),
+        Value::UInt8Array(v) => 5 + v.len(),
+        Value::Int16Array(v) => 5 + 2 * v.len(),
+        Value::UInt16Array(v) => 5 + 2 * v.len(),
+        Value::Int32Array(v) => 5 + 4 * v.len(),
+        Value::UInt32Array(v) => 5 + 4 * v.len(),
+        Value::FloatArray(v) => 5 + 4 * v.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // refID, pos, l_read_name, mapq, bin, n_cigar_op, flag, l_seq, next_refID,
+    // next_pos, tlen
+    const FIXED_FIELDS_LEN: usize = 32;
+
+    fn build_record(data: &[u8]) -> bam::Record {
+        let mut buf = vec![0; FIXED_FIELDS_LEN];
+        buf[8] = 3; // l_read_name
+        buf[12..14].copy_from_slice(&1u16.to_le_bytes()); // n_cigar_op
+        buf[16..20].copy_from_slice(&4u32.to_le_bytes()); // l_seq
+
+        buf.extend_from_slice(b"r0\x00"); // read_name
+        buf.extend_from_slice(&0x40u32.to_le_bytes()); // cigar (4M)
+        buf.extend_from_slice(&[0x12, 0x48]); // seq (ACGT)
+        buf.extend_from_slice(&[0xff; 4]); // qual
+        buf.extend_from_slice(data);
+
+        let mut record = bam::Record::default();
+        record.resize(buf.len());
+        record.copy_from_slice(&buf);
+        record
+    }
+
+    #[test]
+    fn test_set_string_field() -> io::Result<()> {
+        let mut record = build_record(b"NHC\x01");
+        set_string_field(&mut record, b"XF", "AADAT")?;
+        assert_eq!(&record[..], &build_record(b"NHC\x01XFZAADAT\x00")[..]);
+
+        let mut record = build_record(b"XFZ__no_feature\x00NHC\x01");
+        set_string_field(&mut record, b"XF", "CLN3")?;
+        assert_eq!(&record[..], &build_record(b"NHC\x01XFZCLN3\x00")[..]);
+
+        let mut record = build_record(b"ZBBC\x02\x00\x00\x00\x01\x02XFZ__ambiguous\x00");
+        set_string_field(&mut record, b"XF", "PAK4")?;
+        assert_eq!(
+            &record[..],
+            &build_record(b"ZBBC\x02\x00\x00\x00\x01\x02XFZPAK4\x00")[..]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_string_field() -> io::Result<()> {
+        let record = build_record(b"NHC\x01CBZACGT-1\x00UBZTTGCA\x00");
+        assert_eq!(
+            get_string_field(&record, b"CB")?,
+            Some(String::from("ACGT-1"))
+        );
+        assert_eq!(
+            get_string_field(&record, b"UB")?,
+            Some(String::from("TTGCA"))
+        );
+        assert_eq!(get_string_field(&record, b"NH")?, None);
+        assert_eq!(get_string_field(&record, b"RX")?, None);
+
+        let record = build_record(b"CBZAC");
+        assert!(get_string_field(&record, b"CB").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_string_field_with_invalid_data() {
+        let mut record = build_record(b"NHQ\x01XFZAADAT\x00");
+        assert!(set_string_field(&mut record, b"XF", "CLN3").is_err());
+    }
+}
@@ -0,0 +1,312 @@
+use std::{
+    collections::HashMap,
+    error, fmt,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+    str::FromStr,
+};
+
+use flate2::read::MultiGzDecoder;
+
+use crate::{
+    bed, gtf,
+    hierarchy::{self, Hierarchy},
+    read_features as read_gff_features, saf, Feature,
+};
+
+const GZ_EXTENSION: &str = "gz";
+
+/// Annotations format
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    /// General Feature Format version 3
+    Gff3,
+    /// Gene Transfer Format
+    Gtf,
+    /// Browser Extensible Data (BED3 to BED12)
+    Bed,
+    /// Simplified Annotation Format (featureCounts)
+    Saf,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid annotations format: {}", self.0)
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl FromStr for Format {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gff3" => Ok(Self::Gff3),
+            "gtf" => Ok(Self::Gtf),
+            "bed" => Ok(Self::Bed),
+            "saf" => Ok(Self::Saf),
+            _ => Err(ParseError(s.into())),
+        }
+    }
+}
+
+/// Reads features from an annotations file.
+///
+/// If the format is not given, it is detected from the file extension or,
+/// failing that, the first record. The feature type and ID only apply to GFF3
+/// and GTF. BED features are identified by name, and SAF features, by
+/// `GeneID`.
+pub fn read_features<P>(
+    src: P,
+    format: Option<Format>,
+    feature_type: &str,
+    feature_id: &str,
+) -> io::Result<HashMap<String, Vec<Feature>>>
+where
+    P: AsRef<Path>,
+{
+    let src = src.as_ref();
+
+    let format = match format {
+        Some(f) => f,
+        None => detect_format(src)?,
+    };
+
+    let mut inner = open(src)?;
+
+    match format {
+        Format::Gff3 => {
+            let mut reader = noodles_gff::Reader::new(inner);
+            read_gff_features(&mut reader, feature_type, feature_id)
+        }
+        Format::Gtf => {
+            let mut reader = gtf::Reader::new(inner);
+            gtf::read_features(&mut reader, feature_type, feature_id)
+        }
+        Format::Bed => bed::read_features(&mut inner),
+        Format::Saf => saf::read_features(&mut inner),
+    }
+}
+
+/// Reads the exon hierarchy from an annotations file.
+///
+/// See [`read_features`] for how the format is determined. BED and SAF do not
+/// describe a feature hierarchy and are not supported.
+pub fn read_hierarchy<P>(
+    src: P,
+    format: Option<Format>,
+    feature_type: &str,
+) -> io::Result<Hierarchy>
+where
+    P: AsRef<Path>,
+{
+    let src = src.as_ref();
+
+    let format = match format {
+        Some(f) => f,
+        None => detect_format(src)?,
+    };
+
+    let inner = open(src)?;
+
+    match format {
+        Format::Gff3 => {
+            let mut reader = noodles_gff::Reader::new(inner);
+            hierarchy::read_gff_hierarchy(&mut reader, feature_type)
+        }
+        Format::Gtf => {
+            let mut reader = gtf::Reader::new(inner);
+            gtf::read_hierarchy(&mut reader, feature_type)
+        }
+        Format::Bed | Format::Saf => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{:?} annotations do not have a feature hierarchy", format),
+        )),
+    }
+}
+
+/// Reads the exon hierarchies of multiple feature types from an annotations file
+/// in a single pass.
+///
+/// This is the same as [`read_hierarchy`] for each feature type, keyed by
+/// feature type.
+pub fn read_hierarchies<P>(
+    src: P,
+    format: Option<Format>,
+    feature_types: &[&str],
+) -> io::Result<HashMap<String, Hierarchy>>
+where
+    P: AsRef<Path>,
+{
+    let src = src.as_ref();
+
+    let format = match format {
+        Some(f) => f,
+        None => detect_format(src)?,
+    };
+
+    let inner = open(src)?;
+
+    match format {
+        Format::Gff3 => {
+            let mut reader = noodles_gff::Reader::new(inner);
+            hierarchy::read_gff_hierarchies(&mut reader, feature_types)
+        }
+        Format::Gtf => {
+            let mut reader = gtf::Reader::new(inner);
+            gtf::read_hierarchies(&mut reader, feature_types)
+        }
+        Format::Bed | Format::Saf => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{:?} annotations do not have a feature hierarchy", format),
+        )),
+    }
+}
+
+/// Opens a text file, decompressing it if it has a `.gz` extension.
+pub(crate) fn open<P>(src: P) -> io::Result<Box<dyn BufRead>>
+where
+    P: AsRef<Path>,
+{
+    let path = src.as_ref();
+    let extension = path.extension();
+    let file = File::open(path)?;
+
+    match extension.and_then(|ext| ext.to_str()) {
+        Some(GZ_EXTENSION) => {
+            let decoder = MultiGzDecoder::new(file);
+            Ok(Box::new(BufReader::new(decoder)))
+        }
+        _ => Ok(Box::new(BufReader::new(file))),
+    }
+}
+
+fn detect_format(src: &Path) -> io::Result<Format> {
+    if let Some(format) = detect_format_from_extension(src) {
+        return Ok(format);
+    }
+
+    let mut reader = open(src)?;
+    let mut buf = String::new();
+
+    loop {
+        buf.clear();
+
+        if reader.read_line(&mut buf)? == 0 {
+            return Ok(Format::Gff3);
+        }
+
+        let line = buf.trim_end();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        return Ok(detect_format_from_line(line));
+    }
+}
+
+fn detect_format_from_extension(src: &Path) -> Option<Format> {
+    let path = if src.extension().and_then(|ext| ext.to_str()) == Some(GZ_EXTENSION) {
+        src.file_stem().map(Path::new)?
+    } else {
+        src
+    };
+
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("gtf") => Some(Format::Gtf),
+        Some("gff") | Some("gff3") => Some(Format::Gff3),
+        Some("bed") => Some(Format::Bed),
+        Some("saf") => Some(Format::Saf),
+        _ => None,
+    }
+}
+
+/// Detects the format of a record from its attributes field.
+///
+/// GFF3 attributes are `key=value` pairs, whereas GTF attributes are
+/// whitespace-separated `key "value"` pairs.
+fn detect_format_from_line(line: &str) -> Format {
+    let attributes = line.split('\t').nth(8).unwrap_or_default();
+    let first_attribute = attributes.split(';').next().unwrap_or_default().trim();
+
+    match first_attribute.find(|c: char| c == '=' || c.is_whitespace()) {
+        Some(i) if first_attribute[i..].starts_with('=') => Format::Gff3,
+        Some(_) => Format::Gtf,
+        None => Format::Gff3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str() -> Result<(), ParseError> {
+        assert_eq!("gff3".parse::<Format>()?, Format::Gff3);
+        assert_eq!("gtf".parse::<Format>()?, Format::Gtf);
+        assert_eq!("bed".parse::<Format>()?, Format::Bed);
+        assert_eq!("saf".parse::<Format>()?, Format::Saf);
+
+        assert!("".parse::<Format>().is_err());
+        assert!("GTF".parse::<Format>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_format_from_extension() {
+        assert_eq!(
+            detect_format_from_extension(Path::new("annotations.gtf")),
+            Some(Format::Gtf)
+        );
+        assert_eq!(
+            detect_format_from_extension(Path::new("annotations.gtf.gz")),
+            Some(Format::Gtf)
+        );
+        assert_eq!(
+            detect_format_from_extension(Path::new("annotations.gff3")),
+            Some(Format::Gff3)
+        );
+        assert_eq!(
+            detect_format_from_extension(Path::new("annotations.gff.gz")),
+            Some(Format::Gff3)
+        );
+        assert_eq!(
+            detect_format_from_extension(Path::new("peaks.bed.gz")),
+            Some(Format::Bed)
+        );
+        assert_eq!(
+            detect_format_from_extension(Path::new("annotations.saf")),
+            Some(Format::Saf)
+        );
+        assert_eq!(
+            detect_format_from_extension(Path::new("annotations.txt.gz")),
+            None
+        );
+        assert_eq!(detect_format_from_extension(Path::new("annotations")), None);
+    }
+
+    #[test]
+    fn test_detect_format_from_line() {
+        assert_eq!(
+            detect_format_from_line("sq0\t.\texon\t1\t10\t.\t+\t.\tID=exon0;gene_id=gene0"),
+            Format::Gff3
+        );
+        assert_eq!(
+            detect_format_from_line(
+                "sq0\t.\texon\t1\t10\t.\t+\t.\tgene_id \"gene0\"; transcript_id \"tx0\";"
+            ),
+            Format::Gtf
+        );
+        assert_eq!(
+            detect_format_from_line("sq0\t.\texon\t1\t10\t.\t+\t.\tgene_id=gene 0"),
+            Format::Gff3
+        );
+    }
+}
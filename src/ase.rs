@@ -0,0 +1,556 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use noodles_bam as bam;
+
+use crate::MatchIntervals;
+
+const DELIMITER: char = '\t';
+const HEADER_PREFIX: char = '#';
+const MISSING: &str = ".";
+
+const GENOTYPE_KEY: &str = "GT";
+
+/// A biallelic single nucleotide polymorphism (SNP)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Snp {
+    reference_sequence_name: String,
+    position: u64,
+    id: String,
+    reference_base: u8,
+    alternate_base: u8,
+}
+
+impl Snp {
+    pub fn new(
+        reference_sequence_name: String,
+        position: u64,
+        id: String,
+        reference_base: u8,
+        alternate_base: u8,
+    ) -> Self {
+        Self {
+            reference_sequence_name,
+            position,
+            id,
+            reference_base,
+            alternate_base,
+        }
+    }
+
+    pub fn reference_sequence_name(&self) -> &str {
+        &self.reference_sequence_name
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Returns the ID of the SNP or, if missing, its position, e.g., `chr1:8`.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn reference_base(&self) -> u8 {
+        self.reference_base
+    }
+
+    pub fn alternate_base(&self) -> u8 {
+        self.alternate_base
+    }
+
+    /// Classifies a read base as the reference or alternate allele.
+    pub fn allele(&self, base: u8) -> Allele {
+        let base = base.to_ascii_uppercase();
+
+        if base == self.reference_base {
+            Allele::Reference
+        } else if base == self.alternate_base {
+            Allele::Alternate
+        } else {
+            Allele::Other
+        }
+    }
+}
+
+/// The allele of a read at a SNP
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Allele {
+    Reference,
+    Alternate,
+    /// neither the reference nor the alternate base, e.g., `N` or a
+    /// sequencing error, or mates that disagree
+    Other,
+}
+
+/// Reads the biallelic SNPs of a VCF.
+///
+/// Records that are not SNPs with a single alternate base, e.g., indels and
+/// multiallelic sites, are skipped. When the first sample has a genotype
+/// (`GT`), records that are not heterozygous are also skipped. The number of
+/// skipped records is returned with the SNPs.
+pub fn read_snps<R>(reader: &mut R) -> io::Result<(Vec<Snp>, usize)>
+where
+    R: BufRead,
+{
+    let mut snps = Vec::new();
+    let mut skipped_count = 0;
+
+    for result in reader.lines() {
+        let line = result?;
+
+        if line.is_empty() || line.starts_with(HEADER_PREFIX) {
+            continue;
+        }
+
+        match parse_snp(&line)? {
+            Some(snp) => snps.push(snp),
+            None => skipped_count += 1,
+        }
+    }
+
+    Ok((snps, skipped_count))
+}
+
+fn parse_snp(line: &str) -> io::Result<Option<Snp>> {
+    let fields: Vec<_> = line.split(DELIMITER).collect();
+
+    if fields.len() < 8 {
+        return Err(invalid_data(format!(
+            "expected at least 8 fields, got {}",
+            fields.len()
+        )));
+    }
+
+    let reference_sequence_name = fields[0];
+
+    let position = fields[1]
+        .parse()
+        .map_err(|_| invalid_data(format!("invalid position: {}", fields[1])))?;
+
+    let (reference_base, alternate_base) = match (parse_base(fields[3]), parse_base(fields[4])) {
+        (Some(r), Some(a)) if r != a => (r, a),
+        _ => return Ok(None),
+    };
+
+    if let (Some(format), Some(sample)) = (fields.get(8), fields.get(9)) {
+        if !is_heterozygous(format, sample) {
+            return Ok(None);
+        }
+    }
+
+    let id = match fields[2] {
+        MISSING => format!("{}:{}", reference_sequence_name, position),
+        id => id.into(),
+    };
+
+    Ok(Some(Snp::new(
+        reference_sequence_name.into(),
+        position,
+        id,
+        reference_base,
+        alternate_base,
+    )))
+}
+
+fn parse_base(s: &str) -> Option<u8> {
+    match s.as_bytes() {
+        [b] if b"ACGT".contains(&b.to_ascii_uppercase()) => Some(b.to_ascii_uppercase()),
+        _ => None,
+    }
+}
+
+/// Returns whether the genotype of a sample is heterozygous, e.g., `0/1` or
+/// `1|0`.
+///
+/// A sample without a genotype is assumed to be heterozygous.
+fn is_heterozygous(format: &str, sample: &str) -> bool {
+    let i = match format.split(':').position(|key| key == GENOTYPE_KEY) {
+        Some(i) => i,
+        None => return true,
+    };
+
+    let genotype = match sample.split(':').nth(i) {
+        Some(gt) => gt,
+        None => return true,
+    };
+
+    let mut alleles = genotype.split(&['/', '|'][..]);
+
+    match (alleles.next(), alleles.next(), alleles.next()) {
+        (Some(a), Some(b), None) => (a == "0" && b == "1") || (a == "1" && b == "0"),
+        _ => false,
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// SNPs indexed by reference sequence name
+///
+/// The indices of each reference sequence are sorted by position.
+pub struct SnpIndex {
+    snps: Vec<Snp>,
+    positions: HashMap<String, Vec<usize>>,
+}
+
+impl SnpIndex {
+    /// Builds an index of SNPs.
+    ///
+    /// Only the first of multiple SNPs at the same position is indexed.
+    pub fn new(snps: Vec<Snp>) -> Self {
+        let mut positions: HashMap<String, Vec<usize>> = HashMap::new();
+
+        for (i, snp) in snps.iter().enumerate() {
+            positions
+                .entry(snp.reference_sequence_name().into())
+                .or_default()
+                .push(i);
+        }
+
+        for indices in positions.values_mut() {
+            indices.sort_by_key(|&i| snps[i].position());
+            indices.dedup_by_key(|i| snps[*i].position());
+        }
+
+        Self { snps, positions }
+    }
+
+    /// Returns all the SNPs, in input order.
+    pub fn snps(&self) -> &[Snp] {
+        &self.snps
+    }
+
+    /// Returns the indices of the SNPs on a reference sequence between the
+    /// given positions, inclusive.
+    pub fn find(&self, reference_sequence_name: &str, start: u64, end: u64) -> &[usize] {
+        let indices = match self.positions.get(reference_sequence_name) {
+            Some(indices) => indices,
+            None => return &[],
+        };
+
+        let lower_bound = |position: u64| match indices
+            .binary_search_by_key(&position, |&i| self.snps[i].position())
+        {
+            Ok(i) | Err(i) => i,
+        };
+
+        let i = lower_bound(start);
+        let j = lower_bound(end + 1);
+
+        &indices[i..j]
+    }
+}
+
+/// Returns the alleles of a record at the SNPs it aligns to.
+///
+/// Bases are read through the CIGAR, so SNPs in deletions and skipped regions,
+/// e.g., introns, have no allele. This returns pairs of the SNP index and the
+/// allele.
+pub fn find_alleles(
+    index: &SnpIndex,
+    reference_sequence_name: &str,
+    record: &bam::Record,
+) -> Vec<(usize, Allele)> {
+    let cigar = record.cigar();
+    let start = i32::from(record.position()) as u64;
+
+    let sequence: Vec<u8> = record
+        .sequence()
+        .symbols()
+        .map(|symbol| char::from(symbol) as u8)
+        .collect();
+
+    read_alleles(
+        index,
+        reference_sequence_name,
+        MatchIntervals::new(&cigar, start),
+        &sequence,
+    )
+}
+
+fn read_alleles(
+    index: &SnpIndex,
+    reference_sequence_name: &str,
+    mut intervals: MatchIntervals<'_>,
+    sequence: &[u8],
+) -> Vec<(usize, Allele)> {
+    let mut alleles = Vec::new();
+
+    while let Some((interval, read_start)) = intervals.next_with_read_position() {
+        for &i in index.find(reference_sequence_name, *interval.start(), *interval.end()) {
+            let snp = &index.snps()[i];
+            let read_position = read_start + (snp.position() - interval.start()) as usize;
+
+            if let Some(&base) = sequence.get(read_position) {
+                alleles.push((i, snp.allele(base)));
+            }
+        }
+    }
+
+    alleles
+}
+
+/// Merges the alleles of two mates.
+///
+/// A SNP covered by both mates is counted once, and it is `Other` if the
+/// mates disagree.
+pub fn merge_alleles(mut a: Vec<(usize, Allele)>, b: Vec<(usize, Allele)>) -> Vec<(usize, Allele)> {
+    for (i, allele) in b {
+        match a.iter_mut().find(|(j, _)| *j == i) {
+            Some((_, a_allele)) => {
+                if *a_allele != allele {
+                    *a_allele = Allele::Other;
+                }
+            }
+            None => a.push((i, allele)),
+        }
+    }
+
+    a
+}
+
+/// Allele counts
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Counts {
+    reference: u64,
+    alternate: u64,
+    other: u64,
+}
+
+impl Counts {
+    pub fn add(&mut self, allele: Allele) {
+        match allele {
+            Allele::Reference => self.reference += 1,
+            Allele::Alternate => self.alternate += 1,
+            Allele::Other => self.other += 1,
+        }
+    }
+}
+
+/// Writes the allele counts of each SNP.
+///
+/// The first row is a header, followed by one row per SNP in input order.
+pub fn write_snp_counts<W>(writer: &mut W, snps: &[Snp], counts: &[Counts]) -> io::Result<()>
+where
+    W: Write,
+{
+    writeln!(
+        writer,
+        "id\treference_sequence_name\tposition\tref\talt\tref_count\talt_count\tother_count"
+    )?;
+
+    for (snp, counts) in snps.iter().zip(counts) {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            snp.id(),
+            snp.reference_sequence_name(),
+            snp.position(),
+            char::from(snp.reference_base()),
+            char::from(snp.alternate_base()),
+            counts.reference,
+            counts.alternate,
+            counts.other,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Writes the allele counts of each feature, summed over its SNPs.
+pub fn write_feature_counts<W>(
+    writer: &mut W,
+    ids: &[String],
+    counts: &HashMap<String, Counts>,
+) -> io::Result<()>
+where
+    W: Write,
+{
+    writeln!(writer, "id\tref_count\talt_count\tother_count")?;
+
+    let default_counts = Counts::default();
+
+    for id in ids {
+        let c = counts.get(id).unwrap_or(&default_counts);
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}",
+            id, c.reference, c.alternate, c.other
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles_bam::record::{cigar, Cigar};
+    use noodles_sam::record::cigar::op;
+
+    use super::*;
+
+    fn build_index() -> SnpIndex {
+        SnpIndex::new(vec![
+            Snp::new(String::from("sq0"), 13, String::from("rs2"), b'C', b'T'),
+            Snp::new(String::from("sq0"), 5, String::from("rs1"), b'A', b'G'),
+            Snp::new(String::from("sq0"), 21, String::from("rs3"), b'G', b'A'),
+            Snp::new(String::from("sq1"), 8, String::from("rs4"), b'T', b'C'),
+        ])
+    }
+
+    #[test]
+    fn test_read_snps() -> io::Result<()> {
+        let data = b"\
+##fileformat=VCFv4.3
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tsample1
+sq0\t5\trs1\tA\tG\t.\tPASS\t.\tGT\t0/1
+sq0\t8\t.\tc\tt\t.\tPASS\t.\tGT:DP\t1|0:13
+sq0\t13\trs2\tC\tT\t.\tPASS\t.\tGT\t1/1
+sq0\t21\trs3\tG\tA,T\t.\tPASS\t.\tGT\t0/1
+sq0\t34\trs4\tAC\tA\t.\tPASS\t.\tGT\t0/1
+sq1\t8\trs5\tT\tC\t.\tPASS\t.
+";
+
+        let (snps, skipped_count) = read_snps(&mut &data[..])?;
+
+        assert_eq!(
+            snps,
+            [
+                Snp::new(String::from("sq0"), 5, String::from("rs1"), b'A', b'G'),
+                Snp::new(String::from("sq0"), 8, String::from("sq0:8"), b'C', b'T'),
+                Snp::new(String::from("sq1"), 8, String::from("rs5"), b'T', b'C'),
+            ]
+        );
+        assert_eq!(skipped_count, 3);
+
+        assert!(read_snps(&mut &b"sq0\t5\trs1\tA\tG\n"[..]).is_err());
+        assert!(read_snps(&mut &b"sq0\tx\trs1\tA\tG\t.\tPASS\t.\n"[..]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_heterozygous() {
+        assert!(is_heterozygous("GT", "0/1"));
+        assert!(is_heterozygous("GT:DP", "1|0:8"));
+        assert!(is_heterozygous("DP", "8"));
+
+        assert!(!is_heterozygous("GT", "0/0"));
+        assert!(!is_heterozygous("GT", "1/1"));
+        assert!(!is_heterozygous("GT", "0/2"));
+        assert!(!is_heterozygous("GT", "./."));
+        assert!(!is_heterozygous("GT", "0/1/1"));
+    }
+
+    #[test]
+    fn test_snp_index_find() {
+        let index = build_index();
+
+        assert_eq!(index.find("sq0", 1, 4), &[]);
+        assert_eq!(index.find("sq0", 1, 5), &[1]);
+        assert_eq!(index.find("sq0", 5, 21), &[1, 0, 2]);
+        assert_eq!(index.find("sq0", 6, 20), &[0]);
+        assert_eq!(index.find("sq1", 1, 100), &[3]);
+        assert_eq!(index.find("sq2", 1, 100), &[]);
+    }
+
+    #[test]
+    fn test_read_alleles() {
+        let index = build_index();
+
+        // 2S3M5N4M (read positions 2..=4 align to 4..=6, 5..=8 to 12..=15)
+        let ops = [
+            u32::from(cigar::Op::new(op::Kind::SoftClip, 2)).to_le_bytes(),
+            u32::from(cigar::Op::new(op::Kind::Match, 3)).to_le_bytes(),
+            u32::from(cigar::Op::new(op::Kind::Skip, 5)).to_le_bytes(),
+            u32::from(cigar::Op::new(op::Kind::Match, 4)).to_le_bytes(),
+        ];
+        let raw_cigar: Vec<_> = ops.iter().flatten().copied().collect();
+        let cigar = Cigar::new(&raw_cigar);
+
+        let sequence = b"NNTGCAATA";
+        let alleles = read_alleles(&index, "sq0", MatchIntervals::new(&cigar, 4), sequence);
+        assert_eq!(alleles, [(1, Allele::Alternate), (0, Allele::Other)]);
+
+        let sequence = b"NNTACACAA";
+        let alleles = read_alleles(&index, "sq0", MatchIntervals::new(&cigar, 4), sequence);
+        assert_eq!(alleles, [(1, Allele::Reference), (0, Allele::Reference)]);
+    }
+
+    #[test]
+    fn test_merge_alleles() {
+        let a = vec![(0, Allele::Reference), (1, Allele::Alternate)];
+        let b = vec![(1, Allele::Alternate), (2, Allele::Reference)];
+        assert_eq!(
+            merge_alleles(a, b),
+            [
+                (0, Allele::Reference),
+                (1, Allele::Alternate),
+                (2, Allele::Reference)
+            ]
+        );
+
+        let a = vec![(0, Allele::Reference)];
+        let b = vec![(0, Allele::Alternate)];
+        assert_eq!(merge_alleles(a, b), [(0, Allele::Other)]);
+    }
+
+    #[test]
+    fn test_write_snp_counts() -> io::Result<()> {
+        let snps = [Snp::new(
+            String::from("sq0"),
+            5,
+            String::from("rs1"),
+            b'A',
+            b'G',
+        )];
+
+        let mut counts = Counts::default();
+        counts.add(Allele::Reference);
+        counts.add(Allele::Alternate);
+        counts.add(Allele::Alternate);
+        counts.add(Allele::Other);
+
+        let mut buf = Vec::new();
+        write_snp_counts(&mut buf, &snps, &[counts])?;
+
+        let expected = b"\
+id\treference_sequence_name\tposition\tref\talt\tref_count\talt_count\tother_count
+rs1\tsq0\t5\tA\tG\t1\t2\t1
+";
+
+        assert_eq!(&buf[..], &expected[..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_feature_counts() -> io::Result<()> {
+        let mut aadat_counts = Counts::default();
+        aadat_counts.add(Allele::Reference);
+        aadat_counts.add(Allele::Alternate);
+
+        let counts: HashMap<_, _> = vec![(String::from("AADAT"), aadat_counts)]
+            .into_iter()
+            .collect();
+
+        let ids = [String::from("AADAT"), String::from("CLN3")];
+
+        let mut buf = Vec::new();
+        write_feature_counts(&mut buf, &ids, &counts)?;
+
+        let expected = b"\
+id\tref_count\talt_count\tother_count
+AADAT\t1\t1\t0
+CLN3\t0\t0\t0
+";
+
+        assert_eq!(&buf[..], &expected[..]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,274 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead},
+    str::FromStr,
+};
+
+use log::info;
+use noodles_gff as gff;
+
+use crate::{hierarchy, Feature};
+
+const DELIMITER: char = '\t';
+const COMMENT_PREFIX: char = '#';
+const HEADER_PREFIXES: &[&str] = &["track", "browser"];
+const MIN_FIELD_COUNT: usize = 3;
+const BLOCKS_FIELD_COUNT: usize = 12;
+
+/// A BED record.
+///
+/// Positions are 0-based and half-open, e.g., `0 10` is the first 10 bases.
+/// Only the fields used to build features are kept: the name (BED4+), strand
+/// (BED6+), and blocks (BED12).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Record {
+    reference_sequence_name: String,
+    start: u64,
+    end: u64,
+    name: Option<String>,
+    strand: gff::record::Strand,
+    // (start, size), where start is relative to the record start
+    blocks: Vec<(u64, u64)>,
+}
+
+impl Record {
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the record as 1-based features, one per block.
+    ///
+    /// A record without blocks is a single feature.
+    pub fn features(&self) -> Vec<Feature> {
+        if self.blocks.is_empty() {
+            return vec![Feature::new(
+                self.reference_sequence_name.clone(),
+                self.start + 1,
+                self.end,
+                self.strand,
+            )];
+        }
+
+        self.blocks
+            .iter()
+            .map(|&(block_start, block_size)| {
+                let start = self.start + block_start;
+
+                Feature::new(
+                    self.reference_sequence_name.clone(),
+                    start + 1,
+                    start + block_size,
+                    self.strand,
+                )
+            })
+            .collect()
+    }
+}
+
+impl FromStr for Record {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<_> = s.split(DELIMITER).collect();
+
+        if fields.len() < MIN_FIELD_COUNT {
+            return Err(invalid_data(format!(
+                "expected at least {} fields, got {}",
+                MIN_FIELD_COUNT,
+                fields.len()
+            )));
+        }
+
+        let start: u64 = fields[1]
+            .parse()
+            .map_err(|_| invalid_data(format!("invalid start: {}", fields[1])))?;
+
+        let end: u64 = fields[2]
+            .parse()
+            .map_err(|_| invalid_data(format!("invalid end: {}", fields[2])))?;
+
+        if start >= end {
+            return Err(invalid_data(format!("invalid interval: {}-{}", start, end)));
+        }
+
+        let name = fields
+            .get(3)
+            .filter(|name| !name.is_empty() && **name != ".")
+            .map(|name| String::from(*name));
+
+        let strand = match fields.get(5) {
+            Some(s) => s
+                .parse()
+                .map_err(|_| invalid_data(format!("invalid strand: {}", s)))?,
+            None => gff::record::Strand::None,
+        };
+
+        let blocks = if fields.len() >= BLOCKS_FIELD_COUNT {
+            parse_blocks(fields[9], fields[10], fields[11])?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            reference_sequence_name: fields[0].into(),
+            start,
+            end,
+            name,
+            strand,
+            blocks,
+        })
+    }
+}
+
+/// Parses the block count, sizes, and starts fields, e.g., `2`, `10,20,`, and
+/// `0,30,`.
+fn parse_blocks(count: &str, sizes: &str, starts: &str) -> io::Result<Vec<(u64, u64)>> {
+    fn parse_list(s: &str) -> io::Result<Vec<u64>> {
+        s.split(',')
+            .filter(|v| !v.is_empty())
+            .map(|v| {
+                v.parse()
+                    .map_err(|_| invalid_data(format!("invalid block value: {}", v)))
+            })
+            .collect()
+    }
+
+    let count: usize = count
+        .parse()
+        .map_err(|_| invalid_data(format!("invalid block count: {}", count)))?;
+
+    let sizes = parse_list(sizes)?;
+    let starts = parse_list(starts)?;
+
+    if sizes.len() != count || starts.len() != count {
+        return Err(invalid_data(format!(
+            "expected {} blocks, got {} sizes and {} starts",
+            count,
+            sizes.len(),
+            starts.len()
+        )));
+    }
+
+    Ok(starts.into_iter().zip(sizes).collect())
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads features from BED records.
+///
+/// The feature ID is the name of the record or, for BED3, its position. Records
+/// with the same name are combined.
+pub fn read_features<R>(reader: &mut R) -> io::Result<HashMap<String, Vec<Feature>>>
+where
+    R: BufRead,
+{
+    let mut features: HashMap<String, Vec<Feature>> = HashMap::new();
+    let mut buf = String::new();
+
+    info!("reading features");
+
+    loop {
+        buf.clear();
+
+        if reader.read_line(&mut buf)? == 0 {
+            break;
+        }
+
+        let line = buf.trim_end_matches(&['\n', '\r'][..]);
+
+        if line.is_empty()
+            || line.starts_with(COMMENT_PREFIX)
+            || HEADER_PREFIXES
+                .iter()
+                .any(|prefix| line.starts_with(prefix))
+        {
+            continue;
+        }
+
+        let record: Record = line.parse()?;
+        let record_features = record.features();
+
+        let id = match record.name() {
+            Some(name) => name.into(),
+            None => hierarchy::position_id(&record_features[0]),
+        };
+
+        features.entry(id).or_default().extend(record_features);
+    }
+
+    info!("read {} unique features", features.len());
+
+    Ok(features)
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles_gff::record::Strand;
+
+    use super::*;
+
+    #[test]
+    fn test_from_str() -> io::Result<()> {
+        let record: Record = "sq0\t7\t13".parse()?;
+        assert_eq!(
+            record.features(),
+            [Feature::new(String::from("sq0"), 8, 13, Strand::None)]
+        );
+        assert_eq!(record.name(), None);
+
+        let record: Record = "sq0\t7\t13\tpeak0\t0\t-".parse()?;
+        assert_eq!(
+            record.features(),
+            [Feature::new(String::from("sq0"), 8, 13, Strand::Reverse)]
+        );
+        assert_eq!(record.name(), Some("peak0"));
+
+        let record: Record = "sq0\t0\t50\ttx0\t0\t+\t0\t50\t0\t2\t10,20,\t0,30,".parse()?;
+        assert_eq!(
+            record.features(),
+            [
+                Feature::new(String::from("sq0"), 1, 10, Strand::Forward),
+                Feature::new(String::from("sq0"), 31, 50, Strand::Forward),
+            ]
+        );
+
+        assert!("sq0\t7".parse::<Record>().is_err());
+        assert!("sq0\tx\t13".parse::<Record>().is_err());
+        assert!("sq0\t13\t7".parse::<Record>().is_err());
+        assert!("sq0\t7\t13\tpeak0\t0\tx".parse::<Record>().is_err());
+        assert!("sq0\t0\t50\ttx0\t0\t+\t0\t50\t0\t2\t10,\t0,30,"
+            .parse::<Record>()
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_features() -> io::Result<()> {
+        let data = b"track name=peaks
+#chrom\tstart\tend
+sq0\t0\t10\tpeak0\t0\t+
+sq0\t20\t30\tpeak0\t0\t+
+sq1\t40\t50
+";
+
+        let features = read_features(&mut &data[..])?;
+
+        assert_eq!(features.len(), 2);
+        assert_eq!(
+            features["peak0"],
+            [
+                Feature::new(String::from("sq0"), 1, 10, Strand::Forward),
+                Feature::new(String::from("sq0"), 21, 30, Strand::Forward),
+            ]
+        );
+        assert_eq!(
+            features["sq1:41-50"],
+            [Feature::new(String::from("sq1"), 41, 50, Strand::None)]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use std::{cmp, collections::HashMap};
+
+use noodles_gff::record::Strand;
+use noodles_sam::header::ReferenceSequences;
+
+use crate::{hierarchy::position_id, Feature};
+
+/// Builds fixed-width bins over the reference sequences.
+///
+/// Each reference sequence is split into consecutive bins of the given size,
+/// starting at position 1. The last bin of a reference sequence is truncated to
+/// its end. Bins are unstranded and identified by their positions, e.g.,
+/// `sq0:1-1000`.
+pub fn build_features(
+    reference_sequences: &ReferenceSequences,
+    bin_size: u64,
+) -> HashMap<String, Vec<Feature>> {
+    assert!(bin_size > 0, "invalid bin size");
+
+    let mut features = HashMap::new();
+
+    for reference_sequence in reference_sequences.values() {
+        let name = reference_sequence.name();
+        let len = reference_sequence.len() as u64;
+
+        let mut start = 1;
+
+        while start <= len {
+            let end = cmp::min(start + bin_size - 1, len);
+            let feature = Feature::new(name.into(), start, end, Strand::None);
+            features.insert(position_id(&feature), vec![feature]);
+            start = end + 1;
+        }
+    }
+
+    features
+}
+
+/// Returns the IDs of bins in genomic order, i.e., by the index of their
+/// reference sequence in the header and then by start.
+///
+/// Sorting the IDs as strings would put, e.g., `sq0:1001-2000` after
+/// `sq0:10001-11000`.
+pub fn sorted_ids(
+    features: &HashMap<String, Vec<Feature>>,
+    reference_sequences: &ReferenceSequences,
+) -> Vec<String> {
+    let mut bins: Vec<_> = features
+        .iter()
+        .filter_map(|(id, bin_features)| {
+            let feature = bin_features.first()?;
+            let index = reference_sequences
+                .get_full(feature.reference_sequence_name())
+                .map(|(i, _, _)| i);
+            Some(((index, feature.start()), id))
+        })
+        .collect();
+
+    bins.sort_unstable();
+
+    bins.into_iter().map(|(_, id)| id.clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles_sam::header::ReferenceSequence;
+
+    use super::*;
+
+    #[test]
+    fn test_build_features() {
+        let reference_sequences: ReferenceSequences = vec![
+            (
+                String::from("sq0"),
+                ReferenceSequence::new(String::from("sq0"), 25),
+            ),
+            (
+                String::from("sq1"),
+                ReferenceSequence::new(String::from("sq1"), 10),
+            ),
+        ]
+        .into_iter()
+        .collect();
+
+        let features = build_features(&reference_sequences, 10);
+
+        assert_eq!(features.len(), 4);
+        assert_eq!(
+            features["sq0:1-10"],
+            [Feature::new(String::from("sq0"), 1, 10, Strand::None)]
+        );
+        assert_eq!(
+            features["sq0:21-25"],
+            [Feature::new(String::from("sq0"), 21, 25, Strand::None)]
+        );
+        assert!(features.contains_key("sq0:11-20"));
+        assert!(features.contains_key("sq1:1-10"));
+    }
+
+    #[test]
+    fn test_sorted_ids() {
+        let reference_sequences: ReferenceSequences = vec![
+            (
+                String::from("sq1"),
+                ReferenceSequence::new(String::from("sq1"), 2000),
+            ),
+            (
+                String::from("sq0"),
+                ReferenceSequence::new(String::from("sq0"), 1000),
+            ),
+        ]
+        .into_iter()
+        .collect();
+
+        let features = build_features(&reference_sequences, 100);
+        let ids = sorted_ids(&features, &reference_sequences);
+
+        assert_eq!(ids.len(), 30);
+        assert_eq!(ids[0], "sq1:1-100");
+        assert_eq!(ids[1], "sq1:101-200");
+        assert_eq!(ids[9], "sq1:901-1000");
+        assert_eq!(ids[10], "sq1:1001-1100");
+        assert_eq!(ids[19], "sq1:1901-2000");
+        assert_eq!(ids[20], "sq0:1-100");
+        assert_eq!(ids[29], "sq0:901-1000");
+    }
+}
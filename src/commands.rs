@@ -1,7 +1,17 @@
+mod ase;
+mod coverage;
+mod junctions;
+mod merge;
 mod normalize;
+mod qc;
 mod quantify;
+mod ribo;
+mod single_cell;
 
-pub use self::{normalize::normalize, quantify::quantify};
+pub use self::{
+    ase::ase, coverage::coverage, junctions::junctions, merge::merge, normalize::normalize, qc::qc,
+    quantify::quantify, ribo::ribo, single_cell::single_cell,
+};
 
 use std::str::FromStr;
 
@@ -0,0 +1,252 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{self, BufWriter},
+    path::Path,
+};
+
+use anyhow::Context as AnyhowContext;
+use interval_tree::IntervalTree;
+use log::info;
+use noodles_bam as bam;
+use noodles_sam::header::ReferenceSequences;
+
+use crate::{
+    alignment, annotations,
+    ase::{self, find_alleles, merge_alleles, read_snps, Counts, SnpIndex},
+    build_interval_trees,
+    count::{find, log_filtered, Filter, Mode},
+    detect::{self, LibraryLayout},
+    is_reverse, Context, Entry, Features, RecordPairs, StrandSpecification,
+    StrandSpecificationOption,
+};
+
+#[derive(Default)]
+struct Summary {
+    no_feature: u64,
+    ambiguous: u64,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn ase<P, Q, R>(
+    src: P,
+    reference_src: Option<&Path>,
+    annotations_src: Q,
+    annotations_format: Option<annotations::Format>,
+    snps_src: R,
+    feature_type: &str,
+    id: &str,
+    filter: &Filter,
+    strand_specification_option: StrandSpecificationOption,
+    feature_dst: Option<&Path>,
+) -> anyhow::Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    R: AsRef<Path>,
+{
+    let src = src.as_ref();
+    let annotations_src = annotations_src.as_ref();
+    let snps_src = snps_src.as_ref();
+
+    let (snps, skipped_count) = annotations::open(snps_src)
+        .and_then(|mut reader| read_snps(&mut reader))
+        .with_context(|| format!("Could not read {}", snps_src.display()))?;
+
+    info!(
+        "read {} heterozygous SNPs ({} skipped)",
+        snps.len(),
+        skipped_count
+    );
+
+    let index = SnpIndex::new(snps);
+
+    let feature_map =
+        annotations::read_features(annotations_src, annotations_format, feature_type, id)?;
+    let (features, names) = build_interval_trees(&feature_map);
+
+    let (_, header, mut records) = alignment::open(src, reference_src)
+        .with_context(|| format!("Could not open {}", src.display()))?;
+
+    let reference_sequences = header.reference_sequences();
+
+    let detection_records = detect::read_detection_records(&mut records)?;
+
+    let (library_layout, strand_specification) = detect::detect_library_type(
+        detection_records.iter().cloned().map(Ok),
+        reference_sequences,
+        &features,
+        strand_specification_option,
+    )?;
+
+    info!("counting alleles");
+
+    let records = detection_records.into_iter().map(Ok).chain(records);
+
+    let mut counter = Counter {
+        index: &index,
+        features: &features,
+        reference_sequences,
+        strand_specification,
+        snp_counts: vec![Counts::default(); index.snps().len()],
+        feature_counts: HashMap::new(),
+        summary: Summary::default(),
+    };
+
+    let mut ctx = Context::default();
+
+    match library_layout {
+        LibraryLayout::SingleEnd => {
+            for result in records {
+                let record = result?;
+
+                if filter.filter(&mut ctx, &record)? {
+                    continue;
+                }
+
+                counter.add(&[&record])?;
+            }
+        }
+        LibraryLayout::PairedEnd => {
+            let primary_only =
+                !filter.with_secondary_records() && !filter.with_supplementary_records();
+            let mut pairs = RecordPairs::new(records, primary_only);
+
+            for pair in &mut pairs {
+                let (r1, r2) = pair?;
+
+                if filter.filter_pair(&mut ctx, &r1, &r2)? {
+                    continue;
+                }
+
+                counter.add(&[&r1, &r2])?;
+            }
+
+            for record in pairs.singletons() {
+                if filter.filter(&mut ctx, &record)? {
+                    continue;
+                }
+
+                counter.add(&[&record])?;
+            }
+        }
+    }
+
+    let Counter {
+        snp_counts,
+        feature_counts,
+        summary,
+        ..
+    } = counter;
+
+    info!(
+        "skipped {} allele observations with no feature and {} ambiguous",
+        summary.no_feature, summary.ambiguous
+    );
+
+    log_filtered(&ctx);
+
+    let stdout = io::stdout();
+    let mut writer = BufWriter::new(stdout.lock());
+    ase::write_snp_counts(&mut writer, index.snps(), &snp_counts)?;
+
+    if let Some(dst) = feature_dst {
+        let mut ids: Vec<_> = names.into_iter().collect();
+        ids.sort();
+
+        File::create(dst)
+            .map(BufWriter::new)
+            .and_then(|mut writer| ase::write_feature_counts(&mut writer, &ids, &feature_counts))
+            .with_context(|| format!("Could not write {}", dst.display()))?;
+    }
+
+    Ok(())
+}
+
+struct Counter<'a> {
+    index: &'a SnpIndex,
+    features: &'a Features,
+    reference_sequences: &'a ReferenceSequences,
+    strand_specification: StrandSpecification,
+    snp_counts: Vec<Counts>,
+    feature_counts: HashMap<String, Counts>,
+    summary: Summary,
+}
+
+impl<'a> Counter<'a> {
+    /// Counts the alleles of a fragment, i.e., a single record or a pair.
+    ///
+    /// The strand of the fragment is taken from its first mapped record.
+    fn add(&mut self, records: &[&bam::Record]) -> io::Result<()> {
+        let mut mapped_records = records.iter().filter(|r| !r.flags().is_unmapped());
+
+        let record = match mapped_records.next() {
+            Some(r) => r,
+            None => return Ok(()),
+        };
+
+        let name = get_reference_sequence_name(self.reference_sequences, record)?;
+        let mut alleles = find_alleles(self.index, name, record);
+
+        for mate in mapped_records {
+            let mate_name = get_reference_sequence_name(self.reference_sequences, mate)?;
+            alleles = merge_alleles(alleles, find_alleles(self.index, mate_name, mate));
+        }
+
+        let is_reverse = is_reverse(record, self.strand_specification);
+
+        for (i, allele) in alleles {
+            self.snp_counts[i].add(allele);
+
+            let snp = &self.index.snps()[i];
+
+            let ids = self
+                .features
+                .get(snp.reference_sequence_name())
+                .and_then(|t| find_at(t, snp.position(), self.strand_specification, is_reverse))
+                .unwrap_or_default();
+
+            let mut ids = ids.into_iter();
+
+            match (ids.next(), ids.next()) {
+                (None, _) => self.summary.no_feature += 1,
+                (Some(id), None) => self.feature_counts.entry(id).or_default().add(allele),
+                (Some(_), Some(_)) => self.summary.ambiguous += 1,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn find_at(
+    tree: &IntervalTree<u64, Entry>,
+    position: u64,
+    strand_specification: StrandSpecification,
+    is_reverse: bool,
+) -> Option<HashSet<String>> {
+    find(
+        tree,
+        Some(position..=position),
+        Mode::Union,
+        strand_specification,
+        is_reverse,
+    )
+}
+
+fn get_reference_sequence_name<'a>(
+    reference_sequences: &'a ReferenceSequences,
+    record: &bam::Record,
+) -> io::Result<&'a str> {
+    let reference_sequence_id = record.reference_sequence_id();
+
+    reference_sequence_id
+        .and_then(|id| reference_sequences.get_index(id as usize))
+        .map(|(name, _)| name.as_str())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid reference sequence ID: {:?}", reference_sequence_id),
+            )
+        })
+}
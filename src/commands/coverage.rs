@@ -0,0 +1,157 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufWriter},
+    path::Path,
+};
+
+use anyhow::Context as AnyhowContext;
+use log::info;
+
+use crate::{
+    alignment, annotations, build_interval_trees,
+    count::{find, Filter, Mode},
+    coverage::{self, Transcript, BIN_COUNT},
+    detect,
+    hierarchy::Level,
+    is_reverse, Context, Features, MatchIntervals, StrandSpecificationOption,
+};
+
+use super::quantify::build_sample_names;
+
+pub fn coverage<P, Q>(
+    srcs: &[P],
+    reference_src: Option<&Path>,
+    annotations_src: Q,
+    feature_type: &str,
+    filter: &Filter,
+    strand_specification_option: StrandSpecificationOption,
+) -> anyhow::Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let annotations_src = annotations_src.as_ref();
+
+    let sample_names = build_sample_names(srcs)?;
+
+    let hierarchy = annotations::read_hierarchy(annotations_src, None, feature_type)
+        .with_context(|| format!("Could not read {}", annotations_src.display()))?;
+
+    let transcript_map = hierarchy.features(Level::Transcript);
+
+    // Transcripts shorter than the number of bins cannot be binned.
+    let transcripts: HashMap<_, _> = transcript_map
+        .iter()
+        .filter_map(|(id, exons)| Transcript::new(exons).map(|t| (id.as_str(), t)))
+        .filter(|(_, t)| t.len() >= BIN_COUNT as u64)
+        .collect();
+
+    info!(
+        "binning {} of {} transcripts",
+        transcripts.len(),
+        transcript_map.len()
+    );
+
+    let (features, _) = build_interval_trees(&transcript_map);
+
+    let mut profiles = Vec::with_capacity(srcs.len());
+
+    for src in srcs {
+        let src = src.as_ref();
+
+        info!("calculating coverage of {}", src.display());
+
+        let profile = calculate_sample_profile(
+            src,
+            reference_src,
+            &features,
+            &transcripts,
+            filter,
+            strand_specification_option,
+        )?;
+
+        profiles.push(profile);
+    }
+
+    let stdout = io::stdout();
+    let handle = stdout.lock();
+    let mut writer = BufWriter::new(handle);
+
+    coverage::write_profiles(&mut writer, &sample_names, &profiles)
+        .context("Could not write to stdout")?;
+
+    Ok(())
+}
+
+fn calculate_sample_profile(
+    src: &Path,
+    reference_src: Option<&Path>,
+    features: &Features,
+    transcripts: &HashMap<&str, Transcript>,
+    filter: &Filter,
+    strand_specification_option: StrandSpecificationOption,
+) -> anyhow::Result<Vec<f64>> {
+    let (_, header, mut records) = alignment::open(src, reference_src)
+        .with_context(|| format!("Could not open {}", src.display()))?;
+
+    let reference_sequences = header.reference_sequences();
+
+    let detection_records = detect::read_detection_records(&mut records)?;
+
+    let (_, strand_specification) = detect::detect_library_type(
+        detection_records.iter().cloned().map(Ok),
+        reference_sequences,
+        features,
+        strand_specification_option,
+    )?;
+
+    let records = detection_records.into_iter().map(Ok).chain(records);
+    let mut profile = vec![0.0; BIN_COUNT];
+    let mut ctx = Context::default();
+
+    for result in records {
+        let record = result?;
+
+        if filter.filter(&mut ctx, &record)? {
+            continue;
+        }
+
+        let tree = match *record.reference_sequence_id() {
+            Some(id) => match reference_sequences.get_index(id as usize) {
+                Some((name, _)) => match features.get(name) {
+                    Some(tree) => tree,
+                    None => continue,
+                },
+                None => continue,
+            },
+            None => continue,
+        };
+
+        let cigar = record.cigar();
+        let start = i32::from(record.position()) as u64;
+        let is_reverse = is_reverse(&record, strand_specification);
+
+        let intervals = MatchIntervals::new(&cigar, start);
+        let ids = find(
+            tree,
+            intervals,
+            Mode::Union,
+            strand_specification,
+            is_reverse,
+        )
+        .unwrap_or_default();
+
+        for id in ids {
+            let transcript = match transcripts.get(id.as_str()) {
+                Some(t) => t,
+                None => continue,
+            };
+
+            for interval in MatchIntervals::new(&cigar, start) {
+                transcript.add_coverage(&mut profile, *interval.start(), *interval.end());
+            }
+        }
+    }
+
+    Ok(profile)
+}
@@ -0,0 +1,149 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufWriter},
+    path::Path,
+};
+
+use anyhow::Context as AnyhowContext;
+use log::info;
+use noodles_bam as bam;
+
+use crate::{
+    alignment, annotations, build_interval_trees,
+    count::{is_nonunique_record, Filter},
+    detect,
+    hierarchy::Level,
+    is_reverse,
+    junctions::{self, build_annotated_introns, find_introns, Counts, Junction, Strand},
+    Context, StrandSpecification, StrandSpecificationOption,
+};
+
+pub fn junctions<P, Q>(
+    src: P,
+    reference_src: Option<&Path>,
+    annotations_src: Q,
+    feature_type: &str,
+    filter: &Filter,
+    strand_specification_option: StrandSpecificationOption,
+) -> anyhow::Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let src = src.as_ref();
+    let annotations_src = annotations_src.as_ref();
+
+    let hierarchy = annotations::read_hierarchy(annotations_src, None, feature_type)
+        .with_context(|| format!("Could not read {}", annotations_src.display()))?;
+
+    let annotated_introns = build_annotated_introns(&hierarchy.features(Level::Transcript));
+    info!("read {} annotated introns", annotated_introns.len());
+
+    let (features, _) = build_interval_trees(&hierarchy.features(Level::Exon));
+
+    let (_, header, mut records) = alignment::open(src, reference_src)
+        .with_context(|| format!("Could not open {}", src.display()))?;
+
+    let reference_sequences = header.reference_sequences();
+
+    let detection_records = detect::read_detection_records(&mut records)?;
+
+    let (_, strand_specification) = detect::detect_library_type(
+        detection_records.iter().cloned().map(Ok),
+        reference_sequences,
+        &features,
+        strand_specification_option,
+    )?;
+
+    info!("counting junctions");
+
+    let records = detection_records.into_iter().map(Ok).chain(records);
+    let mut junction_counts: HashMap<Junction, Counts> = HashMap::new();
+    let mut ctx = Context::default();
+
+    for result in records {
+        let record = result?;
+
+        if filter.filter(&mut ctx, &record)? {
+            continue;
+        }
+
+        let reference_sequence_id = match *record.reference_sequence_id() {
+            Some(id) => id as usize,
+            None => continue,
+        };
+
+        let reference_sequence_name = match reference_sequences.get_index(reference_sequence_id) {
+            Some((name, _)) => name,
+            None => continue,
+        };
+
+        let is_nonunique = is_nonunique_record(&record)?;
+        let read_strand = transcript_strand(&record, strand_specification);
+
+        let cigar = record.cigar();
+        let start = i32::from(record.position()) as u64;
+
+        for (intron, overhang) in find_introns(&cigar, start) {
+            let key = (
+                reference_sequence_name.clone(),
+                *intron.start(),
+                *intron.end(),
+            );
+
+            // Unstranded reads take the strand of the annotated intron, if any.
+            let strand = match (read_strand, annotated_introns.get(&key)) {
+                (Strand::Undefined, Some(&strand)) => strand,
+                (strand, _) => strand,
+            };
+
+            let junction = Junction::new(
+                reference_sequence_id,
+                *intron.start(),
+                *intron.end(),
+                strand,
+            );
+
+            junction_counts
+                .entry(junction)
+                .or_default()
+                .add(is_nonunique, overhang);
+        }
+    }
+
+    info!("found {} junctions", junction_counts.len());
+
+    let mut junctions: Vec<_> = junction_counts.into_iter().collect();
+    junctions.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let stdout = io::stdout();
+    let handle = stdout.lock();
+    let mut writer = junctions::Writer::new(BufWriter::new(handle));
+
+    for (junction, counts) in &junctions {
+        let (name, _) = reference_sequences
+            .get_index(junction.reference_sequence_id())
+            .expect("invalid reference sequence ID");
+
+        let key = (name.clone(), junction.start(), junction.end());
+        let is_annotated = annotated_introns.contains_key(&key);
+
+        writer
+            .write_junction(name, junction, is_annotated, counts)
+            .context("Could not write to stdout")?;
+    }
+
+    Ok(())
+}
+
+/// Returns the strand of the transcript a record originates from.
+///
+/// The second read of a pair is on the opposite strand of the first. The strand
+/// is undefined when the library is not strand-specific.
+fn transcript_strand(record: &bam::Record, strand_specification: StrandSpecification) -> Strand {
+    match strand_specification {
+        StrandSpecification::None => Strand::Undefined,
+        _ if is_reverse(record, strand_specification) => Strand::Reverse,
+        _ => Strand::Forward,
+    }
+}
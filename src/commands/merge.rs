@@ -0,0 +1,234 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use log::info;
+
+use crate::count;
+
+use super::quantify::build_sample_names;
+
+const DELIMITER: char = '\t';
+
+pub fn merge<P, Q, R>(
+    srcs: &[P],
+    sample_sheet_src: Option<Q>,
+    summary_dst: Option<R>,
+) -> anyhow::Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    R: AsRef<Path>,
+{
+    let samples = match sample_sheet_src {
+        Some(src) => {
+            let src = src.as_ref();
+
+            File::open(src)
+                .map(BufReader::new)
+                .and_then(read_sample_sheet)
+                .with_context(|| format!("Could not read {}", src.display()))?
+        }
+        None => build_sample_names(srcs)?
+            .into_iter()
+            .zip(srcs.iter().map(|src| src.as_ref().to_path_buf()))
+            .collect(),
+    };
+
+    if samples.is_empty() {
+        anyhow::bail!("no counts files given");
+    }
+
+    let mut sample_counts = Vec::with_capacity(samples.len());
+    let mut sample_stats = Vec::with_capacity(samples.len());
+
+    for (_, src) in &samples {
+        info!("reading {}", src.display());
+
+        let (counts, stats) = File::open(src)
+            .map(BufReader::new)
+            .map(count::Reader::new)
+            .and_then(|mut reader| reader.read_counts_and_stats())
+            .with_context(|| format!("Could not read {}", src.display()))?;
+
+        sample_counts.push(counts);
+        sample_stats.push(stats);
+    }
+
+    validate_feature_ids(&samples, &sample_counts)?;
+
+    let sample_names: Vec<_> = samples.iter().map(|(name, _)| name.clone()).collect();
+
+    let mut feature_ids: Vec<_> = sample_counts[0].keys().cloned().collect();
+    feature_ids.sort();
+
+    let stdout = io::stdout();
+    let handle = stdout.lock();
+    let mut writer = count::Writer::new(BufWriter::new(handle));
+
+    let counts: Vec<_> = sample_counts.iter().collect();
+
+    writer
+        .write_header(&sample_names)
+        .and_then(|_| writer.write_count_matrix(&feature_ids, &counts))
+        .context("Could not write to stdout")?;
+
+    if let Some(dst) = summary_dst {
+        let dst = dst.as_ref();
+
+        let mut stat_names: Vec<String> = Vec::new();
+
+        for stats in &sample_stats {
+            for (name, _) in stats {
+                if !stat_names.contains(name) {
+                    stat_names.push(name.clone());
+                }
+            }
+        }
+
+        let stats: Vec<HashMap<_, _>> = sample_stats
+            .into_iter()
+            .map(|s| s.into_iter().collect())
+            .collect();
+        let stats: Vec<_> = stats.iter().collect();
+
+        let mut writer = File::create(dst)
+            .map(BufWriter::new)
+            .map(count::Writer::new)
+            .with_context(|| format!("Could not open {}", dst.display()))?;
+
+        writer
+            .write_header(&sample_names)
+            .and_then(|_| writer.write_count_matrix(&stat_names, &stats))
+            .with_context(|| format!("Could not write {}", dst.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Reads a sample sheet.
+///
+/// A sample sheet is a tab-delimited file with two columns: the sample name
+/// and the path to its counts file. Blank lines are skipped. Sample names must
+/// be unique.
+fn read_sample_sheet<R>(reader: R) -> io::Result<Vec<(String, PathBuf)>>
+where
+    R: BufRead,
+{
+    let mut samples = Vec::new();
+
+    for result in reader.lines() {
+        let line = result?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut fields = line.splitn(2, DELIMITER);
+
+        let (name, src) = match (fields.next(), fields.next()) {
+            (Some(name), Some(src)) if !name.is_empty() && !src.is_empty() => (name, src),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid sample sheet line: {}", line),
+                ))
+            }
+        };
+
+        if samples.iter().any(|(n, _)| n == name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("duplicate sample name: {}", name),
+            ));
+        }
+
+        samples.push((name.into(), PathBuf::from(src)));
+    }
+
+    Ok(samples)
+}
+
+fn validate_feature_ids(
+    samples: &[(String, PathBuf)],
+    sample_counts: &[HashMap<String, f64>],
+) -> anyhow::Result<()> {
+    let (_, expected_src) = &samples[0];
+    let expected_counts = &sample_counts[0];
+
+    for ((_, src), counts) in samples.iter().zip(sample_counts).skip(1) {
+        let missing_id = expected_counts
+            .keys()
+            .find(|id| !counts.contains_key(*id))
+            .or_else(|| counts.keys().find(|id| !expected_counts.contains_key(*id)));
+
+        if let Some(id) = missing_id {
+            anyhow::bail!(
+                "feature IDs of {} do not match {}: {} is not in both",
+                src.display(),
+                expected_src.display(),
+                id
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_sample_sheet() -> io::Result<()> {
+        let data = b"\
+sample1\tcounts/s1.tsv
+
+sample2\tcounts/s2.tsv
+";
+
+        assert_eq!(
+            read_sample_sheet(&data[..])?,
+            [
+                (String::from("sample1"), PathBuf::from("counts/s1.tsv")),
+                (String::from("sample2"), PathBuf::from("counts/s2.tsv")),
+            ]
+        );
+
+        let data = b"sample1\n";
+        assert!(read_sample_sheet(&data[..]).is_err());
+
+        let data = b"\tcounts/s1.tsv\n";
+        assert!(read_sample_sheet(&data[..]).is_err());
+
+        let data = b"sample1\tcounts/s1.tsv\nsample1\tcounts/s2.tsv\n";
+        assert!(read_sample_sheet(&data[..]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_feature_ids() {
+        let samples = [
+            (String::from("sample1"), PathBuf::from("sample1.tsv")),
+            (String::from("sample2"), PathBuf::from("sample2.tsv")),
+        ];
+
+        let counts1: HashMap<_, _> = vec![(String::from("AADAT"), 302.0)].into_iter().collect();
+        let counts2: HashMap<_, _> = vec![(String::from("AADAT"), 8.0)].into_iter().collect();
+        assert!(validate_feature_ids(&samples, &[counts1.clone(), counts2]).is_ok());
+
+        let counts2: HashMap<_, _> = vec![(String::from("CLN3"), 8.0)].into_iter().collect();
+        assert!(validate_feature_ids(&samples, &[counts1.clone(), counts2]).is_err());
+
+        let counts2: HashMap<_, _> =
+            vec![(String::from("AADAT"), 8.0), (String::from("CLN3"), 37.0)]
+                .into_iter()
+                .collect();
+        assert!(validate_feature_ids(&samples, &[counts1, counts2]).is_err());
+    }
+}
@@ -8,14 +8,14 @@ use anyhow::Context;
 use log::info;
 
 use crate::{
-    count,
+    annotations, count,
     normalization::{self, calculate_fpkms, calculate_tpms},
-    read_features,
 };
 
 pub fn normalize<P, Q>(
     counts_src: P,
     annotations_src: Q,
+    annotations_format: Option<annotations::Format>,
     feature_type: &str,
     id: &str,
     method: normalization::Method,
@@ -33,11 +33,13 @@ where
         .read_counts()
         .with_context(|| format!("Could not read {}", counts_src.as_ref().display()))?;
 
-    let mut gff_reader = crate::gff::open(annotations_src.as_ref())
-        .with_context(|| format!("Could not open {}", annotations_src.as_ref().display()))?;
-
-    let feature_map = read_features(&mut gff_reader, feature_type, id)
-        .with_context(|| format!("Could not read {}", annotations_src.as_ref().display()))?;
+    let feature_map = annotations::read_features(
+        annotations_src.as_ref(),
+        annotations_format,
+        feature_type,
+        id,
+    )
+    .with_context(|| format!("Could not read {}", annotations_src.as_ref().display()))?;
 
     let feature_ids: Vec<_> = feature_map.keys().map(|id| id.into()).collect();
 
@@ -0,0 +1,156 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufWriter},
+    path::Path,
+};
+
+use anyhow::Context as AnyhowContext;
+use log::info;
+
+use crate::{
+    alignment, annotations, build_interval_trees,
+    count::{find, splicing, Filter, Mode},
+    hierarchy::Level,
+    qc::{self, Class},
+    Context, Feature, MatchIntervals, StrandSpecification,
+};
+
+// GFF3 uses Sequence Ontology names, and GTF (e.g., Ensembl) uses lowercase
+// names.
+static CDS_TYPES: &[&str] = &["CDS"];
+static FIVE_PRIME_UTR_TYPES: &[&str] = &["five_prime_UTR", "five_prime_utr"];
+static THREE_PRIME_UTR_TYPES: &[&str] = &["three_prime_UTR", "three_prime_utr"];
+
+pub fn qc<P, Q>(
+    src: P,
+    reference_src: Option<&Path>,
+    annotations_src: Q,
+    feature_type: &str,
+    filter: &Filter,
+) -> anyhow::Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let src = src.as_ref();
+    let annotations_src = annotations_src.as_ref();
+
+    info!("reading features");
+
+    // The annotations are read once for the exons and the feature types of
+    // each class.
+    let mut feature_types = vec![feature_type];
+    feature_types.extend(CDS_TYPES);
+    feature_types.extend(FIVE_PRIME_UTR_TYPES);
+    feature_types.extend(THREE_PRIME_UTR_TYPES);
+
+    let hierarchies = annotations::read_hierarchies(annotations_src, None, &feature_types)
+        .with_context(|| format!("Could not read {}", annotations_src.display()))?;
+
+    let type_features = |types: &[&str]| -> Vec<Feature> {
+        types
+            .iter()
+            .flat_map(|ty| hierarchies[*ty].features(Level::Exon).into_iter())
+            .flat_map(|(_, features)| features)
+            .collect()
+    };
+
+    let exons = &hierarchies[feature_type];
+
+    let introns: Vec<_> = exons
+        .features(Level::Gene)
+        .values()
+        .flat_map(|gene_exons| splicing::build_introns(gene_exons))
+        .collect();
+
+    let class_features = vec![
+        (Class::Cds, type_features(CDS_TYPES)),
+        (Class::FivePrimeUtr, type_features(FIVE_PRIME_UTR_TYPES)),
+        (Class::ThreePrimeUtr, type_features(THREE_PRIME_UTR_TYPES)),
+        (
+            Class::Exon,
+            exons
+                .features(Level::Exon)
+                .values()
+                .flatten()
+                .cloned()
+                .collect(),
+        ),
+        (Class::Intron, introns),
+    ];
+
+    let feature_map: HashMap<_, _> = class_features
+        .iter()
+        .map(|(class, features)| (String::from(class.name()), features.clone()))
+        .collect();
+
+    let (features, _) = build_interval_trees(&feature_map);
+
+    let (_, header, records) = alignment::open(src, reference_src)
+        .with_context(|| format!("Could not open {}", src.display()))?;
+
+    let reference_sequences = header.reference_sequences();
+
+    let reference_sequence_lengths: Vec<_> = reference_sequences
+        .values()
+        .map(|reference_sequence| {
+            (
+                String::from(reference_sequence.name()),
+                reference_sequence.len() as u64,
+            )
+        })
+        .collect();
+
+    let bases = qc::count_bases(&class_features, &reference_sequence_lengths);
+
+    info!("classifying records");
+
+    let mut reads: HashMap<&'static str, u64> = HashMap::new();
+    let mut ctx = Context::default();
+
+    for result in records {
+        let record = result?;
+
+        if filter.filter(&mut ctx, &record)? {
+            continue;
+        }
+
+        let reference_sequence_name = match *record.reference_sequence_id() {
+            Some(id) => match reference_sequences.get_index(id as usize) {
+                Some((name, _)) => name,
+                None => continue,
+            },
+            None => continue,
+        };
+
+        let class = match features.get(reference_sequence_name) {
+            Some(tree) => {
+                let cigar = record.cigar();
+                let start = i32::from(record.position()) as u64;
+                let intervals = MatchIntervals::new(&cigar, start);
+
+                let names = find(
+                    tree,
+                    intervals,
+                    Mode::Union,
+                    StrandSpecification::None,
+                    false,
+                )
+                .unwrap_or_default();
+
+                Class::from_names(names.iter().map(|name| name.as_str()))
+            }
+            None => Class::Intergenic,
+        };
+
+        *reads.entry(class.name()).or_insert(0) += 1;
+    }
+
+    let stdout = io::stdout();
+    let handle = stdout.lock();
+    let mut writer = BufWriter::new(handle);
+
+    qc::write_distribution(&mut writer, &bases, &reads).context("Could not write to stdout")?;
+
+    Ok(())
+}
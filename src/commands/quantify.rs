@@ -1,109 +1,1378 @@
 use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    env,
     fs::File,
-    io::{self, BufWriter},
-    path::Path,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
     sync::Arc,
 };
 
 use anyhow::Context as AnyhowContext;
 use log::{info, warn};
-use noodles::Region;
-use noodles_bam::{self as bam, bai};
+use noodles_bam as bam;
 use noodles_sam::{self as sam, header::ReferenceSequences};
 
 use crate::{
-    build_interval_trees,
+    alignment::{self, data::get_string_field},
+    annotations, bins, build_interval_trees,
     count::{
-        self, count_paired_end_record_singletons, count_paired_end_records,
-        count_single_end_records, Filter,
+        self, count_paired_end_record_pair, count_paired_end_record_singleton,
+        count_paired_end_record_singletons, count_paired_end_records, count_single_end_record,
+        count_single_end_records, splicing, AmbiguousMode, DuplicateMode, Filter, FragmentMode,
+        Mode, NonuniqueMode,
     },
-    detect::{detect_specification, LibraryLayout},
+    detect::{self, detect_specification, LibraryLayout},
+    hierarchy::Level,
+    index::{self, Index},
     normalization::{self, calculate_fpkms, calculate_tpms},
-    read_features, Context, Features, StrandSpecification, StrandSpecificationOption,
+    Context, Feature, Features, RecordPairs, StrandSpecification, StrandSpecificationOption,
 };
 
+static PROGRAM_NAME: &str = env!("CARGO_PKG_NAME");
+static PROGRAM_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const ASSIGNMENT_TAG: &[u8; 2] = b"XF";
+const READ_GROUP_TAG: &[u8; 2] = b"RG";
+
+type FeatureMap = HashMap<String, Vec<Feature>>;
+
+static DUPLICATION_COLUMN_NAMES: [&str; 3] = ["count", "duplicate", "duplication_rate"];
+
 #[allow(clippy::too_many_arguments)]
-pub fn quantify<P, Q, R>(
-    bam_src: P,
-    annotations_src: Q,
+pub fn quantify<P, R>(
+    srcs: &[P],
+    reference_src: Option<&Path>,
+    index_src: Option<&Path>,
+    annotations_src: Option<&Path>,
+    annotations_format: Option<annotations::Format>,
+    bin_size: Option<u64>,
     feature_type: &str,
     id: &str,
     filter: Filter,
+    mode: Mode,
+    ambiguous_mode: Option<AmbiguousMode>,
+    fragment_mode: FragmentMode,
     strand_specification_option: StrandSpecificationOption,
-    threads: usize,
+    group_tag: Option<&[u8; 2]>,
+    threads: Option<usize>,
     normalize: Option<normalization::Method>,
+    annotated_dst: Option<&Path>,
+    levels: &[Level],
+    splicing_dst: Option<&Path>,
+    duplication_dst: Option<&Path>,
     results_dst: R,
 ) -> anyhow::Result<()>
 where
     P: AsRef<Path>,
-    Q: AsRef<Path>,
     R: AsRef<Path>,
 {
-    let mut gff_reader = crate::gff::open(annotations_src)?;
-    let feature_map = read_features(&mut gff_reader, feature_type, id)?;
-    let (features, names) = build_interval_trees(&feature_map);
+    if srcs.len() > 1 && index_src.is_some() {
+        anyhow::bail!("an index can only be given for a single input");
+    }
 
-    let mut reader = File::open(bam_src.as_ref())
-        .map(bam::Reader::new)
-        .with_context(|| format!("Could not open {}", bam_src.as_ref().display()))?;
+    if srcs.len() > 1 && annotated_dst.is_some() {
+        anyhow::bail!("annotated alignments can only be written for a single input");
+    }
+
+    if levels.len() > 1 && annotated_dst.is_some() {
+        anyhow::bail!("annotated alignments can only be written for a single level");
+    }
+
+    if splicing_dst.is_some() && annotated_dst.is_some() {
+        anyhow::bail!("annotated alignments cannot be written with splicing counts");
+    }
+
+    // In the intersection modes, a record crossing an exon-intron boundary
+    // intersects neither the exon nor the intron at every position.
+    if splicing_dst.is_some() && mode != Mode::Union {
+        anyhow::bail!("splicing counts can only be counted using the union mode");
+    }
+
+    if group_tag.is_some() && annotated_dst.is_some() {
+        anyhow::bail!("annotated alignments cannot be written when splitting by group");
+    }
+
+    // Groups are counted sequentially from the start of the input.
+    if group_tag.is_some() && (index_src.is_some() || threads.is_some()) {
+        anyhow::bail!("an index or threads cannot be used when splitting by group");
+    }
+
+    let threads = threads.unwrap_or_else(num_cpus::get);
+
+    if bin_size.is_some() && (!levels.is_empty() || splicing_dst.is_some()) {
+        anyhow::bail!("bins cannot be counted with levels or splicing counts");
+    }
+
+    if bin_size.is_some()
+        && matches!(
+            strand_specification_option,
+            StrandSpecificationOption::Forward | StrandSpecificationOption::Reverse
+        )
+    {
+        anyhow::bail!("bins are unstranded and cannot be counted with a strand specification");
+    }
+
+    if duplication_dst.is_some() && filter.duplicate_mode() != DuplicateMode::Keep {
+        anyhow::bail!("duplication rates can only be calculated when keeping duplicates");
+    }
+
+    // UMI-deduplicated records are counted before their duplicate flags are
+    // read, so they would be missing from the duplicate counts.
+    if duplication_dst.is_some() && filter.umi_source().is_some() {
+        anyhow::bail!("duplication rates cannot be calculated when deduplicating UMIs");
+    }
+
+    // Nonunique records distributed by EM are counted before their duplicate
+    // flags are read, so they would also be missing from the duplicate counts.
+    if duplication_dst.is_some() && filter.nonunique_mode() == Some(NonuniqueMode::Em) {
+        anyhow::bail!("duplication rates cannot be calculated with the em nonunique mode");
+    }
+
+    let sample_names = build_sample_names(srcs)?;
+
+    // With groups, there is a column for each group of each sample.
+    let mut column_names = Vec::with_capacity(sample_names.len());
+
+    let mut hierarchy = None;
+
+    // Bins are written in genomic order rather than by ID.
+    let mut bin_ids = None;
+
+    let feature_maps = match (annotations_src, bin_size) {
+        (_, Some(bin_size)) => {
+            let (features, ids) = read_bins(srcs, reference_src, bin_size)?;
+            bin_ids = Some(ids);
+            vec![features]
+        }
+        (Some(annotations_src), None) if levels.is_empty() => {
+            vec![annotations::read_features(
+                annotations_src,
+                annotations_format,
+                feature_type,
+                id,
+            )?]
+        }
+        (Some(annotations_src), None) => {
+            let h = hierarchy.get_or_insert(annotations::read_hierarchy(
+                annotations_src,
+                annotations_format,
+                feature_type,
+            )?);
+
+            levels.iter().map(|&level| h.features(level)).collect()
+        }
+        (None, None) => anyhow::bail!("either annotations or a bin size must be given"),
+    };
+
+    // Splicing counts are counted with the levels as an additional set of
+    // features.
+    let splicing_feature_map = splicing_dst.map(|_| match &hierarchy {
+        Some(h) => splicing::build_features(&h.features(Level::Gene)),
+        None => splicing::build_features(&feature_maps[0]),
+    });
+
+    let mut level_features = Vec::with_capacity(feature_maps.len());
+    let mut level_feature_ids = Vec::with_capacity(feature_maps.len());
+
+    let feature_maps_with_splicing = feature_maps
+        .iter()
+        .map(|feature_map| (feature_map, false))
+        .chain(
+            splicing_feature_map
+                .iter()
+                .map(|feature_map| (feature_map, true)),
+        );
+
+    for (feature_map, splicing) in feature_maps_with_splicing {
+        let (features, names) = build_interval_trees(feature_map);
+
+        level_features.push(LevelFeatures {
+            features: Arc::new(features),
+            splicing,
+        });
+
+        let feature_ids = match bin_ids.take() {
+            Some(ids) => ids,
+            None => {
+                let mut feature_ids = Vec::with_capacity(names.len());
+                feature_ids.extend(names.into_iter());
+                feature_ids.sort();
+                feature_ids
+            }
+        };
+
+        level_feature_ids.push(feature_ids);
+    }
+
+    let mut level_ctxs: Vec<Vec<Context>> = level_features
+        .iter()
+        .map(|_| Vec::with_capacity(srcs.len()))
+        .collect();
+
+    for (src, sample_name) in srcs.iter().zip(&sample_names) {
+        let src = src.as_ref();
+
+        info!("quantifying {}", src.display());
+
+        if let Some(group_tag) = group_tag {
+            let groups = quantify_sample_by_group(
+                src,
+                reference_src,
+                &level_features,
+                &filter,
+                mode,
+                ambiguous_mode,
+                fragment_mode,
+                strand_specification_option,
+                group_tag,
+            )?;
+
+            // A sample without groups still has a column, so the samples of
+            // the matrix are not lost.
+            if groups.is_empty() {
+                column_names.push(sample_name.clone());
+
+                for sample_ctxs in level_ctxs.iter_mut() {
+                    sample_ctxs.push(Context::default());
+                }
+            }
+
+            for (group, ctxs) in groups {
+                if srcs.len() > 1 {
+                    column_names.push(format!("{}.{}", sample_name, group));
+                } else {
+                    column_names.push(group);
+                }
+
+                for (sample_ctxs, ctx) in level_ctxs.iter_mut().zip(ctxs) {
+                    sample_ctxs.push(ctx);
+                }
+            }
+
+            continue;
+        }
+
+        let ctxs = quantify_sample(
+            src,
+            reference_src,
+            index_src,
+            &level_features,
+            &filter,
+            mode,
+            ambiguous_mode,
+            fragment_mode,
+            strand_specification_option,
+            threads,
+            annotated_dst,
+        )?;
+
+        column_names.push(sample_name.clone());
+
+        for (sample_ctxs, ctx) in level_ctxs.iter_mut().zip(ctxs) {
+            sample_ctxs.push(ctx);
+        }
+    }
+
+    // With groups, the output is always a matrix, so its layout does not depend
+    // on the number of groups.
+    let is_matrix = group_tag.is_some() || column_names.len() > 1;
+
+    if let Some(dst) = splicing_dst {
+        let ctxs = level_ctxs.pop().expect("missing splicing counts");
+        let ids = level_feature_ids
+            .pop()
+            .expect("missing splicing feature IDs");
+        let gene_ids = splicing::gene_ids(&ids);
+        write_splicing_results(dst, &column_names, &gene_ids, &ctxs)?;
+    }
+
+    // Duplication rates are only written for the first level.
+    if let Some(dst) = duplication_dst {
+        write_duplication_results(
+            dst,
+            &column_names,
+            &level_feature_ids[0],
+            &level_ctxs[0],
+            is_matrix,
+        )?;
+    }
+
+    let results_dst = results_dst.as_ref();
+
+    let dsts: Vec<_> = if levels.is_empty() {
+        vec![results_dst.to_path_buf()]
+    } else {
+        levels
+            .iter()
+            .map(|&level| build_level_dst(results_dst, level))
+            .collect()
+    };
+
+    for (((dst, feature_ids), feature_map), ctxs) in dsts
+        .iter()
+        .zip(&level_feature_ids)
+        .zip(&feature_maps)
+        .zip(&level_ctxs)
+    {
+        write_results(
+            dst,
+            &column_names,
+            feature_ids,
+            feature_map,
+            ctxs,
+            normalize,
+            filter.duplicate_mode(),
+            is_matrix,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// The features of a counting level
+#[derive(Clone)]
+struct LevelFeatures {
+    features: Arc<Features>,
+    /// Whether the features are splicing features (see `splicing::build_features`)
+    splicing: bool,
+}
+
+/// Builds fixed-width bins from the reference sequences of the first input.
+///
+/// The header is read before the inputs are counted, so the first input cannot
+/// be stdin.
+/// Builds bins over the reference sequences of the first input.
+///
+/// This returns the bins and their IDs in genomic order.
+fn read_bins<P>(
+    srcs: &[P],
+    reference_src: Option<&Path>,
+    bin_size: u64,
+) -> anyhow::Result<(FeatureMap, Vec<String>)>
+where
+    P: AsRef<Path>,
+{
+    let src = match srcs.first() {
+        Some(src) => src.as_ref(),
+        None => anyhow::bail!("missing input"),
+    };
+
+    if alignment::is_stdin(src) {
+        anyhow::bail!("bins cannot be built from stdin");
+    }
+
+    if bin_size == 0 {
+        anyhow::bail!("invalid bin size: {}", bin_size);
+    }
+
+    let (_, header, _) = alignment::open(src, reference_src)
+        .with_context(|| format!("Could not open {}", src.display()))?;
+
+    let reference_sequences = header.reference_sequences();
+    let features = bins::build_features(reference_sequences, bin_size);
+    let ids = bins::sorted_ids(&features, reference_sequences);
+    info!("built {} bins", features.len());
+
+    Ok((features, ids))
+}
+
+/// Builds the output destination of a feature level by adding the level name
+/// before the extension, e.g., `counts.gene.tsv` from `counts.tsv`.
+fn build_level_dst(dst: &Path, level: Level) -> PathBuf {
+    let mut file_name = dst
+        .file_stem()
+        .map(|s| s.to_os_string())
+        .unwrap_or_default();
+
+    file_name.push(".");
+    file_name.push(level.name());
+
+    if let Some(extension) = dst.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+
+    dst.with_file_name(file_name)
+}
+
+/// Writes the exonic, intronic, and spanning counts of each gene.
+///
+/// There are three columns per sample, one for each splicing kind. With
+/// multiple samples, the column names are prefixed with the sample name, e.g.,
+/// `sample1.exonic`.
+fn write_splicing_results(
+    dst: &Path,
+    sample_names: &[String],
+    gene_ids: &[String],
+    ctxs: &[Context],
+) -> anyhow::Result<()> {
+    let mut writer = File::create(dst)
+        .map(BufWriter::new)
+        .map(count::Writer::new)
+        .with_context(|| format!("Could not open {}", dst.display()))?;
+
+    let mut column_names = Vec::with_capacity(ctxs.len() * splicing::KINDS.len());
+    let mut counts = Vec::with_capacity(column_names.capacity());
+
+    for (sample_name, ctx) in sample_names.iter().zip(ctxs) {
+        for &kind in &splicing::KINDS {
+            if ctxs.len() > 1 {
+                column_names.push(format!("{}.{}", sample_name, kind.name()));
+            } else {
+                column_names.push(kind.name().into());
+            }
+
+            counts.push(splicing::kind_counts(&ctx.counts, kind));
+        }
+    }
+
+    let counts: Vec<_> = counts.iter().collect();
+
+    info!("writing splicing counts to {}", dst.display());
+
+    writer
+        .write_header(&column_names)
+        .and_then(|_| writer.write_count_matrix(gene_ids, &counts))
+        .with_context(|| format!("Could not write {}", dst.display()))?;
 
-    let header: sam::Header = reader
-        .read_header()?
-        .parse()
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-        .context("Could not parse BAM header")?;
+    Ok(())
+}
+
+/// Writes the count, duplicate count, and duplication rate of each feature.
+///
+/// The duplication rate is the fraction of the count from records marked as
+/// duplicates, or 0 for a feature with no count. In a matrix, the column names
+/// are prefixed with the sample name, e.g., `sample1.count`.
+fn write_duplication_results(
+    dst: &Path,
+    sample_names: &[String],
+    feature_ids: &[String],
+    ctxs: &[Context],
+    is_matrix: bool,
+) -> anyhow::Result<()> {
+    let mut writer = File::create(dst)
+        .map(BufWriter::new)
+        .map(count::Writer::new)
+        .with_context(|| format!("Could not open {}", dst.display()))?;
+
+    let mut column_names = Vec::with_capacity(ctxs.len() * DUPLICATION_COLUMN_NAMES.len());
+
+    for sample_name in sample_names {
+        for name in &DUPLICATION_COLUMN_NAMES {
+            if is_matrix {
+                column_names.push(format!("{}.{}", sample_name, name));
+            } else {
+                column_names.push(String::from(*name));
+            }
+        }
+    }
+
+    let rates: Vec<_> = ctxs
+        .iter()
+        .map(|ctx| calculate_duplication_rates(&ctx.counts, &ctx.duplicate_counts))
+        .collect();
+
+    let mut columns = Vec::with_capacity(column_names.len());
+
+    for (ctx, sample_rates) in ctxs.iter().zip(&rates) {
+        columns.push(&ctx.counts);
+        columns.push(&ctx.duplicate_counts);
+        columns.push(sample_rates);
+    }
+
+    info!("writing duplication rates to {}", dst.display());
+
+    writer
+        .write_header(&column_names)
+        .and_then(|_| writer.write_count_matrix(feature_ids, &columns))
+        .with_context(|| format!("Could not write {}", dst.display()))?;
+
+    Ok(())
+}
+
+fn calculate_duplication_rates(
+    counts: &HashMap<String, f64>,
+    duplicate_counts: &HashMap<String, f64>,
+) -> HashMap<String, f64> {
+    counts
+        .iter()
+        .filter(|(_, &count)| count > 0.0)
+        .map(|(id, count)| {
+            let duplicate_count = duplicate_counts.get(id).copied().unwrap_or(0.0);
+            (id.clone(), duplicate_count / count)
+        })
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn write_results(
+    dst: &Path,
+    sample_names: &[String],
+    feature_ids: &[String],
+    feature_map: &HashMap<String, Vec<Feature>>,
+    ctxs: &[Context],
+    normalize: Option<normalization::Method>,
+    duplicate_mode: DuplicateMode,
+    is_matrix: bool,
+) -> anyhow::Result<()> {
+    let writer = File::create(dst)
+        .map(BufWriter::new)
+        .with_context(|| format!("Could not open {}", dst.display()))?;
+
+    info!("writing results to {}", dst.display());
+
+    write_results_to(
+        writer,
+        sample_names,
+        feature_ids,
+        feature_map,
+        ctxs,
+        normalize,
+        duplicate_mode,
+        is_matrix,
+    )
+}
+
+/// Writes counts or normalized values.
+///
+/// A matrix has a header of sample names and a column for each sample.
+/// Otherwise, there is a single sample, which is written without a header.
+#[allow(clippy::too_many_arguments)]
+fn write_results_to<W>(
+    writer: W,
+    sample_names: &[String],
+    feature_ids: &[String],
+    feature_map: &HashMap<String, Vec<Feature>>,
+    ctxs: &[Context],
+    normalize: Option<normalization::Method>,
+    duplicate_mode: DuplicateMode,
+    is_matrix: bool,
+) -> anyhow::Result<()>
+where
+    W: Write,
+{
+    if let Some(normalization_method) = normalize {
+        let mut value_writer = normalization::Writer::new(writer);
+
+        let values = match normalization_method {
+            normalization::Method::Fpkm => {
+                info!("calculating fpkms");
+                ctxs.iter()
+                    .map(|ctx| calculate_fpkms(&ctx.counts, feature_map))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+            }
+            normalization::Method::Tpm => {
+                info!("calculating tpms");
+                ctxs.iter()
+                    .map(|ctx| calculate_tpms(&ctx.counts, feature_map))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+            }
+        };
+
+        match &values[..] {
+            [sample_values] if !is_matrix => {
+                value_writer.write_values(feature_ids, sample_values)?;
+            }
+            _ => {
+                value_writer.write_header(sample_names)?;
+                value_writer.write_value_matrix(feature_ids, &values)?;
+            }
+        }
+    } else {
+        let mut count_writer = count::Writer::new(writer);
+
+        match ctxs {
+            [ctx] if !is_matrix => {
+                count_writer.write_counts(feature_ids, &ctx.counts)?;
+                count_writer.write_stats(ctx, duplicate_mode)?;
+            }
+            _ => {
+                let counts: Vec<_> = ctxs.iter().map(|ctx| &ctx.counts).collect();
+                let ctxs: Vec<_> = ctxs.iter().collect();
 
-    let bai_src = bam_src.as_ref().with_extension("bam.bai");
-    let index =
-        bai::read(&bai_src).with_context(|| format!("Could not read {}", bai_src.display()))?;
+                count_writer.write_header(sample_names)?;
+                count_writer.write_count_matrix(feature_ids, &counts)?;
+                count_writer.write_stats_matrix(&ctxs, duplicate_mode)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds sample names from the file stems of the inputs, e.g., `sample1` from
+/// `sample1.bam`.
+pub(super) fn build_sample_names<P>(srcs: &[P]) -> anyhow::Result<Vec<String>>
+where
+    P: AsRef<Path>,
+{
+    let mut sample_names: Vec<String> = Vec::with_capacity(srcs.len());
+
+    for src in srcs {
+        let src = src.as_ref();
+
+        let name = src
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| src.to_string_lossy().into_owned());
+
+        if sample_names.contains(&name) {
+            anyhow::bail!("duplicate sample name: {}", name);
+        }
+
+        sample_names.push(name);
+    }
+
+    Ok(sample_names)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn quantify_sample(
+    src: &Path,
+    reference_src: Option<&Path>,
+    index_src: Option<&Path>,
+    level_features: &[LevelFeatures],
+    filter: &Filter,
+    mode: Mode,
+    ambiguous_mode: Option<AmbiguousMode>,
+    fragment_mode: FragmentMode,
+    strand_specification_option: StrandSpecificationOption,
+    threads: usize,
+    annotated_dst: Option<&Path>,
+) -> anyhow::Result<Vec<Context>> {
+    // Exons are the same at every level, so any level can be used for detection.
+    let features = &level_features[0].features;
+
+    let (format, header, mut records) = alignment::open(src, reference_src)
+        .with_context(|| format!("Could not open {}", src.display()))?;
 
     let reference_sequences = header.reference_sequences().clone();
 
-    let mut feature_ids = Vec::with_capacity(names.len());
-    feature_ids.extend(names.into_iter());
-    feature_ids.sort();
+    // Only stdin is buffered for detection. Other inputs are reopened to be
+    // counted.
+    let detection_records = if alignment::is_stdin(src) {
+        Some(detect::read_detection_records(&mut records)?)
+    } else {
+        None
+    };
+
+    let (library_layout, strand_specification) = match detection_records {
+        Some(ref buf) => detect::detect_library_type(
+            buf.iter().cloned().map(Ok),
+            &reference_sequences,
+            features,
+            strand_specification_option,
+        )?,
+        None => detect::detect_library_type(
+            records.by_ref(),
+            &reference_sequences,
+            features,
+            strand_specification_option,
+        )?,
+    };
+
+    info!("counting features");
+
+    let index = match format {
+        // Annotated records are written in input order, so they are not counted by region.
+        alignment::Format::Bam if !alignment::is_stdin(src) && annotated_dst.is_none() => {
+            read_index(src, index_src)?
+        }
+        _ => None,
+    };
+
+    let mut ctxs = if let Some(index) = index {
+        count_bam_records_by_region(
+            src,
+            Arc::new(index),
+            reference_sequences.clone(),
+            Arc::new(level_features.to_vec()),
+            library_layout,
+            filter.clone(),
+            mode,
+            ambiguous_mode,
+            fragment_mode,
+            strand_specification,
+            threads,
+        )?
+    } else {
+        info!("counting records sequentially");
+
+        let records: alignment::Records = match detection_records {
+            Some(buf) => Box::new(buf.into_iter().map(Ok).chain(records)),
+            None => {
+                let (_, _, records) = alignment::open(src, reference_src)
+                    .with_context(|| format!("Could not open {}", src.display()))?;
+                records
+            }
+        };
+
+        match (annotated_dst, level_features) {
+            (Some(dst), [level]) => vec![count_records_with_assignments(
+                records,
+                &header,
+                dst,
+                &level.features,
+                &reference_sequences,
+                library_layout,
+                filter,
+                mode,
+                ambiguous_mode,
+                fragment_mode,
+                strand_specification,
+            )?],
+            (_, [level]) => vec![count_records(
+                records,
+                &level.features,
+                &reference_sequences,
+                library_layout,
+                filter,
+                mode,
+                ambiguous_mode,
+                fragment_mode,
+                strand_specification,
+            )?],
+            _ => count_records_by_level(
+                records,
+                level_features,
+                &reference_sequences,
+                library_layout,
+                filter,
+                mode,
+                ambiguous_mode,
+                fragment_mode,
+                strand_specification,
+            )?,
+        }
+    };
+
+    resolve_hits(filter, &mut ctxs);
+
+    Ok(ctxs)
+}
+
+/// Resolves the hits that are collected before they are counted, i.e.,
+/// nonunique hits and UMI hits.
+fn resolve_hits(filter: &Filter, ctxs: &mut [Context]) {
+    if filter.nonunique_mode().is_some() {
+        info!("distributing nonunique records");
+
+        for ctx in ctxs.iter_mut() {
+            ctx.distribute_nonunique_hits();
+        }
+    }
+
+    if filter.umi_source().is_some() {
+        info!("deduplicating UMIs");
+
+        for ctx in ctxs.iter_mut() {
+            ctx.deduplicate_umi_hits();
+        }
+    }
+}
+
+/// Quantifies a sample split by the value of a data field of each record,
+/// e.g., the read group (`RG`).
+///
+/// The strand specification is detected for each group, but the library layout
+/// is detected for the whole sample. Records are counted sequentially. With the
+/// `RG` tag, every read group in the header is a group, even if it has no
+/// records. Records without the tag are not counted.
+///
+/// This returns the contexts of each level for each group, sorted by group.
+#[allow(clippy::too_many_arguments)]
+fn quantify_sample_by_group(
+    src: &Path,
+    reference_src: Option<&Path>,
+    level_features: &[LevelFeatures],
+    filter: &Filter,
+    mode: Mode,
+    ambiguous_mode: Option<AmbiguousMode>,
+    fragment_mode: FragmentMode,
+    strand_specification_option: StrandSpecificationOption,
+    group_tag: &[u8; 2],
+) -> anyhow::Result<BTreeMap<String, Vec<Context>>> {
+    let features = &level_features[0].features;
+
+    let (_, header, mut records) = alignment::open(src, reference_src)
+        .with_context(|| format!("Could not open {}", src.display()))?;
+
+    let reference_sequences = header.reference_sequences().clone();
 
     info!("detecting library type");
 
-    let (library_layout, detected_strand_specification, strandedness_confidence) =
-        detect_specification(&bam_src, &reference_sequences, &features)?;
+    // Records are buffered for detection by group, so they are counted
+    // afterward.
+    let detection_records = detect::read_detection_records(&mut records)?;
+
+    let (library_layout, detected_strand_specification, _) = detect_specification(
+        detection_records.iter().cloned().map(Ok),
+        &reference_sequences,
+        features,
+    )?;
 
     match library_layout {
         LibraryLayout::SingleEnd => info!("library layout: single end"),
         LibraryLayout::PairedEnd => info!("library layout: paired end"),
     }
 
-    match detected_strand_specification {
-        StrandSpecification::None => info!(
-            "strand specification: none (confidence: {:.2})",
-            strandedness_confidence
-        ),
-        StrandSpecification::Forward => info!(
-            "strand specification: forward (confidence: {:.2})",
-            strandedness_confidence
-        ),
-        StrandSpecification::Reverse => info!(
-            "strand specification: reverse (confidence: {:.2})",
-            strandedness_confidence
-        ),
+    let mut group_detection_records: BTreeMap<String, Vec<bam::Record>> = BTreeMap::new();
+
+    for record in &detection_records {
+        if let Some(group) = get_string_field(record, group_tag)? {
+            group_detection_records
+                .entry(group)
+                .or_default()
+                .push(record.clone());
+        }
     }
 
-    let strand_specification = match strand_specification_option {
-        StrandSpecificationOption::None => StrandSpecification::None,
-        StrandSpecificationOption::Forward => StrandSpecification::Forward,
-        StrandSpecificationOption::Reverse => StrandSpecification::Reverse,
-        StrandSpecificationOption::Auto => detected_strand_specification,
+    let mut strand_specifications = HashMap::new();
+
+    for (group, group_records) in group_detection_records {
+        let (_, detected_strand_specification, strandedness_confidence) = detect_specification(
+            group_records.into_iter().map(Ok),
+            &reference_sequences,
+            features,
+        )?;
+
+        info!(
+            "{}: strand specification: {:?} (confidence: {:.2})",
+            group, detected_strand_specification, strandedness_confidence
+        );
+
+        let strand_specification = detect::resolve_strand_specification(
+            strand_specification_option,
+            detected_strand_specification,
+        );
+
+        if strand_specification != detected_strand_specification {
+            warn!(
+                "{}: input strand specification ({:?}) does not match detected strandedness ({:?})",
+                group, strand_specification, detected_strand_specification,
+            );
+        }
+
+        strand_specifications.insert(group, strand_specification);
+    }
+
+    // Groups without records in the detection records use the detection of the
+    // whole sample.
+    let default_strand_specification = detect::resolve_strand_specification(
+        strand_specification_option,
+        detected_strand_specification,
+    );
+
+    info!("counting features by group");
+
+    let records: alignment::Records =
+        Box::new(detection_records.into_iter().map(Ok).chain(records));
+
+    let mut groups = count_records_by_group(
+        records,
+        level_features,
+        &reference_sequences,
+        library_layout,
+        filter,
+        mode,
+        ambiguous_mode,
+        fragment_mode,
+        group_tag,
+        &strand_specifications,
+        default_strand_specification,
+    )?;
+
+    if group_tag == READ_GROUP_TAG {
+        for id in header.read_groups().keys() {
+            groups
+                .entry(id.clone())
+                .or_insert_with(|| level_features.iter().map(|_| Context::default()).collect());
+        }
+    }
+
+    for ctxs in groups.values_mut() {
+        resolve_hits(filter, ctxs);
+    }
+
+    Ok(groups)
+}
+
+/// Counts records sequentially for each feature level, split by group.
+#[allow(clippy::too_many_arguments)]
+fn count_records_by_group(
+    records: alignment::Records,
+    level_features: &[LevelFeatures],
+    reference_sequences: &ReferenceSequences,
+    library_layout: LibraryLayout,
+    filter: &Filter,
+    mode: Mode,
+    ambiguous_mode: Option<AmbiguousMode>,
+    fragment_mode: FragmentMode,
+    group_tag: &[u8; 2],
+    strand_specifications: &HashMap<String, StrandSpecification>,
+    default_strand_specification: StrandSpecification,
+) -> io::Result<BTreeMap<String, Vec<Context>>> {
+    let mut groups: BTreeMap<String, Vec<Context>> = BTreeMap::new();
+    let mut ungrouped_count = 0;
+
+    let mut get_group =
+        |record: &bam::Record| -> io::Result<Option<(String, StrandSpecification)>> {
+            match get_string_field(record, group_tag)? {
+                Some(group) => {
+                    let strand_specification = strand_specifications
+                        .get(&group)
+                        .copied()
+                        .unwrap_or(default_strand_specification);
+
+                    Ok(Some((group, strand_specification)))
+                }
+                None => {
+                    ungrouped_count += 1;
+                    Ok(None)
+                }
+            }
+        };
+
+    let new_ctxs = || {
+        level_features
+            .iter()
+            .map(|_| Context::default())
+            .collect::<Vec<_>>()
     };
 
-    if strand_specification != detected_strand_specification {
+    match library_layout {
+        LibraryLayout::SingleEnd => {
+            for result in records {
+                let record = result?;
+
+                let (group, strand_specification) = match get_group(&record)? {
+                    Some(g) => g,
+                    None => continue,
+                };
+
+                let ctxs = groups.entry(group).or_insert_with(new_ctxs);
+
+                for (ctx, level) in ctxs.iter_mut().zip(level_features) {
+                    count_single_end_record(
+                        ctx,
+                        &level.features,
+                        level.splicing,
+                        reference_sequences,
+                        filter,
+                        mode,
+                        ambiguous_mode,
+                        fragment_mode,
+                        strand_specification,
+                        &record,
+                    )?;
+                }
+            }
+        }
+        LibraryLayout::PairedEnd => {
+            let primary_only =
+                !filter.with_secondary_records() && !filter.with_supplementary_records();
+            let mut pairs = RecordPairs::new(records, primary_only);
+
+            for pair in &mut pairs {
+                let (r1, r2) = pair?;
+
+                let (group, strand_specification) = match get_group(&r1)? {
+                    Some(g) => g,
+                    None => continue,
+                };
+
+                let ctxs = groups.entry(group).or_insert_with(new_ctxs);
+
+                for (ctx, level) in ctxs.iter_mut().zip(level_features) {
+                    count_paired_end_record_pair(
+                        ctx,
+                        &level.features,
+                        level.splicing,
+                        reference_sequences,
+                        filter,
+                        mode,
+                        ambiguous_mode,
+                        fragment_mode,
+                        strand_specification,
+                        &r1,
+                        &r2,
+                    )?;
+                }
+            }
+
+            for record in pairs.singletons() {
+                let (group, strand_specification) = match get_group(&record)? {
+                    Some(g) => g,
+                    None => continue,
+                };
+
+                let ctxs = groups.entry(group).or_insert_with(new_ctxs);
+
+                for (ctx, level) in ctxs.iter_mut().zip(level_features) {
+                    count_paired_end_record_singleton(
+                        ctx,
+                        &level.features,
+                        level.splicing,
+                        reference_sequences,
+                        filter,
+                        mode,
+                        ambiguous_mode,
+                        fragment_mode,
+                        strand_specification,
+                        &record,
+                    )?;
+                }
+            }
+        }
+    }
+
+    if ungrouped_count > 0 {
         warn!(
-            "input strand specification ({:?}) does not match detected strandedness ({:?})",
-            strand_specification, detected_strand_specification,
+            "{} records without a group were not counted",
+            ungrouped_count
         );
     }
 
-    info!("counting features");
+    Ok(groups)
+}
+
+/// Counts records sequentially for each feature level in a single pass.
+#[allow(clippy::too_many_arguments)]
+fn count_records_by_level(
+    records: alignment::Records,
+    level_features: &[LevelFeatures],
+    reference_sequences: &ReferenceSequences,
+    library_layout: LibraryLayout,
+    filter: &Filter,
+    mode: Mode,
+    ambiguous_mode: Option<AmbiguousMode>,
+    fragment_mode: FragmentMode,
+    strand_specification: StrandSpecification,
+) -> io::Result<Vec<Context>> {
+    let mut ctxs: Vec<_> = level_features.iter().map(|_| Context::default()).collect();
+
+    match library_layout {
+        LibraryLayout::SingleEnd => {
+            for result in records {
+                let record = result?;
+
+                for (ctx, level) in ctxs.iter_mut().zip(level_features) {
+                    count_single_end_record(
+                        ctx,
+                        &level.features,
+                        level.splicing,
+                        reference_sequences,
+                        filter,
+                        mode,
+                        ambiguous_mode,
+                        fragment_mode,
+                        strand_specification,
+                        &record,
+                    )?;
+                }
+            }
+        }
+        LibraryLayout::PairedEnd => {
+            let primary_only =
+                !filter.with_secondary_records() && !filter.with_supplementary_records();
+            let mut pairs = RecordPairs::new(records, primary_only);
+
+            for pair in &mut pairs {
+                let (r1, r2) = pair?;
+
+                for (ctx, level) in ctxs.iter_mut().zip(level_features) {
+                    count_paired_end_record_pair(
+                        ctx,
+                        &level.features,
+                        level.splicing,
+                        reference_sequences,
+                        filter,
+                        mode,
+                        ambiguous_mode,
+                        fragment_mode,
+                        strand_specification,
+                        &r1,
+                        &r2,
+                    )?;
+                }
+            }
+
+            for record in pairs.singletons() {
+                for (ctx, level) in ctxs.iter_mut().zip(level_features) {
+                    count_paired_end_record_singleton(
+                        ctx,
+                        &level.features,
+                        level.splicing,
+                        reference_sequences,
+                        filter,
+                        mode,
+                        ambiguous_mode,
+                        fragment_mode,
+                        strand_specification,
+                        &record,
+                    )?;
+                }
+            }
+        }
+    }
+
+    Ok(ctxs)
+}
+
+/// Counts records sequentially and writes each record with its assignment.
+///
+/// The assignment is set as the BAM data field `XF` (see `count::assignment`).
+/// Paired end records are written as pairs are found, i.e., mates are written
+/// together, followed by the singletons. This does not keep the input sort
+/// order, so the output header sort order (`@HD SO`) is set to unsorted.
+#[allow(clippy::too_many_arguments)]
+fn count_records_with_assignments(
+    records: alignment::Records,
+    header: &sam::Header,
+    dst: &Path,
+    features: &Features,
+    reference_sequences: &ReferenceSequences,
+    library_layout: LibraryLayout,
+    filter: &Filter,
+    mode: Mode,
+    ambiguous_mode: Option<AmbiguousMode>,
+    fragment_mode: FragmentMode,
+    strand_specification: StrandSpecification,
+) -> anyhow::Result<Context> {
+    let mut raw_header = add_program(&header.to_string(), &command_line());
+
+    if let LibraryLayout::PairedEnd = library_layout {
+        raw_header = set_unsorted(&raw_header);
+    }
+
+    let header = raw_header
+        .parse::<sam::Header>()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let mut writer = File::create(dst)
+        .map(bam::Writer::new)
+        .with_context(|| format!("Could not open {}", dst.display()))?;
+
+    writer.write_header(&header)?;
+    writer.write_reference_sequences(header.reference_sequences())?;
+
+    let mut ctx = Context::default();
+
+    match library_layout {
+        LibraryLayout::SingleEnd => {
+            for result in records {
+                let mut record = result?;
+                let mut record_ctx = Context::default();
+
+                count_single_end_record(
+                    &mut record_ctx,
+                    features,
+                    false,
+                    reference_sequences,
+                    filter,
+                    mode,
+                    ambiguous_mode,
+                    fragment_mode,
+                    strand_specification,
+                    &record,
+                )?;
+
+                write_assigned_record(&mut writer, &record_ctx, &mut record)?;
+                ctx.add(&record_ctx);
+            }
+        }
+        LibraryLayout::PairedEnd => {
+            let primary_only =
+                !filter.with_secondary_records() && !filter.with_supplementary_records();
+
+            // Records that are not paired are skipped by the pairing, so they are
+            // written before it.
+            let writer = RefCell::new(writer);
+
+            let records = records.filter_map(|result| match result {
+                Ok(mut record) if primary_only && is_not_primary(&record) => {
+                    let record_ctx = Context::default();
+                    write_assigned_record(&mut writer.borrow_mut(), &record_ctx, &mut record)
+                        .err()
+                        .map(Err)
+                }
+                result => Some(result),
+            });
+
+            let mut pairs = RecordPairs::new(records, primary_only);
+
+            for pair in &mut pairs {
+                let (mut r1, mut r2) = pair?;
+                let mut record_ctx = Context::default();
+
+                count_paired_end_record_pair(
+                    &mut record_ctx,
+                    features,
+                    false,
+                    reference_sequences,
+                    filter,
+                    mode,
+                    ambiguous_mode,
+                    fragment_mode,
+                    strand_specification,
+                    &r1,
+                    &r2,
+                )?;
+
+                write_assigned_record(&mut writer.borrow_mut(), &record_ctx, &mut r1)?;
+                write_assigned_record(&mut writer.borrow_mut(), &record_ctx, &mut r2)?;
+                ctx.add(&record_ctx);
+            }
+
+            for mut record in pairs.singletons() {
+                let mut record_ctx = Context::default();
+
+                count_paired_end_record_singleton(
+                    &mut record_ctx,
+                    features,
+                    false,
+                    reference_sequences,
+                    filter,
+                    mode,
+                    ambiguous_mode,
+                    fragment_mode,
+                    strand_specification,
+                    &record,
+                )?;
+
+                write_assigned_record(&mut writer.borrow_mut(), &record_ctx, &mut record)?;
+                ctx.add(&record_ctx);
+            }
+        }
+    }
+
+    Ok(ctx)
+}
+
+fn is_not_primary(record: &bam::Record) -> bool {
+    let flags = record.flags();
+    flags.is_secondary() || flags.is_supplementary()
+}
+
+fn write_assigned_record<W>(
+    writer: &mut bam::Writer<W>,
+    record_ctx: &Context,
+    record: &mut bam::Record,
+) -> io::Result<()>
+where
+    W: Write,
+{
+    let assignment = count::assignment(record_ctx);
+    alignment::data::set_string_field(record, ASSIGNMENT_TAG, &assignment)?;
+    writer.write_record(record)
+}
+
+fn command_line() -> String {
+    env::args().collect::<Vec<_>>().join(" ")
+}
+
+/// Adds a program (`@PG`) line for this program to a raw SAM header.
+///
+/// The program ID is made unique, and the previous program (`PP`) is the last
+/// program in the header, if any.
+fn add_program(header: &str, command_line: &str) -> String {
+    let ids: Vec<_> = header
+        .lines()
+        .filter(|line| line.starts_with("@PG\t"))
+        .filter_map(|line| line.split('\t').find_map(|field| field.strip_prefix("ID:")))
+        .collect();
+
+    let mut id = String::from(PROGRAM_NAME);
+    let mut i = 1;
+
+    while ids.contains(&id.as_str()) {
+        id = format!("{}.{}", PROGRAM_NAME, i);
+        i += 1;
+    }
 
+    let mut line = format!("@PG\tID:{}\tPN:{}", id, PROGRAM_NAME);
+
+    if let Some(previous_id) = ids.last() {
+        line.push_str(&format!("\tPP:{}", previous_id));
+    }
+
+    line.push_str(&format!("\tVN:{}\tCL:{}\n", PROGRAM_VERSION, command_line));
+
+    let mut header = header.to_string();
+
+    if !header.is_empty() && !header.ends_with('\n') {
+        header.push('\n');
+    }
+
+    header.push_str(&line);
+
+    header
+}
+
+/// Sets the sort order (`SO`) of the header (`@HD`) line of a raw SAM header to
+/// unsorted.
+fn set_unsorted(header: &str) -> String {
+    let mut lines = Vec::new();
+
+    for line in header.lines() {
+        if line.starts_with("@HD\t") {
+            let fields: Vec<_> = line
+                .split('\t')
+                .map(|field| {
+                    if field.starts_with("SO:") {
+                        "SO:unsorted"
+                    } else {
+                        field
+                    }
+                })
+                .collect();
+
+            lines.push(fields.join("\t"));
+        } else {
+            lines.push(line.into());
+        }
+    }
+
+    let mut buf = lines.join("\n");
+
+    if header.ends_with('\n') {
+        buf.push('\n');
+    }
+
+    buf
+}
+
+fn read_index(src: &Path, index_src: Option<&Path>) -> anyhow::Result<Option<Index>> {
+    let index_src = match index_src.map(PathBuf::from).or_else(|| index::find(src)) {
+        Some(index_src) => index_src,
+        None => return Ok(None),
+    };
+
+    info!("reading index {}", index_src.display());
+
+    index::read(&index_src)
+        .map(Some)
+        .with_context(|| format!("Could not read {}", index_src.display()))
+}
+
+/// Counts records by reference sequence for each feature level in a single pass.
+#[allow(clippy::too_many_arguments)]
+fn count_bam_records_by_region(
+    bam_src: &Path,
+    index: Arc<Index>,
+    reference_sequences: ReferenceSequences,
+    level_features: Arc<Vec<LevelFeatures>>,
+    library_layout: LibraryLayout,
+    filter: Filter,
+    mode: Mode,
+    ambiguous_mode: Option<AmbiguousMode>,
+    fragment_mode: FragmentMode,
+    strand_specification: StrandSpecification,
+    threads: usize,
+) -> anyhow::Result<Vec<Context>> {
     info!("using {} thread(s)", threads);
 
     let mut runtime = tokio::runtime::Builder::new()
@@ -111,131 +1380,162 @@ where
         .core_threads(threads)
         .build()?;
 
-    let index = Arc::new(index);
     let reference_sequences = Arc::new(reference_sequences);
-    let features = Arc::new(features);
 
-    let ctx = runtime.block_on(async {
+    let ctxs = runtime.block_on(async {
+        let mut ctxs: Vec<_> = level_features.iter().map(|_| Context::default()).collect();
+
         match library_layout {
             LibraryLayout::SingleEnd => {
                 let tasks: Vec<_> = reference_sequences
                     .values()
                     .map(|reference_sequence| {
                         tokio::spawn(count_single_end_records_by_region(
-                            bam_src.as_ref().to_path_buf(),
+                            bam_src.to_path_buf(),
                             index.clone(),
                             reference_sequences.clone(),
                             reference_sequence.name().into(),
-                            features.clone(),
+                            level_features.clone(),
                             filter.clone(),
+                            mode,
+                            ambiguous_mode,
+                            fragment_mode,
                             strand_specification,
                         ))
                     })
                     .collect();
 
-                let mut ctx = Context::default();
-
                 for task in tasks {
-                    let region_ctx = task.await??;
-                    ctx.add(&region_ctx);
+                    let region_ctxs = task.await??;
+                    add_level_contexts(&mut ctxs, &region_ctxs);
                 }
-
-                Ok::<Context, anyhow::Error>(ctx)
             }
             LibraryLayout::PairedEnd => {
                 let tasks: Vec<_> = reference_sequences
                     .values()
                     .map(|reference_sequence| {
                         tokio::spawn(count_paired_end_records_by_region(
-                            bam_src.as_ref().to_path_buf(),
+                            bam_src.to_path_buf(),
                             index.clone(),
                             reference_sequences.clone(),
                             reference_sequence.name().into(),
-                            features.clone(),
+                            level_features.clone(),
                             filter.clone(),
+                            mode,
+                            ambiguous_mode,
+                            fragment_mode,
                             strand_specification,
                         ))
                     })
                     .collect();
 
-                let mut ctx1 = Context::default();
-                let mut pairs = Vec::with_capacity(reference_sequences.len());
+                let mut singletons = Vec::with_capacity(reference_sequences.len());
 
                 for task in tasks {
-                    let (region_ctx, region_pairs) = task.await??;
-                    ctx1.add(&region_ctx);
-                    pairs.push(region_pairs);
+                    let (region_ctxs, region_singletons) = task.await??;
+                    add_level_contexts(&mut ctxs, &region_ctxs);
+                    singletons.push(region_singletons);
                 }
 
-                let records = pairs.into_iter().flat_map(|r| r.into_iter()).map(Ok);
-                let (ctx2, mut pairs) = count_paired_end_records(
-                    records,
-                    &features,
-                    &reference_sequences,
-                    &filter,
-                    strand_specification,
-                )?;
+                // Mates on different reference sequences are paired after all regions are
+                // counted.
+                let records = singletons.into_iter().flatten().map(Ok);
 
-                let singletons = pairs.singletons().map(Ok);
-                let ctx3 = count_paired_end_record_singletons(
-                    singletons,
-                    &features,
+                let singletons_ctxs = count_records_by_level(
+                    Box::new(records),
+                    &level_features,
                     &reference_sequences,
+                    library_layout,
                     &filter,
+                    mode,
+                    ambiguous_mode,
+                    fragment_mode,
                     strand_specification,
                 )?;
 
-                ctx1.add(&ctx2);
-                ctx1.add(&ctx3);
-
-                Ok::<Context, anyhow::Error>(ctx1)
+                add_level_contexts(&mut ctxs, &singletons_ctxs);
             }
         }
+
+        Ok::<Vec<Context>, anyhow::Error>(ctxs)
     })?;
 
-    let writer = File::create(results_dst.as_ref())
-        .map(BufWriter::new)
-        .with_context(|| format!("Could not open {}", results_dst.as_ref().display()))?;
+    Ok(ctxs)
+}
 
-    if let Some(normalization_method) = normalize {
-        let mut value_writer = normalization::Writer::new(writer);
+fn add_level_contexts(ctxs: &mut [Context], other: &[Context]) {
+    for (ctx, other_ctx) in ctxs.iter_mut().zip(other) {
+        ctx.add(other_ctx);
+    }
+}
 
-        match normalization_method {
-            normalization::Method::Fpkm => {
-                info!("calculating fpkms");
-                let fpkms = calculate_fpkms(&ctx.counts, &feature_map)
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
-                info!("writing fpkms");
-                value_writer.write_values(&feature_ids, &fpkms)?;
-            }
-            normalization::Method::Tpm => {
-                info!("calculating tpms");
-                let tpms = calculate_tpms(&ctx.counts, &feature_map)
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
-                info!("writing tpms");
-                value_writer.write_values(&feature_ids, &tpms)?;
-            }
-        }
-    } else {
-        info!("writing counts");
+#[allow(clippy::too_many_arguments)]
+fn count_records(
+    records: alignment::Records,
+    features: &Features,
+    reference_sequences: &ReferenceSequences,
+    library_layout: LibraryLayout,
+    filter: &Filter,
+    mode: Mode,
+    ambiguous_mode: Option<AmbiguousMode>,
+    fragment_mode: FragmentMode,
+    strand_specification: StrandSpecification,
+) -> io::Result<Context> {
+    match library_layout {
+        LibraryLayout::SingleEnd => count_single_end_records(
+            records,
+            features,
+            reference_sequences,
+            filter,
+            mode,
+            ambiguous_mode,
+            fragment_mode,
+            strand_specification,
+        ),
+        LibraryLayout::PairedEnd => {
+            let (mut ctx, mut pairs) = count_paired_end_records(
+                records,
+                features,
+                reference_sequences,
+                filter,
+                mode,
+                ambiguous_mode,
+                fragment_mode,
+                strand_specification,
+            )?;
 
-        let mut count_writer = count::Writer::new(writer);
-        count_writer.write_counts(&feature_ids, &ctx.counts)?;
-        count_writer.write_stats(&ctx)?;
-    }
+            let singletons = pairs.singletons().map(Ok);
+            let singletons_ctx = count_paired_end_record_singletons(
+                singletons,
+                features,
+                reference_sequences,
+                filter,
+                mode,
+                ambiguous_mode,
+                fragment_mode,
+                strand_specification,
+            )?;
 
-    Ok(())
+            ctx.add(&singletons_ctx);
+
+            Ok(ctx)
+        }
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn count_single_end_records_by_region<P>(
     bam_src: P,
-    index: Arc<bai::Index>,
+    index: Arc<Index>,
     reference_sequences: Arc<ReferenceSequences>,
     reference_sequence_name: String,
-    features: Arc<Features>,
+    level_features: Arc<Vec<LevelFeatures>>,
     filter: Filter,
+    mode: Mode,
+    ambiguous_mode: Option<AmbiguousMode>,
+    fragment_mode: FragmentMode,
     strand_specification: StrandSpecification,
-) -> anyhow::Result<Context>
+) -> anyhow::Result<Vec<Context>>
 where
     P: AsRef<Path>,
 {
@@ -243,39 +1543,54 @@ where
         .map(bam::Reader::new)
         .with_context(|| format!("Could not open {}", bam_src.as_ref().display()))?;
 
-    let reference_sequence_len = reference_sequences
-        .get(&reference_sequence_name)
-        .map(|rs| rs.len() as u64)
-        .ok_or_else(|| {
-            io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "invalid reference sequence name",
-            )
-        })?;
-
-    let region = Region::mapped(reference_sequence_name, 1, reference_sequence_len);
-    let query = reader.query(&reference_sequences, &index, &region)?;
-
-    let ctx = count_single_end_records(
-        query,
-        &features,
+    let query = index::query(
+        &mut reader,
         &reference_sequences,
-        &filter,
-        strand_specification,
+        &index,
+        &reference_sequence_name,
     )?;
 
-    Ok(ctx)
+    let mut ctxs: Vec<_> = level_features.iter().map(|_| Context::default()).collect();
+
+    for result in query {
+        let record = result?;
+
+        for (ctx, level) in ctxs.iter_mut().zip(level_features.iter()) {
+            count_single_end_record(
+                ctx,
+                &level.features,
+                level.splicing,
+                &reference_sequences,
+                &filter,
+                mode,
+                ambiguous_mode,
+                fragment_mode,
+                strand_specification,
+                &record,
+            )?;
+        }
+    }
+
+    Ok(ctxs)
 }
 
+/// Counts the record pairs of a reference sequence for each feature level.
+///
+/// This also returns the unpaired records, whose mates may be on another
+/// reference sequence.
+#[allow(clippy::too_many_arguments)]
 async fn count_paired_end_records_by_region<P>(
     bam_src: P,
-    index: Arc<bai::Index>,
+    index: Arc<Index>,
     reference_sequences: Arc<ReferenceSequences>,
     reference_sequence_name: String,
-    features: Arc<Features>,
+    level_features: Arc<Vec<LevelFeatures>>,
     filter: Filter,
+    mode: Mode,
+    ambiguous_mode: Option<AmbiguousMode>,
+    fragment_mode: FragmentMode,
     strand_specification: StrandSpecification,
-) -> anyhow::Result<(Context, Vec<bam::Record>)>
+) -> anyhow::Result<(Vec<Context>, Vec<bam::Record>)>
 where
     P: AsRef<Path>,
 {
@@ -283,26 +1598,225 @@ where
         .map(bam::Reader::new)
         .with_context(|| format!("Could not open {}", bam_src.as_ref().display()))?;
 
-    let reference_sequence_len = reference_sequences
-        .get(&reference_sequence_name)
-        .map(|rs| rs.len() as u64)
-        .ok_or_else(|| {
-            io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "invalid reference sequence name",
-            )
-        })?;
-
-    let region = Region::mapped(reference_sequence_name, 1, reference_sequence_len);
-    let query = reader.query(&reference_sequences, &index, &region)?;
-
-    let (ctx, mut pairs) = count_paired_end_records(
-        query,
-        &features,
+    let query = index::query(
+        &mut reader,
         &reference_sequences,
-        &filter,
-        strand_specification,
+        &index,
+        &reference_sequence_name,
     )?;
 
-    Ok((ctx, pairs.singletons().collect()))
+    let mut ctxs: Vec<_> = level_features.iter().map(|_| Context::default()).collect();
+
+    let primary_only = !filter.with_secondary_records() && !filter.with_supplementary_records();
+    let mut pairs = RecordPairs::new(query, primary_only);
+
+    for pair in &mut pairs {
+        let (r1, r2) = pair?;
+
+        for (ctx, level) in ctxs.iter_mut().zip(level_features.iter()) {
+            count_paired_end_record_pair(
+                ctx,
+                &level.features,
+                level.splicing,
+                &reference_sequences,
+                &filter,
+                mode,
+                ambiguous_mode,
+                fragment_mode,
+                strand_specification,
+                &r1,
+                &r2,
+            )?;
+        }
+    }
+
+    Ok((ctxs, pairs.singletons().collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_level_dst() {
+        assert_eq!(
+            build_level_dst(Path::new("results/counts.tsv"), Level::Gene),
+            PathBuf::from("results/counts.gene.tsv")
+        );
+
+        assert_eq!(
+            build_level_dst(Path::new("counts"), Level::Transcript),
+            PathBuf::from("counts.transcript")
+        );
+    }
+
+    #[test]
+    fn test_write_results_to_with_one_group() -> anyhow::Result<()> {
+        // e.g., a single sample with one read group (`@RG ID:lib1`)
+        let sample_names = [String::from("lib1")];
+        let feature_ids = [String::from("AADAT"), String::from("CLN3")];
+        let feature_map = HashMap::new();
+
+        let mut ctx = Context::default();
+        ctx.counts.insert(String::from("AADAT"), 3.0);
+        ctx.no_feature = 2;
+
+        let ctxs = [ctx];
+
+        let mut buf = Vec::new();
+        write_results_to(
+            &mut buf,
+            &sample_names,
+            &feature_ids,
+            &feature_map,
+            &ctxs,
+            None,
+            DuplicateMode::Keep,
+            true,
+        )?;
+
+        let expected = b"\
+\tlib1
+AADAT\t3
+CLN3\t0
+__no_feature\t2
+__ambiguous\t0
+__too_low_aQual\t0
+__not_aligned\t0
+__alignment_not_unique\t0
+";
+
+        assert_eq!(&buf[..], &expected[..]);
+
+        let mut buf = Vec::new();
+        write_results_to(
+            &mut buf,
+            &sample_names,
+            &feature_ids,
+            &feature_map,
+            &ctxs,
+            None,
+            DuplicateMode::Keep,
+            false,
+        )?;
+
+        assert!(buf.starts_with(b"AADAT\t3\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_results_to_with_bins() -> anyhow::Result<()> {
+        use noodles_sam::header::{ReferenceSequence, ReferenceSequences};
+
+        // 11 bins on sq1, followed by 1 on sq0
+        let reference_sequences: ReferenceSequences = vec![
+            (
+                String::from("sq1"),
+                ReferenceSequence::new(String::from("sq1"), 105),
+            ),
+            (
+                String::from("sq0"),
+                ReferenceSequence::new(String::from("sq0"), 8),
+            ),
+        ]
+        .into_iter()
+        .collect();
+
+        let feature_map = bins::build_features(&reference_sequences, 10);
+        let feature_ids = bins::sorted_ids(&feature_map, &reference_sequences);
+        let ctxs = [Context::default()];
+
+        let mut buf = Vec::new();
+        write_results_to(
+            &mut buf,
+            &[String::from("sample")],
+            &feature_ids,
+            &feature_map,
+            &ctxs,
+            None,
+            DuplicateMode::Keep,
+            false,
+        )?;
+
+        let ids: Vec<_> = buf
+            .split(|&b| b == b'\n')
+            .filter_map(|line| line.split(|&b| b == b'\t').next())
+            .map(|id| String::from_utf8_lossy(id).into_owned())
+            .filter(|id| !id.is_empty() && !id.starts_with("__"))
+            .collect();
+
+        assert_eq!(
+            ids,
+            [
+                "sq1:1-10",
+                "sq1:11-20",
+                "sq1:21-30",
+                "sq1:31-40",
+                "sq1:41-50",
+                "sq1:51-60",
+                "sq1:61-70",
+                "sq1:71-80",
+                "sq1:81-90",
+                "sq1:91-100",
+                "sq1:101-105",
+                "sq0:1-8",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_calculate_duplication_rates() {
+        let counts: HashMap<_, _> = vec![
+            (String::from("AADAT"), 8.0),
+            (String::from("CLN3"), 5.0),
+            (String::from("NEO1"), 0.0),
+        ]
+        .into_iter()
+        .collect();
+
+        let duplicate_counts: HashMap<_, _> =
+            vec![(String::from("AADAT"), 2.0)].into_iter().collect();
+
+        let rates = calculate_duplication_rates(&counts, &duplicate_counts);
+
+        assert_eq!(rates.len(), 2);
+        assert_eq!(rates["AADAT"], 0.25);
+        assert_eq!(rates["CLN3"], 0.0);
+    }
+
+    #[test]
+    fn test_add_program() {
+        let header = "@HD\tVN:1.6\n@SQ\tSN:sq0\tLN:8\n";
+        let expected = format!(
+            "@HD\tVN:1.6\n@SQ\tSN:sq0\tLN:8\n@PG\tID:{name}\tPN:{name}\tVN:{version}\tCL:squab quantify\n",
+            name = PROGRAM_NAME,
+            version = PROGRAM_VERSION,
+        );
+        assert_eq!(add_program(header, "squab quantify"), expected);
+
+        let header = format!(
+            "@PG\tID:bwa\tPN:bwa\n@PG\tID:{}\tPN:{}\tPP:bwa",
+            PROGRAM_NAME, PROGRAM_NAME
+        );
+        let expected = format!(
+            "{header}\n@PG\tID:{name}.1\tPN:{name}\tPP:{name}\tVN:{version}\tCL:squab quantify\n",
+            header = header,
+            name = PROGRAM_NAME,
+            version = PROGRAM_VERSION,
+        );
+        assert_eq!(add_program(&header, "squab quantify"), expected);
+    }
+
+    #[test]
+    fn test_set_unsorted() {
+        let header = "@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:sq0\tLN:8\n";
+        let expected = "@HD\tVN:1.6\tSO:unsorted\n@SQ\tSN:sq0\tLN:8\n";
+        assert_eq!(set_unsorted(header), expected);
+
+        let header = "@HD\tVN:1.6\n@SQ\tSN:sq0\tLN:8";
+        assert_eq!(set_unsorted(header), header);
+    }
 }
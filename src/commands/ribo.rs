@@ -0,0 +1,203 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    iter,
+    path::Path,
+};
+
+use anyhow::Context as AnyhowContext;
+use log::info;
+use noodles_gff::record::Strand;
+
+use crate::{
+    alignment, annotations, build_interval_trees,
+    count::{find, log_filtered, Filter, Mode},
+    coverage::Transcript,
+    detect,
+    hierarchy::Level,
+    is_reverse,
+    ribo::{self, five_prime_soft_clip_len, p_site, read_len, read_offsets, Summary},
+    Context, Features, MatchIntervals, StrandSpecificationOption,
+};
+
+#[allow(clippy::too_many_arguments)]
+pub fn ribo<P, Q, R>(
+    src: P,
+    reference_src: Option<&Path>,
+    annotations_src: Q,
+    offsets_src: R,
+    feature_type: &str,
+    level: Level,
+    filter: &Filter,
+    strand_specification_option: StrandSpecificationOption,
+) -> anyhow::Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    R: AsRef<Path>,
+{
+    let src = src.as_ref();
+    let annotations_src = annotations_src.as_ref();
+    let offsets_src = offsets_src.as_ref();
+
+    let offsets = File::open(offsets_src)
+        .map(BufReader::new)
+        .and_then(|mut reader| read_offsets(&mut reader))
+        .with_context(|| format!("Could not read {}", offsets_src.display()))?;
+
+    info!("read {} P-site offsets", offsets.len());
+
+    let hierarchy = annotations::read_hierarchy(annotations_src, None, feature_type)
+        .with_context(|| format!("Could not read {}", annotations_src.display()))?;
+
+    let feature_map = hierarchy.features(level);
+    let (features, names) = build_interval_trees(&feature_map);
+
+    let transcript_map = hierarchy.features(Level::Transcript);
+    let (transcript_features, _) = build_interval_trees(&transcript_map);
+
+    // The phase of a transcript is the phase of its 5'-most segment.
+    let transcripts: HashMap<_, _> = transcript_map
+        .iter()
+        .filter_map(|(id, segments)| {
+            let transcript = Transcript::new(segments)?;
+
+            let first_segment = if transcript.strand() == Strand::Reverse {
+                segments.iter().max_by_key(|s| s.end())
+            } else {
+                segments.iter().min_by_key(|s| s.start())
+            }?;
+
+            let phase = hierarchy.phase(first_segment).unwrap_or(0);
+
+            Some((id.as_str(), (transcript, phase)))
+        })
+        .collect();
+
+    let (_, header, mut records) = alignment::open(src, reference_src)
+        .with_context(|| format!("Could not open {}", src.display()))?;
+
+    let reference_sequences = header.reference_sequences();
+
+    let detection_records = detect::read_detection_records(&mut records)?;
+
+    let (_, strand_specification) = detect::detect_library_type(
+        detection_records.iter().cloned().map(Ok),
+        reference_sequences,
+        &features,
+        strand_specification_option,
+    )?;
+
+    info!("counting P-sites");
+
+    let records = detection_records.into_iter().map(Ok).chain(records);
+    let mut summary = Summary::default();
+    let mut ctx = Context::default();
+
+    for result in records {
+        let record = result?;
+
+        if filter.filter(&mut ctx, &record)? {
+            continue;
+        }
+
+        let reference_sequence_name = match *record.reference_sequence_id() {
+            Some(id) => match reference_sequences.get_index(id as usize) {
+                Some((name, _)) => name,
+                None => continue,
+            },
+            None => continue,
+        };
+
+        let cigar = record.cigar();
+        let start = i32::from(record.position()) as u64;
+        let intervals: Vec<_> = MatchIntervals::new(&cigar, start).collect();
+
+        let is_reverse_complemented = record.flags().is_reverse_complemented();
+        let soft_clip_len = u64::from(five_prime_soft_clip_len(&cigar, is_reverse_complemented));
+
+        // A P-site in the soft clipped bases has no position.
+        let position = offsets
+            .get(&read_len(&cigar))
+            .and_then(|&offset| offset.checked_sub(soft_clip_len))
+            .and_then(|offset| p_site(&intervals, is_reverse_complemented, offset));
+
+        let position = match position {
+            Some(p) => p,
+            None => {
+                summary.no_offset += 1;
+                continue;
+            }
+        };
+
+        let is_reverse = is_reverse(&record, strand_specification);
+
+        let find_ids = |features: &Features| {
+            features
+                .get(reference_sequence_name)
+                .and_then(|tree| {
+                    find(
+                        tree,
+                        iter::once(position..=position),
+                        Mode::Union,
+                        strand_specification,
+                        is_reverse,
+                    )
+                })
+                .unwrap_or_default()
+        };
+
+        let ids = find_ids(&features);
+
+        let id = match ids.len() {
+            0 => {
+                summary.no_feature += 1;
+                continue;
+            }
+            1 => ids.into_iter().next().unwrap(),
+            _ => {
+                summary.ambiguous += 1;
+                continue;
+            }
+        };
+
+        // The frame is only defined when the transcripts that contain the
+        // P-site agree.
+        let frames: HashSet<_> = find_ids(&transcript_features)
+            .iter()
+            .filter_map(|transcript_id| transcripts.get(transcript_id.as_str()))
+            .filter_map(|(transcript, phase)| {
+                transcript
+                    .position(position)
+                    .map(|p| ribo::frame(p, *phase))
+            })
+            .collect();
+
+        let frame = if frames.len() == 1 {
+            frames.into_iter().next()
+        } else {
+            None
+        };
+
+        summary.counts.entry(id).or_default().add(frame);
+    }
+
+    info!(
+        "skipped {} records without a P-site offset, {} P-sites with no feature, and {} ambiguous",
+        summary.no_offset, summary.no_feature, summary.ambiguous
+    );
+
+    log_filtered(&ctx);
+
+    let mut ids: Vec<_> = names.into_iter().collect();
+    ids.sort();
+
+    let stdout = io::stdout();
+    let handle = stdout.lock();
+    let mut writer = BufWriter::new(handle);
+
+    ribo::write_counts(&mut writer, &ids, &summary).context("Could not write to stdout")?;
+
+    Ok(())
+}
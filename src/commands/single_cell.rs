@@ -0,0 +1,180 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use anyhow::Context as AnyhowContext;
+use log::info;
+
+use crate::{
+    alignment::{self, data::get_string_field},
+    annotations, build_interval_trees,
+    count::{find, get_tree, log_filtered, Filter, Mode},
+    detect, is_reverse,
+    single_cell::{self, read_whitelist, Matrix},
+    Context, MatchIntervals, StrandSpecificationOption,
+};
+
+#[derive(Default)]
+struct Summary {
+    no_barcode: u64,
+    no_umi: u64,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn single_cell<P, Q, D>(
+    src: P,
+    reference_src: Option<&Path>,
+    annotations_src: Q,
+    annotations_format: Option<annotations::Format>,
+    whitelist_src: Option<&Path>,
+    feature_type: &str,
+    id: &str,
+    barcode_tag: &[u8; 2],
+    umi_tag: &[u8; 2],
+    filter: &Filter,
+    mode: Mode,
+    strand_specification_option: StrandSpecificationOption,
+    dst: D,
+) -> anyhow::Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    D: AsRef<Path>,
+{
+    let src = src.as_ref();
+    let annotations_src = annotations_src.as_ref();
+    let dst = dst.as_ref();
+
+    let whitelist = match whitelist_src {
+        Some(whitelist_src) => {
+            let whitelist = File::open(whitelist_src)
+                .map(BufReader::new)
+                .and_then(|mut reader| read_whitelist(&mut reader))
+                .with_context(|| format!("Could not read {}", whitelist_src.display()))?;
+
+            info!("read {} whitelisted barcodes", whitelist.len());
+
+            Some(whitelist)
+        }
+        None => None,
+    };
+
+    let feature_map =
+        annotations::read_features(annotations_src, annotations_format, feature_type, id)?;
+    let (features, names) = build_interval_trees(&feature_map);
+
+    let (_, header, mut records) = alignment::open(src, reference_src)
+        .with_context(|| format!("Could not open {}", src.display()))?;
+
+    let reference_sequences = header.reference_sequences();
+
+    let detection_records = detect::read_detection_records(&mut records)?;
+
+    let (_, strand_specification) = detect::detect_library_type(
+        detection_records.iter().cloned().map(Ok),
+        reference_sequences,
+        &features,
+        strand_specification_option,
+    )?;
+
+    info!("counting features by cell");
+
+    let records = detection_records.into_iter().map(Ok).chain(records);
+    let mut matrix = Matrix::default();
+    let mut summary = Summary::default();
+    let mut ctx = Context::default();
+
+    for result in records {
+        let record = result?;
+
+        if filter.filter(&mut ctx, &record)? {
+            continue;
+        }
+
+        let barcode = match get_string_field(&record, barcode_tag)? {
+            Some(b) if whitelist.as_ref().map(|w| w.contains(&b)).unwrap_or(true) => b,
+            _ => {
+                summary.no_barcode += 1;
+                continue;
+            }
+        };
+
+        let umi = match get_string_field(&record, umi_tag)? {
+            Some(u) => u,
+            None => {
+                summary.no_umi += 1;
+                continue;
+            }
+        };
+
+        let tree = match get_tree(
+            &mut ctx,
+            &features,
+            reference_sequences,
+            record.reference_sequence_id(),
+        )? {
+            Some(t) => t,
+            None => continue,
+        };
+
+        let is_reverse = is_reverse(&record, strand_specification);
+
+        let cigar = record.cigar();
+        let start = i32::from(record.position()) as u64;
+        let intervals = MatchIntervals::new(&cigar, start);
+
+        let ids = find(tree, intervals, mode, strand_specification, is_reverse).unwrap_or_default();
+
+        match ids.len() {
+            0 => ctx.no_feature += 1,
+            1 => {
+                for id in ids {
+                    matrix.add(&barcode, &id, &umi);
+                }
+            }
+            _ => ctx.ambiguous += 1,
+        }
+    }
+
+    info!(
+        "counted {} UMIs in {} cells",
+        matrix.umi_count(),
+        matrix.barcodes().len()
+    );
+
+    info!(
+        "skipped {} records without a cell barcode, {} without a UMI, {} with no feature, and {} ambiguous",
+        summary.no_barcode, summary.no_umi, ctx.no_feature, ctx.ambiguous
+    );
+
+    log_filtered(&ctx);
+
+    let mut ids: Vec<_> = names.into_iter().collect();
+    ids.sort();
+
+    let barcodes = matrix.barcodes();
+
+    fs::create_dir_all(dst).with_context(|| format!("Could not create {}", dst.display()))?;
+
+    let matrix_dst = dst.join("matrix.mtx");
+    File::create(&matrix_dst)
+        .map(BufWriter::new)
+        .and_then(|mut writer| single_cell::write_matrix(&mut writer, &ids, &barcodes, &matrix))
+        .with_context(|| format!("Could not write {}", matrix_dst.display()))?;
+
+    let barcodes_dst = dst.join("barcodes.tsv");
+    File::create(&barcodes_dst)
+        .map(BufWriter::new)
+        .and_then(|mut writer| single_cell::write_names(&mut writer, &barcodes))
+        .with_context(|| format!("Could not write {}", barcodes_dst.display()))?;
+
+    let features_dst = dst.join("features.tsv");
+    File::create(&features_dst)
+        .map(BufWriter::new)
+        .and_then(|mut writer| single_cell::write_names(&mut writer, &ids))
+        .with_context(|| format!("Could not write {}", features_dst.display()))?;
+
+    Ok(())
+}
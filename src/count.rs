@@ -1,26 +1,53 @@
+mod ambiguous_mode;
+mod assignment;
 mod context;
+mod duplicate_mode;
 mod filter;
+mod fragment_mode;
+mod mode;
+mod nonunique_mode;
+mod read_position;
 mod reader;
+pub mod splicing;
+mod umi;
+mod umi_source;
 mod writer;
 
-pub use self::{context::Context, filter::Filter, reader::Reader, writer::Writer};
-
-use std::{collections::HashSet, convert::TryFrom, io};
+pub use self::{
+    ambiguous_mode::AmbiguousMode,
+    assignment::assignment,
+    context::Context,
+    duplicate_mode::DuplicateMode,
+    filter::{is_nonunique_record, log_filtered, Filter},
+    fragment_mode::FragmentMode,
+    mode::Mode,
+    nonunique_mode::NonuniqueMode,
+    read_position::ReadPosition,
+    reader::Reader,
+    umi_source::UmiSource,
+    writer::Writer,
+};
+
+use std::{collections::HashSet, convert::TryFrom, io, ops::RangeInclusive};
 
 use interval_tree::IntervalTree;
 use noodles_bam as bam;
 use noodles_gff as gff;
 use noodles_sam::{self as sam, header::ReferenceSequences};
 
-use crate::{Entry, Features, MatchIntervals, PairPosition, RecordPairs, StrandSpecification};
+use crate::{Entry, Features, PairPosition, RecordPairs, StrandSpecification};
 
-use self::context::Event;
+use self::{context::Event, filter::alignment_hit_count, umi::UmiPosition};
 
+#[allow(clippy::too_many_arguments)]
 pub fn count_single_end_records<I>(
     records: I,
     features: &Features,
     references: &ReferenceSequences,
     filter: &Filter,
+    mode: Mode,
+    ambiguous_mode: Option<AmbiguousMode>,
+    fragment_mode: FragmentMode,
     strand_specification: StrandSpecification,
 ) -> io::Result<Context>
 where
@@ -34,8 +61,12 @@ where
         count_single_end_record(
             &mut ctx,
             features,
+            false,
             references,
             filter,
+            mode,
+            ambiguous_mode,
+            fragment_mode,
             strand_specification,
             &record,
         )?;
@@ -44,11 +75,16 @@ where
     Ok(ctx)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn count_single_end_record(
     ctx: &mut Context,
     features: &Features,
+    splicing: bool,
     reference_sequences: &ReferenceSequences,
     filter: &Filter,
+    mode: Mode,
+    ambiguous_mode: Option<AmbiguousMode>,
+    fragment_mode: FragmentMode,
     strand_specification: StrandSpecification,
     record: &bam::Record,
 ) -> io::Result<()> {
@@ -56,8 +92,6 @@ pub fn count_single_end_record(
         return Ok(());
     }
 
-    let cigar = record.cigar();
-    let start = i32::from(record.position()) as u64;
     let flags = record.flags();
 
     let is_reverse = match strand_specification {
@@ -65,7 +99,7 @@ pub fn count_single_end_record(
         _ => flags.is_reverse_complemented(),
     };
 
-    let intervals = MatchIntervals::new(&cigar, start);
+    let intervals = fragment_mode.intervals(&[record]);
 
     let tree = match get_tree(
         ctx,
@@ -77,18 +111,28 @@ pub fn count_single_end_record(
         None => return Ok(()),
     };
 
-    let set = find(tree, intervals, strand_specification, is_reverse);
-
-    update_intersections(ctx, set);
+    let set = find(tree, intervals, mode, strand_specification, is_reverse);
 
-    Ok(())
+    update_record_intersections(
+        ctx,
+        filter,
+        ambiguous_mode,
+        splicing,
+        record,
+        record.flags().is_duplicate(),
+        set.unwrap_or_default(),
+    )
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn count_paired_end_records<I>(
     records: I,
     features: &Features,
     reference_sequences: &ReferenceSequences,
     filter: &Filter,
+    mode: Mode,
+    ambiguous_mode: Option<AmbiguousMode>,
+    fragment_mode: FragmentMode,
     strand_specification: StrandSpecification,
 ) -> io::Result<(Context, RecordPairs<I>)>
 where
@@ -102,12 +146,88 @@ where
     for pair in &mut pairs {
         let (r1, r2) = pair?;
 
-        if filter.filter_pair(&mut ctx, &r1, &r2)? {
-            continue;
-        }
+        count_paired_end_record_pair(
+            &mut ctx,
+            features,
+            false,
+            reference_sequences,
+            filter,
+            mode,
+            ambiguous_mode,
+            fragment_mode,
+            strand_specification,
+            &r1,
+            &r2,
+        )?;
+    }
+
+    Ok((ctx, pairs))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn count_paired_end_record_singletons<I>(
+    records: I,
+    features: &Features,
+    reference_sequences: &ReferenceSequences,
+    filter: &Filter,
+    mode: Mode,
+    ambiguous_mode: Option<AmbiguousMode>,
+    fragment_mode: FragmentMode,
+    strand_specification: StrandSpecification,
+) -> io::Result<Context>
+where
+    I: Iterator<Item = io::Result<bam::Record>>,
+{
+    let mut ctx = Context::default();
+
+    for result in records {
+        let record = result?;
+
+        count_paired_end_record_singleton(
+            &mut ctx,
+            features,
+            false,
+            reference_sequences,
+            filter,
+            mode,
+            ambiguous_mode,
+            fragment_mode,
+            strand_specification,
+            &record,
+        )?;
+    }
+
+    Ok(ctx)
+}
 
-        let cigar = r1.cigar();
-        let start = i32::from(r1.position()) as u64;
+/// Counts a pair of records as a single fragment.
+#[allow(clippy::too_many_arguments)]
+pub fn count_paired_end_record_pair(
+    ctx: &mut Context,
+    features: &Features,
+    splicing: bool,
+    reference_sequences: &ReferenceSequences,
+    filter: &Filter,
+    mode: Mode,
+    ambiguous_mode: Option<AmbiguousMode>,
+    fragment_mode: FragmentMode,
+    strand_specification: StrandSpecification,
+    r1: &bam::Record,
+    r2: &bam::Record,
+) -> io::Result<()> {
+    if filter.filter_pair(ctx, r1, r2)? {
+        return Ok(());
+    }
+
+    // As in the duplicate filter, a pair is a duplicate if either mate is
+    // marked.
+    let is_duplicate = r1.flags().is_duplicate() || r2.flags().is_duplicate();
+
+    // The positions of a fragment are only defined when its mates are on the
+    // same reference sequence. Otherwise, the mates are intersected separately.
+    if fragment_mode != FragmentMode::Alignment
+        && r1.reference_sequence_id() == r2.reference_sequence_id()
+    {
         let f1 = r1.flags();
 
         let is_reverse = match strand_specification {
@@ -115,132 +235,231 @@ where
             _ => f1.is_reverse_complemented(),
         };
 
-        let intervals = MatchIntervals::new(&cigar, start);
+        let intervals = fragment_mode.intervals(&[r1, r2]);
 
         let tree = match get_tree(
-            &mut ctx,
+            ctx,
             features,
             reference_sequences,
             r1.reference_sequence_id(),
         )? {
             Some(t) => t,
-            None => continue,
+            None => return Ok(()),
         };
 
-        let mut set = find(tree, intervals, strand_specification, is_reverse);
+        let set = find(tree, intervals, mode, strand_specification, is_reverse);
+
+        return update_record_intersections(
+            ctx,
+            filter,
+            ambiguous_mode,
+            splicing,
+            r1,
+            is_duplicate,
+            set.unwrap_or_default(),
+        );
+    }
 
-        let cigar = r2.cigar();
-        let start = i32::from(r2.position()) as u64;
-        let f2 = r2.flags();
+    let f1 = r1.flags();
 
-        let is_reverse = match strand_specification {
-            StrandSpecification::Reverse => f2.is_reverse_complemented(),
-            _ => !f2.is_reverse_complemented(),
-        };
+    let is_reverse = match strand_specification {
+        StrandSpecification::Reverse => !f1.is_reverse_complemented(),
+        _ => f1.is_reverse_complemented(),
+    };
 
-        let intervals = MatchIntervals::new(&cigar, start);
+    // A read position is on a single mate, so it is intersected with the features
+    // of that mate's reference sequence.
+    if let FragmentMode::ReadPosition(read_position, _) = fragment_mode {
+        let record = read_position.record(&[r1, r2]).unwrap_or(r1);
+        let intervals = fragment_mode.intervals(&[r1, r2]);
 
         let tree = match get_tree(
-            &mut ctx,
+            ctx,
             features,
             reference_sequences,
-            r2.reference_sequence_id(),
+            record.reference_sequence_id(),
         )? {
             Some(t) => t,
-            None => continue,
+            None => return Ok(()),
         };
 
-        let set2 = find(tree, intervals, strand_specification, is_reverse);
-
-        set.extend(set2.into_iter());
+        let set = find(tree, intervals, mode, strand_specification, is_reverse);
 
-        update_intersections(&mut ctx, set);
+        return update_record_intersections(
+            ctx,
+            filter,
+            ambiguous_mode,
+            splicing,
+            r1,
+            is_duplicate,
+            set.unwrap_or_default(),
+        );
     }
 
-    Ok((ctx, pairs))
+    // Cut sites are defined for each mate, but a span is not, so it falls back
+    // to the aligned blocks.
+    let mate_fragment_mode = match fragment_mode {
+        FragmentMode::CutSites => FragmentMode::CutSites,
+        _ => FragmentMode::Alignment,
+    };
+
+    let intervals = mate_fragment_mode.intervals(&[r1]);
+
+    let tree = match get_tree(
+        ctx,
+        features,
+        reference_sequences,
+        r1.reference_sequence_id(),
+    )? {
+        Some(t) => t,
+        None => return Ok(()),
+    };
+
+    let set1 = find(tree, intervals, mode, strand_specification, is_reverse);
+
+    let f2 = r2.flags();
+
+    let is_reverse = match strand_specification {
+        StrandSpecification::Reverse => f2.is_reverse_complemented(),
+        _ => !f2.is_reverse_complemented(),
+    };
+
+    let intervals = mate_fragment_mode.intervals(&[r2]);
+
+    let tree = match get_tree(
+        ctx,
+        features,
+        reference_sequences,
+        r2.reference_sequence_id(),
+    )? {
+        Some(t) => t,
+        None => return Ok(()),
+    };
+
+    let set2 = find(tree, intervals, mode, strand_specification, is_reverse);
+
+    let set = combine(mode, set1, set2);
+
+    update_record_intersections(
+        ctx,
+        filter,
+        ambiguous_mode,
+        splicing,
+        r1,
+        is_duplicate,
+        set.unwrap_or_default(),
+    )
 }
 
-pub fn count_paired_end_record_singletons<I>(
-    records: I,
+/// Counts a paired end record whose mate was not found.
+#[allow(clippy::too_many_arguments)]
+pub fn count_paired_end_record_singleton(
+    ctx: &mut Context,
     features: &Features,
+    splicing: bool,
     reference_sequences: &ReferenceSequences,
     filter: &Filter,
+    mode: Mode,
+    ambiguous_mode: Option<AmbiguousMode>,
+    fragment_mode: FragmentMode,
     strand_specification: StrandSpecification,
-) -> io::Result<Context>
-where
-    I: Iterator<Item = io::Result<bam::Record>>,
-{
-    let mut ctx = Context::default();
+    record: &bam::Record,
+) -> io::Result<()> {
+    if filter.filter(ctx, record)? {
+        return Ok(());
+    }
 
-    for result in records {
-        let record = result?;
+    let flags = record.flags();
 
-        if filter.filter(&mut ctx, &record)? {
-            continue;
+    let is_reverse = match PairPosition::try_from(record) {
+        Ok(PairPosition::First) => match strand_specification {
+            StrandSpecification::Reverse => !flags.is_reverse_complemented(),
+            _ => flags.is_reverse_complemented(),
+        },
+        Ok(PairPosition::Second) => match strand_specification {
+            StrandSpecification::Reverse => flags.is_reverse_complemented(),
+            _ => !flags.is_reverse_complemented(),
+        },
+        Err(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "record is neither read 1 nor 2",
+            ))
         }
+    };
 
-        let cigar = record.cigar();
-        let start = i32::from(record.position()) as u64;
-        let flags = record.flags();
-
-        let is_reverse = match PairPosition::try_from(&record) {
-            Ok(PairPosition::First) => match strand_specification {
-                StrandSpecification::Reverse => !flags.is_reverse_complemented(),
-                _ => flags.is_reverse_complemented(),
-            },
-            Ok(PairPosition::Second) => match strand_specification {
-                StrandSpecification::Reverse => flags.is_reverse_complemented(),
-                _ => !flags.is_reverse_complemented(),
-            },
-            Err(_) => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "record is neither read 1 nor 2",
-                ))
-            }
-        };
-
-        let intervals = MatchIntervals::new(&cigar, start);
-
-        let tree = match get_tree(
-            &mut ctx,
-            features,
-            reference_sequences,
-            record.reference_sequence_id(),
-        )? {
-            Some(t) => t,
-            None => continue,
-        };
+    let intervals = fragment_mode.intervals(&[record]);
 
-        let set = find(tree, intervals, strand_specification, is_reverse);
+    let tree = match get_tree(
+        ctx,
+        features,
+        reference_sequences,
+        record.reference_sequence_id(),
+    )? {
+        Some(t) => t,
+        None => return Ok(()),
+    };
 
-        update_intersections(&mut ctx, set);
-    }
+    let set = find(tree, intervals, mode, strand_specification, is_reverse);
 
-    Ok(ctx)
+    update_record_intersections(
+        ctx,
+        filter,
+        ambiguous_mode,
+        splicing,
+        record,
+        record.flags().is_duplicate(),
+        set.unwrap_or_default(),
+    )
 }
 
-fn find(
+/// Finds the features that intersect the given match intervals.
+///
+/// This returns `None` when no sets were combined, e.g., no positions have
+/// features in intersection-nonempty mode.
+pub fn find<I>(
     tree: &IntervalTree<u64, Entry>,
-    intervals: MatchIntervals,
+    intervals: I,
+    mode: Mode,
     strand_specification: StrandSpecification,
     is_reverse: bool,
-) -> HashSet<String> {
-    let mut set = HashSet::new();
+) -> Option<HashSet<String>>
+where
+    I: IntoIterator<Item = RangeInclusive<u64>>,
+{
+    let mut set = None;
 
     for interval in intervals {
-        for entry in tree.find(interval.clone()) {
-            let (gene_name, strand) = entry.get();
-
-            match strand_specification {
-                StrandSpecification::None => {
-                    set.insert(gene_name.to_string());
+        let entries: Vec<_> = tree
+            .find(interval.clone())
+            .filter(|entry| {
+                let (_, strand) = entry.get();
+
+                match strand_specification {
+                    StrandSpecification::None => true,
+                    StrandSpecification::Forward | StrandSpecification::Reverse => {
+                        (strand == &gff::record::Strand::Reverse && is_reverse)
+                            || (strand == &gff::record::Strand::Forward && !is_reverse)
+                    }
+                }
+            })
+            .map(|entry| (entry.key().clone(), entry.get().0.clone()))
+            .collect();
+
+        match mode {
+            Mode::Union => {
+                let names = entries.into_iter().map(|(_, name)| name);
+                set.get_or_insert_with(HashSet::new).extend(names);
+            }
+            Mode::IntersectionStrict => {
+                for step in steps(&interval, &entries) {
+                    set = combine(mode, set, Some(step));
                 }
-                StrandSpecification::Forward | StrandSpecification::Reverse => {
-                    if (strand == &gff::record::Strand::Reverse && is_reverse)
-                        || (strand == &gff::record::Strand::Forward && !is_reverse)
-                    {
-                        set.insert(gene_name.to_string());
+            }
+            Mode::IntersectionNonempty => {
+                for step in steps(&interval, &entries) {
+                    if !step.is_empty() {
+                        set = combine(mode, set, Some(step));
                     }
                 }
             }
@@ -250,6 +469,57 @@ fn find(
     set
 }
 
+/// Splits an interval into the sets of features at each position.
+///
+/// Consecutive positions with the same features are grouped into a single step.
+fn steps(
+    interval: &RangeInclusive<u64>,
+    entries: &[(RangeInclusive<u64>, String)],
+) -> Vec<HashSet<String>> {
+    let mut breakpoints = vec![*interval.start()];
+
+    for (key, _) in entries {
+        if key.start() > interval.start() {
+            breakpoints.push(*key.start());
+        }
+
+        if key.end() < interval.end() {
+            breakpoints.push(key.end() + 1);
+        }
+    }
+
+    breakpoints.sort_unstable();
+    breakpoints.dedup();
+
+    breakpoints
+        .into_iter()
+        .map(|position| {
+            entries
+                .iter()
+                .filter(|(key, _)| key.contains(&position))
+                .map(|(_, name)| name.clone())
+                .collect()
+        })
+        .collect()
+}
+
+fn combine(
+    mode: Mode,
+    a: Option<HashSet<String>>,
+    b: Option<HashSet<String>>,
+) -> Option<HashSet<String>> {
+    match (a, b) {
+        (Some(a), Some(b)) => match mode {
+            Mode::Union => Some(a.union(&b).cloned().collect()),
+            Mode::IntersectionStrict | Mode::IntersectionNonempty => {
+                Some(a.intersection(&b).cloned().collect())
+            }
+        },
+        (Some(set), None) | (None, Some(set)) => Some(set),
+        (None, None) => None,
+    }
+}
+
 fn get_reference_sequence<'a>(
     reference_sequences: &'a ReferenceSequences,
     reference_sequence_id: bam::record::ReferenceSequenceId,
@@ -264,15 +534,133 @@ fn get_reference_sequence<'a>(
         })
 }
 
-fn update_intersections(ctx: &mut Context, intersections: HashSet<String>) {
+/// Counts the set of features a record intersects.
+///
+/// With `splicing`, the features are splicing features (see
+/// `splicing::build_features`), and the set is first resolved to the record's
+/// splicing kind. `is_duplicate` is whether the record (or pair) is marked as a
+/// duplicate.
+fn update_record_intersections(
+    ctx: &mut Context,
+    filter: &Filter,
+    ambiguous_mode: Option<AmbiguousMode>,
+    splicing: bool,
+    record: &bam::Record,
+    is_duplicate: bool,
+    intersections: HashSet<String>,
+) -> io::Result<()> {
+    let intersections = if splicing {
+        splicing::resolve(intersections)
+    } else {
+        intersections
+    };
+
+    let mut weight = None;
+
+    if let Some(nonunique_mode) = filter.nonunique_mode() {
+        if let Some(hit_count) = alignment_hit_count(record)?.filter(|&n| n > 1) {
+            match nonunique_mode {
+                NonuniqueMode::Uniform => {
+                    weight = Some(1.0 / f64::from(hit_count));
+                }
+                NonuniqueMode::Em => {
+                    let read_name = record.read_name().to_vec();
+
+                    // Reads with no feature or that are ambiguous are counted
+                    // once when the hits are distributed. With an ambiguous
+                    // mode, the features of an ambiguous record are hits.
+                    if intersections.is_empty() {
+                        ctx.add_event(Event::NonuniqueNoFeature(read_name));
+                        return Ok(());
+                    } else if intersections.len() > 1 && ambiguous_mode.is_none() {
+                        ctx.add_event(Event::NonuniqueAmbiguous(read_name));
+                        return Ok(());
+                    }
+
+                    for name in intersections {
+                        ctx.add_event(Event::NonuniqueHit(read_name.clone(), name));
+                    }
+
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    // Only records assigned to a single feature are deduplicated.
+    if let Some(umi_source) = filter.umi_source() {
+        if weight.is_none() && intersections.len() == 1 {
+            let umi = umi_source.umi(record)?.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "missing UMI: {}",
+                        String::from_utf8_lossy(record.read_name())
+                    ),
+                )
+            })?;
+
+            let position = UmiPosition::from_record(record);
+
+            for name in intersections {
+                ctx.add_event(Event::UmiHit(name, position, umi.clone()));
+            }
+
+            return Ok(());
+        }
+    }
+
+    if is_duplicate {
+        // Duplicates are counted as any other record and also tracked by
+        // feature.
+        let mut record_ctx = Context::default();
+        update_intersections(&mut record_ctx, ambiguous_mode, intersections, weight);
+
+        for (name, &count) in &record_ctx.counts {
+            ctx.add_event(Event::DuplicateHit(name.clone(), count));
+        }
+
+        ctx.add(&record_ctx);
+    } else {
+        update_intersections(ctx, ambiguous_mode, intersections, weight);
+    }
+
+    Ok(())
+}
+
+/// Adds the events for the set of features a record intersects.
+///
+/// `weight` is the fraction of the record to assign. When `None`, the whole
+/// record is assigned.
+fn update_intersections(
+    ctx: &mut Context,
+    ambiguous_mode: Option<AmbiguousMode>,
+    intersections: HashSet<String>,
+    weight: Option<f64>,
+) {
     if intersections.is_empty() {
         ctx.add_event(Event::NoFeature);
     } else if intersections.len() == 1 {
         for name in intersections {
-            ctx.add_event(Event::Hit(name));
+            match weight {
+                Some(w) => ctx.add_event(Event::WeightedHit(name, w)),
+                None => ctx.add_event(Event::Hit(name)),
+            }
         }
     } else if intersections.len() > 1 {
         ctx.add_event(Event::Ambiguous);
+
+        let weight = weight.unwrap_or(1.0);
+
+        let feature_weight = match ambiguous_mode {
+            Some(AmbiguousMode::Fractional) => weight / intersections.len() as f64,
+            Some(AmbiguousMode::All) => weight,
+            None => return,
+        };
+
+        for name in intersections {
+            ctx.add_event(Event::WeightedHit(name, feature_weight));
+        }
     }
 }
 
@@ -296,6 +684,8 @@ pub fn get_tree<'t>(
 
 #[cfg(test)]
 mod tests {
+    use crate::MatchIntervals;
+
     use super::*;
 
     fn build_reference_sequences() -> ReferenceSequences {
@@ -339,4 +729,130 @@ mod tests {
 
         Ok(())
     }
+
+    fn build_tree() -> IntervalTree<u64, Entry> {
+        let mut tree = IntervalTree::default();
+        tree.insert(
+            1..=10,
+            (String::from("gene0"), gff::record::Strand::Forward),
+        );
+        tree.insert(
+            6..=15,
+            (String::from("gene1"), gff::record::Strand::Forward),
+        );
+        tree
+    }
+
+    fn build_raw_cigar(len: u32) -> Vec<u8> {
+        use bam::record::cigar;
+        use sam::record::cigar::op::Kind;
+
+        u32::from(cigar::Op::new(Kind::Match, len))
+            .to_le_bytes()
+            .to_vec()
+    }
+
+    fn find_names(mode: Mode, start: u64, len: u32) -> Option<Vec<String>> {
+        let tree = build_tree();
+        let raw_cigar = build_raw_cigar(len);
+        let cigar = bam::record::Cigar::new(&raw_cigar);
+        let intervals = MatchIntervals::new(&cigar, start);
+
+        find(&tree, intervals, mode, StrandSpecification::None, false).map(|set| {
+            let mut names: Vec<_> = set.into_iter().collect();
+            names.sort();
+            names
+        })
+    }
+
+    #[test]
+    fn test_find() {
+        let names = find_names(Mode::Union, 3, 8);
+        assert_eq!(
+            names,
+            Some(vec![String::from("gene0"), String::from("gene1")])
+        );
+        let names = find_names(Mode::IntersectionStrict, 3, 8);
+        assert_eq!(names, Some(vec![String::from("gene0")]));
+        let names = find_names(Mode::IntersectionNonempty, 3, 8);
+        assert_eq!(names, Some(vec![String::from("gene0")]));
+
+        let names = find_names(Mode::Union, 11, 10);
+        assert_eq!(names, Some(vec![String::from("gene1")]));
+        let names = find_names(Mode::IntersectionStrict, 11, 10);
+        assert_eq!(names, Some(Vec::new()));
+        let names = find_names(Mode::IntersectionNonempty, 11, 10);
+        assert_eq!(names, Some(vec![String::from("gene1")]));
+
+        let names = find_names(Mode::Union, 21, 5);
+        assert_eq!(names, Some(Vec::new()));
+        let names = find_names(Mode::IntersectionStrict, 21, 5);
+        assert_eq!(names, Some(Vec::new()));
+        let names = find_names(Mode::IntersectionNonempty, 21, 5);
+        assert_eq!(names, None);
+    }
+
+    #[test]
+    fn test_update_intersections() {
+        let set: HashSet<String> = [String::from("gene0"), String::from("gene1")]
+            .iter()
+            .cloned()
+            .collect();
+
+        let mut ctx = Context::default();
+        update_intersections(&mut ctx, None, set.clone(), None);
+        assert!(ctx.counts.is_empty());
+        assert_eq!(ctx.ambiguous, 1);
+
+        let mut ctx = Context::default();
+        update_intersections(&mut ctx, Some(AmbiguousMode::Fractional), set.clone(), None);
+        assert_eq!(ctx.counts["gene0"], 0.5);
+        assert_eq!(ctx.counts["gene1"], 0.5);
+        assert_eq!(ctx.ambiguous, 1);
+
+        let mut ctx = Context::default();
+        update_intersections(&mut ctx, Some(AmbiguousMode::All), set.clone(), None);
+        assert_eq!(ctx.counts["gene0"], 1.0);
+        assert_eq!(ctx.counts["gene1"], 1.0);
+        assert_eq!(ctx.ambiguous, 1);
+
+        let mut ctx = Context::default();
+        update_intersections(&mut ctx, Some(AmbiguousMode::Fractional), set, Some(0.5));
+        assert_eq!(ctx.counts["gene0"], 0.25);
+        assert_eq!(ctx.counts["gene1"], 0.25);
+
+        let mut ctx = Context::default();
+        let set = [String::from("gene0")].iter().cloned().collect();
+        update_intersections(&mut ctx, Some(AmbiguousMode::Fractional), set, None);
+        assert_eq!(ctx.counts["gene0"], 1.0);
+        assert_eq!(ctx.ambiguous, 0);
+
+        let mut ctx = Context::default();
+        update_intersections(&mut ctx, Some(AmbiguousMode::All), HashSet::new(), None);
+        assert!(ctx.counts.is_empty());
+        assert_eq!(ctx.no_feature, 1);
+    }
+
+    #[test]
+    fn test_combine() {
+        let a: HashSet<String> = [String::from("gene0"), String::from("gene1")]
+            .iter()
+            .cloned()
+            .collect();
+        let b: HashSet<String> = [String::from("gene1")].iter().cloned().collect();
+
+        assert_eq!(
+            combine(Mode::Union, Some(a.clone()), Some(b.clone())),
+            Some(a.clone())
+        );
+        assert_eq!(
+            combine(Mode::IntersectionStrict, Some(a.clone()), Some(b.clone())),
+            Some(b.clone())
+        );
+        assert_eq!(
+            combine(Mode::IntersectionNonempty, None, Some(b.clone())),
+            Some(b)
+        );
+        assert_eq!(combine(Mode::IntersectionNonempty, None, None), None);
+    }
 }
@@ -0,0 +1,56 @@
+use std::{error, fmt, str::FromStr};
+
+/// Ambiguous record assignment mode
+///
+/// This determines how a record that intersects more than one feature is
+/// assigned to the features in the set.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AmbiguousMode {
+    /// each feature is assigned an equal fraction of the record
+    Fractional,
+    /// each feature is assigned the whole record
+    All,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid ambiguous mode: {}", self.0)
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl FromStr for AmbiguousMode {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fractional" => Ok(Self::Fractional),
+            "all" => Ok(Self::All),
+            _ => Err(ParseError(s.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str() -> Result<(), ParseError> {
+        assert_eq!(
+            "fractional".parse::<AmbiguousMode>()?,
+            AmbiguousMode::Fractional
+        );
+        assert_eq!("all".parse::<AmbiguousMode>()?, AmbiguousMode::All);
+
+        assert!("".parse::<AmbiguousMode>().is_err());
+        assert!("none".parse::<AmbiguousMode>().is_err());
+        assert!("All".parse::<AmbiguousMode>().is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use super::Context;
+
+static NO_FEATURE: &str = "__no_feature";
+static AMBIGUOUS: &str = "__ambiguous";
+static LOW_QUALITY: &str = "__too_low_aQual";
+static UNMAPPED: &str = "__not_aligned";
+static NONUNIQUE: &str = "__alignment_not_unique";
+static DUPLICATE: &str = "__duplicate";
+static NOT_DUPLICATE: &str = "__not_duplicate";
+static SKIPPED: &str = "__skipped";
+
+/// Returns the assignment of a single record (or pair) from the events it
+/// produced.
+///
+/// This is the same as htseq-count's `XF` tag: either the ID of the feature the
+/// record was assigned to or, otherwise, the reason it was not counted, e.g.,
+/// `__no_feature`. Ambiguous records that were assigned to features list them,
+/// e.g., `__ambiguous[AADAT+CLN3]`. Records that were skipped without an
+/// event, e.g., secondary records, are `__skipped`.
+pub fn assignment(ctx: &Context) -> String {
+    if ctx.unmapped > 0 {
+        return UNMAPPED.into();
+    } else if ctx.duplicate > 0 {
+        return DUPLICATE.into();
+    } else if ctx.not_duplicate > 0 {
+        return NOT_DUPLICATE.into();
+    } else if ctx.nonunique > 0 {
+        return NONUNIQUE.into();
+    } else if ctx.low_quality > 0 {
+        return LOW_QUALITY.into();
+    }
+
+    let mut ids: Vec<_> = ctx
+        .counts
+        .keys()
+        .chain(ctx.nonunique_hits.values().flatten())
+        .chain(ctx.umi_hits.keys().map(|(id, _)| id))
+        .map(|id| id.as_str())
+        .collect();
+
+    ids.sort_unstable();
+    ids.dedup();
+
+    if ctx.ambiguous > 0 || !ctx.nonunique_ambiguous.is_empty() {
+        if ids.is_empty() {
+            AMBIGUOUS.into()
+        } else {
+            format!("{}[{}]", AMBIGUOUS, ids.join("+"))
+        }
+    } else if !ids.is_empty() {
+        ids.join("+")
+    } else if ctx.no_feature > 0 || !ctx.nonunique_no_features.is_empty() {
+        NO_FEATURE.into()
+    } else {
+        SKIPPED.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::count::context::Event;
+
+    fn build_context(events: Vec<Event>) -> Context {
+        let mut ctx = Context::default();
+
+        for event in events {
+            ctx.add_event(event);
+        }
+
+        ctx
+    }
+
+    #[test]
+    fn test_assignment() {
+        let ctx = build_context(vec![Event::Hit(String::from("AADAT"))]);
+        assert_eq!(assignment(&ctx), "AADAT");
+
+        let ctx = build_context(vec![Event::NonuniqueHit(
+            b"r0".to_vec(),
+            String::from("AADAT"),
+        )]);
+        assert_eq!(assignment(&ctx), "AADAT");
+
+        let ctx = build_context(vec![Event::NoFeature]);
+        assert_eq!(assignment(&ctx), "__no_feature");
+
+        let ctx = build_context(vec![Event::NonuniqueNoFeature(b"r0".to_vec())]);
+        assert_eq!(assignment(&ctx), "__no_feature");
+
+        let ctx = build_context(vec![Event::Ambiguous]);
+        assert_eq!(assignment(&ctx), "__ambiguous");
+
+        let ctx = build_context(vec![Event::NonuniqueAmbiguous(b"r0".to_vec())]);
+        assert_eq!(assignment(&ctx), "__ambiguous");
+
+        let ctx = build_context(vec![
+            Event::Ambiguous,
+            Event::WeightedHit(String::from("CLN3"), 0.5),
+            Event::WeightedHit(String::from("AADAT"), 0.5),
+        ]);
+        assert_eq!(assignment(&ctx), "__ambiguous[AADAT+CLN3]");
+
+        let ctx = build_context(vec![Event::LowQuality]);
+        assert_eq!(assignment(&ctx), "__too_low_aQual");
+
+        let ctx = build_context(vec![Event::Unmapped]);
+        assert_eq!(assignment(&ctx), "__not_aligned");
+
+        let ctx = build_context(vec![Event::Duplicate]);
+        assert_eq!(assignment(&ctx), "__duplicate");
+
+        let ctx = build_context(vec![Event::NotDuplicate]);
+        assert_eq!(assignment(&ctx), "__not_duplicate");
+
+        let ctx = build_context(vec![Event::Nonunique]);
+        assert_eq!(assignment(&ctx), "__alignment_not_unique");
+
+        let ctx = build_context(Vec::new());
+        assert_eq!(assignment(&ctx), "__skipped");
+    }
+}
@@ -2,43 +2,230 @@ mod event;
 
 pub use self::event::Event;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+use super::umi::{count_molecules, UmiPosition};
+
+const MAX_EM_ITERATIONS: usize = 1000;
+const EM_TOLERANCE: f64 = 1e-6;
 
 #[derive(Default)]
 pub struct Context {
-    pub counts: HashMap<String, u64>,
+    pub counts: HashMap<String, f64>,
+    pub nonunique_hits: HashMap<Vec<u8>, Vec<String>>,
+    pub nonunique_no_features: HashSet<Vec<u8>>,
+    pub nonunique_ambiguous: HashSet<Vec<u8>>,
+    pub umi_hits: HashMap<(String, UmiPosition), HashMap<String, u64>>,
+    pub duplicate_counts: HashMap<String, f64>,
     pub no_feature: u64,
     pub ambiguous: u64,
     pub low_quality: u64,
     pub unmapped: u64,
     pub nonunique: u64,
+    pub duplicate: u64,
+    pub not_duplicate: u64,
+    pub umi_records: u64,
+    pub umi_duplicates: u64,
 }
 
 impl Context {
     pub fn add(&mut self, other: &Context) {
         for (name, count) in other.counts.iter() {
-            let entry = self.counts.entry(name.to_string()).or_insert(0);
+            let entry = self.counts.entry(name.to_string()).or_insert(0.0);
+            *entry += count;
+        }
+
+        for (read_name, names) in other.nonunique_hits.iter() {
+            let entry = self.nonunique_hits.entry(read_name.clone()).or_default();
+            entry.extend(names.iter().cloned());
+        }
+
+        self.nonunique_no_features
+            .extend(other.nonunique_no_features.iter().cloned());
+        self.nonunique_ambiguous
+            .extend(other.nonunique_ambiguous.iter().cloned());
+
+        for (name, count) in other.duplicate_counts.iter() {
+            let entry = self.duplicate_counts.entry(name.to_string()).or_insert(0.0);
             *entry += count;
         }
 
+        for (key, umis) in other.umi_hits.iter() {
+            let entry = self.umi_hits.entry(key.clone()).or_default();
+
+            for (umi, count) in umis {
+                *entry.entry(umi.clone()).or_insert(0) += count;
+            }
+        }
+
         self.no_feature += other.no_feature;
         self.ambiguous += other.ambiguous;
         self.low_quality += other.low_quality;
         self.unmapped += other.unmapped;
         self.nonunique += other.nonunique;
+        self.duplicate += other.duplicate;
+        self.not_duplicate += other.not_duplicate;
+        self.umi_records += other.umi_records;
+        self.umi_duplicates += other.umi_duplicates;
     }
 
     pub fn add_event(&mut self, event: Event) {
         match event {
             Event::Hit(id) => {
-                let count = self.counts.entry(id).or_insert(0);
-                *count += 1;
+                let count = self.counts.entry(id).or_insert(0.0);
+                *count += 1.0;
+            }
+            Event::WeightedHit(id, weight) => {
+                let count = self.counts.entry(id).or_insert(0.0);
+                *count += weight;
+            }
+            Event::NonuniqueHit(read_name, id) => {
+                let names = self.nonunique_hits.entry(read_name).or_default();
+                names.push(id);
+            }
+            Event::NonuniqueNoFeature(read_name) => {
+                self.nonunique_no_features.insert(read_name);
+            }
+            Event::NonuniqueAmbiguous(read_name) => {
+                self.nonunique_ambiguous.insert(read_name);
+            }
+            Event::UmiHit(id, position, umi) => {
+                let umis = self.umi_hits.entry((id, position)).or_default();
+                *umis.entry(umi).or_insert(0) += 1;
+            }
+            Event::DuplicateHit(id, weight) => {
+                let count = self.duplicate_counts.entry(id).or_insert(0.0);
+                *count += weight;
             }
             Event::NoFeature => self.no_feature += 1,
             Event::Ambiguous => self.ambiguous += 1,
             Event::LowQuality => self.low_quality += 1,
             Event::Unmapped => self.unmapped += 1,
             Event::Nonunique => self.nonunique += 1,
+            Event::Duplicate => self.duplicate += 1,
+            Event::NotDuplicate => self.not_duplicate += 1,
+        }
+    }
+
+    /// Counts the collected UMI hits as unique molecules.
+    ///
+    /// The UMIs of each feature and position are grouped (see
+    /// `umi::count_molecules`), and each group is counted once. The remaining
+    /// records are duplicates.
+    ///
+    /// The UMI hits are consumed.
+    pub fn deduplicate_umi_hits(&mut self) {
+        for ((id, _), umis) in self.umi_hits.drain() {
+            let record_count: u64 = umis.values().sum();
+            let molecule_count = count_molecules(&umis) as u64;
+
+            let count = self.counts.entry(id).or_insert(0.0);
+            *count += molecule_count as f64;
+
+            self.umi_records += record_count;
+            self.umi_duplicates += record_count - molecule_count;
+        }
+    }
+
+    /// Returns the fraction of records with a UMI that are duplicates.
+    ///
+    /// This returns `None` if no records were deduplicated.
+    pub fn umi_duplicate_fraction(&self) -> Option<f64> {
+        if self.umi_records > 0 {
+            Some(self.umi_duplicates as f64 / self.umi_records as f64)
+        } else {
+            None
+        }
+    }
+
+    /// Distributes the collected nonunique hits to the counts using expectation maximization.
+    ///
+    /// The abundance of each feature is initialized with its unique count. Each
+    /// iteration, a nonunique record is split across the features it hits
+    /// proportionally to their current abundances, and the abundances are
+    /// updated with the split. Records that only hit features with no abundance
+    /// are split evenly.
+    ///
+    /// A read with no hits in any of its alignments is counted once as
+    /// ambiguous, if any of its alignments are ambiguous, or otherwise as no
+    /// feature.
+    ///
+    /// The nonunique hits are consumed.
+    pub fn distribute_nonunique_hits(&mut self) {
+        for read_name in self.nonunique_ambiguous.drain() {
+            if !self.nonunique_hits.contains_key(&read_name) {
+                self.ambiguous += 1;
+                self.nonunique_no_features.remove(&read_name);
+            }
+        }
+
+        for read_name in self.nonunique_no_features.drain() {
+            if !self.nonunique_hits.contains_key(&read_name) {
+                self.no_feature += 1;
+            }
+        }
+
+        let reads: Vec<Vec<String>> = self
+            .nonunique_hits
+            .drain()
+            .map(|(_, names)| {
+                let mut names: Vec<_> = names
+                    .into_iter()
+                    .collect::<HashSet<_>>()
+                    .into_iter()
+                    .collect();
+                names.sort();
+                names
+            })
+            .collect();
+
+        if reads.is_empty() {
+            return;
+        }
+
+        let unique_counts = self.counts.clone();
+        let mut abundances = unique_counts.clone();
+        let mut nonunique_counts: HashMap<String, f64> = HashMap::new();
+
+        for _ in 0..MAX_EM_ITERATIONS {
+            nonunique_counts.clear();
+
+            for names in &reads {
+                let total: f64 = names
+                    .iter()
+                    .map(|name| abundances.get(name).copied().unwrap_or(0.0))
+                    .sum();
+
+                for name in names {
+                    let weight = if total > 0.0 {
+                        abundances.get(name).copied().unwrap_or(0.0) / total
+                    } else {
+                        1.0 / names.len() as f64
+                    };
+
+                    let count = nonunique_counts.entry(name.clone()).or_insert(0.0);
+                    *count += weight;
+                }
+            }
+
+            let mut delta: f64 = 0.0;
+
+            for (name, nonunique_count) in &nonunique_counts {
+                let unique_count = unique_counts.get(name).copied().unwrap_or(0.0);
+                let abundance = abundances.entry(name.clone()).or_insert(0.0);
+                let next_abundance = unique_count + nonunique_count;
+                delta = delta.max((next_abundance - *abundance).abs());
+                *abundance = next_abundance;
+            }
+
+            if delta < EM_TOLERANCE {
+                break;
+            }
+        }
+
+        for (name, nonunique_count) in nonunique_counts {
+            let count = self.counts.entry(name).or_insert(0.0);
+            *count += nonunique_count;
         }
     }
 }
@@ -51,53 +238,167 @@ mod tests {
     fn test_add() {
         let mut ctx_a = Context::default();
 
-        ctx_a.counts.insert(String::from("AADAT"), 2);
+        ctx_a.counts.insert(String::from("AADAT"), 2.0);
+        ctx_a
+            .nonunique_hits
+            .insert(b"r0".to_vec(), vec![String::from("AADAT")]);
         ctx_a.no_feature = 3;
         ctx_a.ambiguous = 5;
         ctx_a.low_quality = 8;
         ctx_a.unmapped = 13;
         ctx_a.nonunique = 21;
+        ctx_a.duplicate = 3;
+        ctx_a.not_duplicate = 2;
+        ctx_a.umi_records = 34;
+        ctx_a.umi_duplicates = 5;
 
         let mut ctx_b = Context::default();
 
-        ctx_b.counts.insert(String::from("AADAT"), 2);
-        ctx_b.counts.insert(String::from("CLN3"), 3);
+        ctx_b.counts.insert(String::from("AADAT"), 2.0);
+        ctx_b.counts.insert(String::from("CLN3"), 3.0);
+        ctx_b.duplicate_counts.insert(String::from("CLN3"), 1.0);
+        ctx_b
+            .nonunique_hits
+            .insert(b"r0".to_vec(), vec![String::from("CLN3")]);
         ctx_b.no_feature = 5;
         ctx_b.ambiguous = 8;
         ctx_b.low_quality = 13;
         ctx_b.unmapped = 21;
         ctx_b.nonunique = 34;
+        ctx_b.duplicate = 5;
+        ctx_b.not_duplicate = 1;
+        ctx_b.umi_records = 55;
+        ctx_b.umi_duplicates = 8;
 
         ctx_a.add(&ctx_b);
 
         assert_eq!(ctx_a.counts.len(), 2);
-        assert_eq!(ctx_a.counts["AADAT"], 4);
-        assert_eq!(ctx_a.counts["CLN3"], 3);
+        assert_eq!(ctx_a.counts["AADAT"], 4.0);
+        assert_eq!(ctx_a.counts["CLN3"], 3.0);
+        assert_eq!(ctx_a.duplicate_counts["CLN3"], 1.0);
+
+        assert_eq!(
+            ctx_a.nonunique_hits[&b"r0"[..]],
+            [String::from("AADAT"), String::from("CLN3")]
+        );
 
         assert_eq!(ctx_a.no_feature, 8);
         assert_eq!(ctx_a.ambiguous, 13);
         assert_eq!(ctx_a.low_quality, 21);
         assert_eq!(ctx_a.unmapped, 34);
         assert_eq!(ctx_a.nonunique, 55);
+        assert_eq!(ctx_a.duplicate, 8);
+        assert_eq!(ctx_a.not_duplicate, 3);
+        assert_eq!(ctx_a.umi_records, 89);
+        assert_eq!(ctx_a.umi_duplicates, 13);
     }
 
     #[test]
     fn test_add_event() {
         let mut ctx = Context::default();
         ctx.add_event(Event::Hit(String::from("AADAT")));
+        ctx.add_event(Event::WeightedHit(String::from("AADAT"), 0.5));
+        ctx.add_event(Event::NonuniqueHit(b"r0".to_vec(), String::from("CLN3")));
+        ctx.add_event(Event::NonuniqueNoFeature(b"r1".to_vec()));
+        ctx.add_event(Event::NonuniqueAmbiguous(b"r2".to_vec()));
         ctx.add_event(Event::NoFeature);
         ctx.add_event(Event::Ambiguous);
         ctx.add_event(Event::LowQuality);
         ctx.add_event(Event::Unmapped);
         ctx.add_event(Event::Nonunique);
+        ctx.add_event(Event::Duplicate);
+        ctx.add_event(Event::NotDuplicate);
+        ctx.add_event(Event::DuplicateHit(String::from("AADAT"), 0.5));
 
         assert_eq!(ctx.counts.len(), 1);
-        assert_eq!(ctx.counts["AADAT"], 1);
+        assert_eq!(ctx.counts["AADAT"], 1.5);
+
+        assert_eq!(ctx.nonunique_hits.len(), 1);
+        assert_eq!(ctx.nonunique_hits[&b"r0"[..]], [String::from("CLN3")]);
+        assert!(ctx.nonunique_no_features.contains(&b"r1"[..]));
+        assert!(ctx.nonunique_ambiguous.contains(&b"r2"[..]));
 
         assert_eq!(ctx.no_feature, 1);
         assert_eq!(ctx.ambiguous, 1);
         assert_eq!(ctx.low_quality, 1);
         assert_eq!(ctx.unmapped, 1);
         assert_eq!(ctx.nonunique, 1);
+        assert_eq!(ctx.duplicate, 1);
+        assert_eq!(ctx.not_duplicate, 1);
+        assert_eq!(ctx.duplicate_counts["AADAT"], 0.5);
+    }
+
+    #[test]
+    fn test_deduplicate_umi_hits() {
+        let mut ctx = Context::default();
+        ctx.counts.insert(String::from("AADAT"), 1.0);
+
+        let position = UmiPosition::new(0, 8, false);
+
+        for umi in &["ACGT", "ACGT", "ACGT", "ACGA", "TTTT"] {
+            ctx.add_event(Event::UmiHit(
+                String::from("AADAT"),
+                position,
+                String::from(*umi),
+            ));
+        }
+
+        // The same UMI at another position is another molecule.
+        ctx.add_event(Event::UmiHit(
+            String::from("AADAT"),
+            UmiPosition::new(0, 13, true),
+            String::from("ACGT"),
+        ));
+
+        assert_eq!(ctx.umi_duplicate_fraction(), None);
+
+        ctx.deduplicate_umi_hits();
+
+        assert!(ctx.umi_hits.is_empty());
+        assert_eq!(ctx.counts["AADAT"], 4.0);
+        assert_eq!(ctx.umi_records, 6);
+        assert_eq!(ctx.umi_duplicates, 3);
+        assert_eq!(ctx.umi_duplicate_fraction(), Some(0.5));
+    }
+
+    #[test]
+    fn test_distribute_nonunique_hits() {
+        let mut ctx = Context::default();
+
+        ctx.counts.insert(String::from("AADAT"), 3.0);
+        ctx.counts.insert(String::from("CLN3"), 1.0);
+
+        ctx.add_event(Event::NonuniqueHit(b"r0".to_vec(), String::from("AADAT")));
+        ctx.add_event(Event::NonuniqueHit(b"r0".to_vec(), String::from("CLN3")));
+        ctx.add_event(Event::NonuniqueHit(b"r1".to_vec(), String::from("NEO1")));
+        ctx.add_event(Event::NonuniqueHit(b"r1".to_vec(), String::from("PAK4")));
+
+        // r0 has other alignments with no feature and that are ambiguous, r2
+        // has none with a feature, and r3 has an ambiguous alignment.
+        ctx.add_event(Event::NonuniqueNoFeature(b"r0".to_vec()));
+        ctx.add_event(Event::NonuniqueAmbiguous(b"r0".to_vec()));
+        ctx.add_event(Event::NonuniqueNoFeature(b"r2".to_vec()));
+        ctx.add_event(Event::NonuniqueNoFeature(b"r2".to_vec()));
+        ctx.add_event(Event::NonuniqueNoFeature(b"r3".to_vec()));
+        ctx.add_event(Event::NonuniqueAmbiguous(b"r3".to_vec()));
+
+        ctx.distribute_nonunique_hits();
+
+        assert!(ctx.nonunique_hits.is_empty());
+        assert!(ctx.nonunique_no_features.is_empty());
+        assert!(ctx.nonunique_ambiguous.is_empty());
+        assert_eq!(ctx.no_feature, 1);
+        assert_eq!(ctx.ambiguous, 1);
+
+        // r0 converges to AADAT = 3 + 3/(3 + 1) = 3.75 and CLN3 = 1 + 1/(3 + 1) = 1.25.
+        assert!((ctx.counts["AADAT"] - 3.75).abs() < 1e-3);
+        assert!((ctx.counts["CLN3"] - 1.25).abs() < 1e-3);
+
+        // r1 hits features with no unique counts and is split evenly.
+        assert!((ctx.counts["NEO1"] - 0.5).abs() < f64::EPSILON);
+        assert!((ctx.counts["PAK4"] - 0.5).abs() < f64::EPSILON);
+
+        let total: f64 = ctx.counts.values().sum();
+        assert!((total - 6.0).abs() < 1e-9);
     }
 }
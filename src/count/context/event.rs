@@ -1,9 +1,19 @@
-#[derive(Clone, Debug, Eq, PartialEq)]
+use super::super::umi::UmiPosition;
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Event {
     Hit(String),
+    WeightedHit(String, f64),
+    NonuniqueHit(Vec<u8>, String),
+    NonuniqueNoFeature(Vec<u8>),
+    NonuniqueAmbiguous(Vec<u8>),
+    UmiHit(String, UmiPosition, String),
+    DuplicateHit(String, f64),
     NoFeature,
     Ambiguous,
     LowQuality,
     Unmapped,
     Nonunique,
+    Duplicate,
+    NotDuplicate,
 }
@@ -0,0 +1,57 @@
+use std::{error, fmt, str::FromStr};
+
+/// Duplicate record mode
+///
+/// This determines how records marked as PCR or optical duplicates (BAM flag
+/// 0x400), e.g., by Picard MarkDuplicates, are counted.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DuplicateMode {
+    /// duplicates are counted as any other record
+    Keep,
+    /// duplicates are not counted
+    Skip,
+    /// only duplicates are counted
+    Only,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid duplicate mode: {}", self.0)
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl FromStr for DuplicateMode {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(Self::Keep),
+            "skip" => Ok(Self::Skip),
+            "only" => Ok(Self::Only),
+            _ => Err(ParseError(s.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str() -> Result<(), ParseError> {
+        assert_eq!("keep".parse::<DuplicateMode>()?, DuplicateMode::Keep);
+        assert_eq!("skip".parse::<DuplicateMode>()?, DuplicateMode::Skip);
+        assert_eq!("only".parse::<DuplicateMode>()?, DuplicateMode::Only);
+
+        assert!("".parse::<DuplicateMode>().is_err());
+        assert!("remove".parse::<DuplicateMode>().is_err());
+        assert!("Skip".parse::<DuplicateMode>().is_err());
+
+        Ok(())
+    }
+}
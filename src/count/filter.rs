@@ -1,9 +1,10 @@
-use std::io;
+use std::{convert::TryFrom, io};
 
+use log::info;
 use noodles_bam as bam;
 use noodles_sam as sam;
 
-use super::{context::Event, Context};
+use super::{context::Event, Context, DuplicateMode, NonuniqueMode, UmiSource};
 
 #[derive(Clone)]
 pub struct Filter {
@@ -11,6 +12,9 @@ pub struct Filter {
     with_secondary_records: bool,
     with_supplementary_records: bool,
     with_nonunique_records: bool,
+    nonunique_mode: Option<NonuniqueMode>,
+    umi_source: Option<UmiSource>,
+    duplicate_mode: DuplicateMode,
 }
 
 impl Filter {
@@ -29,6 +33,18 @@ impl Filter {
     pub fn with_nonunique_records(&self) -> bool {
         self.with_nonunique_records
     }
+
+    pub fn nonunique_mode(&self) -> Option<NonuniqueMode> {
+        self.nonunique_mode
+    }
+
+    pub fn umi_source(&self) -> Option<UmiSource> {
+        self.umi_source
+    }
+
+    pub fn duplicate_mode(&self) -> DuplicateMode {
+        self.duplicate_mode
+    }
 }
 
 impl Filter {
@@ -37,12 +53,18 @@ impl Filter {
         with_secondary_records: bool,
         with_supplementary_records: bool,
         with_nonunique_records: bool,
+        nonunique_mode: Option<NonuniqueMode>,
+        umi_source: Option<UmiSource>,
+        duplicate_mode: DuplicateMode,
     ) -> Filter {
         Self {
             min_mapping_quality,
             with_secondary_records,
             with_supplementary_records,
             with_nonunique_records,
+            nonunique_mode,
+            umi_source,
+            duplicate_mode,
         }
     }
 
@@ -60,12 +82,16 @@ impl Filter {
             return Ok(true);
         }
 
+        if self.filter_duplicate(ctx, flags.is_duplicate()) {
+            return Ok(true);
+        }
+
         if !self.with_nonunique_records && is_nonunique_record(&record)? {
             ctx.add_event(Event::Nonunique);
             return Ok(true);
         }
 
-        if u8::from(record.mapping_quality()) < self.min_mapping_quality {
+        if self.is_low_quality(record)? {
             ctx.add_event(Event::LowQuality);
             return Ok(true);
         }
@@ -94,24 +120,80 @@ impl Filter {
             return Ok(true);
         }
 
+        if self.filter_duplicate(ctx, f1.is_duplicate() || f2.is_duplicate()) {
+            return Ok(true);
+        }
+
         if !self.with_nonunique_records && (is_nonunique_record(&r1)? || is_nonunique_record(&r2)?)
         {
             ctx.add_event(Event::Nonunique);
             return Ok(true);
         }
 
-        if u8::from(r1.mapping_quality()) < self.min_mapping_quality
-            || u8::from(r2.mapping_quality()) < self.min_mapping_quality
-        {
+        if self.is_low_quality(r1)? || self.is_low_quality(r2)? {
             ctx.add_event(Event::LowQuality);
             return Ok(true);
         }
 
         Ok(false)
     }
+
+    /// Returns whether a record is below the minimum mapping quality.
+    ///
+    /// Aligners give the alignments of nonunique reads a low mapping quality,
+    /// e.g., STAR and HISAT2 use 0 to 3, so nonunique records are never low
+    /// quality when they are distributed by a nonunique mode.
+    fn is_low_quality(&self, record: &bam::Record) -> io::Result<bool> {
+        if u8::from(record.mapping_quality()) >= self.min_mapping_quality {
+            Ok(false)
+        } else if self.nonunique_mode.is_some() {
+            is_nonunique_record(record).map(|is_nonunique| !is_nonunique)
+        } else {
+            Ok(true)
+        }
+    }
+
+    /// Returns whether a record (or pair) is filtered by the duplicate mode.
+    ///
+    /// Skipped duplicates are counted as duplicate events and, when only
+    /// counting duplicates, skipped nonduplicates as not duplicate events.
+    fn filter_duplicate(&self, ctx: &mut Context, is_duplicate: bool) -> bool {
+        match self.duplicate_mode {
+            DuplicateMode::Keep => false,
+            DuplicateMode::Skip if is_duplicate => {
+                ctx.add_event(Event::Duplicate);
+                true
+            }
+            DuplicateMode::Skip => false,
+            DuplicateMode::Only if !is_duplicate => {
+                ctx.add_event(Event::NotDuplicate);
+                true
+            }
+            DuplicateMode::Only => false,
+        }
+    }
+}
+
+/// Logs the number of records (or pairs) removed by a filter.
+///
+/// These are the same counts as the `__too_low_aQual`, `__not_aligned`,
+/// `__alignment_not_unique`, `__duplicate`, and `__not_duplicate` rows of the
+/// counts trailer.
+pub fn log_filtered(ctx: &Context) {
+    info!(
+        "filtered {} low quality, {} unaligned, {} nonunique, {} duplicate, and {} not duplicate records",
+        ctx.low_quality, ctx.unmapped, ctx.nonunique, ctx.duplicate, ctx.not_duplicate
+    );
 }
 
-fn is_nonunique_record(record: &bam::Record) -> io::Result<bool> {
+pub fn is_nonunique_record(record: &bam::Record) -> io::Result<bool> {
+    alignment_hit_count(record).map(|n| n.map(|n| n > 1).unwrap_or(false))
+}
+
+/// Returns the number of alignments of the record's read (BAM data tag NH).
+///
+/// A negative count is invalid.
+pub fn alignment_hit_count(record: &bam::Record) -> io::Result<Option<u32>> {
     use bam::record::data::field::Value;
     use sam::record::data::field::Tag;
 
@@ -121,13 +203,24 @@ fn is_nonunique_record(record: &bam::Record) -> io::Result<bool> {
         let field = result?;
 
         if field.tag() == &Tag::AlignmentHitCount {
-            match field.value() {
-                Value::Int8(n) => return Ok(*n > 1),
-                Value::UInt8(n) => return Ok(*n > 1),
-                _ => {}
-            }
+            let n = match field.value() {
+                Value::Int8(n) => u32::try_from(*n),
+                Value::UInt8(n) => Ok(u32::from(*n)),
+                Value::Int16(n) => u32::try_from(*n),
+                Value::UInt16(n) => Ok(u32::from(*n)),
+                Value::Int32(n) => u32::try_from(*n),
+                Value::UInt32(n) => Ok(*n),
+                _ => continue,
+            };
+
+            return n.map(Some).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid alignment hit count: {:?}", field.value()),
+                )
+            });
         }
     }
 
-    Ok(false)
+    Ok(None)
 }
@@ -0,0 +1,150 @@
+use std::{error, fmt, ops::RangeInclusive, str::FromStr};
+
+use noodles_bam as bam;
+
+use crate::MatchIntervals;
+
+use super::ReadPosition;
+
+// Tn5 inserts adapters 9 bases apart, so the center of the insertion is 4 bases
+// after the 5' end of a forward read and 5 bases before the 5' end of a reverse
+// read.
+const FORWARD_CUT_SITE_SHIFT: u64 = 4;
+const REVERSE_CUT_SITE_SHIFT: u64 = 5;
+
+/// Fragment mode
+///
+/// This determines which positions of a record or pair of records are
+/// intersected with features.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FragmentMode {
+    /// the aligned blocks of each record
+    Alignment,
+    /// the template span, i.e., from the leftmost start to the rightmost end of
+    /// the records, including the insert between mates
+    Span,
+    /// the Tn5 cut site of each record, i.e., its 5' end shifted +4 (forward)
+    /// or -5 (reverse)
+    CutSites,
+    /// a single position of the fragment, shifted toward its 3' end
+    ReadPosition(ReadPosition, i64),
+}
+
+impl FragmentMode {
+    /// Returns the intervals of a fragment, given its records.
+    ///
+    /// The records are expected to be on the same reference sequence.
+    pub fn intervals(self, records: &[&bam::Record]) -> Vec<RangeInclusive<u64>> {
+        match self {
+            Self::Alignment => records
+                .iter()
+                .flat_map(|record| {
+                    let cigar = record.cigar();
+                    let start = i32::from(record.position()) as u64;
+                    MatchIntervals::new(&cigar, start).collect::<Vec<_>>()
+                })
+                .collect(),
+            Self::Span => {
+                let intervals: Vec<_> = records.iter().map(|record| span(record)).collect();
+                merge_spans(&intervals).into_iter().collect()
+            }
+            Self::CutSites => records
+                .iter()
+                .map(|record| {
+                    let is_reverse = record.flags().is_reverse_complemented();
+                    let position = cut_site(&span(record), is_reverse);
+                    position..=position
+                })
+                .collect(),
+            Self::ReadPosition(read_position, shift) => read_position
+                .position(records, shift)
+                .map(|position| position..=position)
+                .into_iter()
+                .collect(),
+        }
+    }
+}
+
+/// Returns the reference span of a record, i.e., from its start to its end.
+fn span(record: &bam::Record) -> RangeInclusive<u64> {
+    let start = i32::from(record.position()) as u64;
+    let reference_len = record.cigar().reference_len() as u64;
+    let end = start + reference_len.saturating_sub(1);
+    start..=end
+}
+
+/// Returns the span that covers all of the given spans.
+fn merge_spans(spans: &[RangeInclusive<u64>]) -> Option<RangeInclusive<u64>> {
+    let start = spans.iter().map(|span| *span.start()).min()?;
+    let end = spans.iter().map(|span| *span.end()).max()?;
+    Some(start..=end)
+}
+
+/// Returns the Tn5 cut site of a record, given its span.
+fn cut_site(span: &RangeInclusive<u64>, is_reverse: bool) -> u64 {
+    if is_reverse {
+        span.end().saturating_sub(REVERSE_CUT_SITE_SHIFT).max(1)
+    } else {
+        span.start() + FORWARD_CUT_SITE_SHIFT
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid fragment mode: {}", self.0)
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl FromStr for FragmentMode {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "alignment" => Ok(Self::Alignment),
+            "span" => Ok(Self::Span),
+            "cut-sites" => Ok(Self::CutSites),
+            _ => Err(ParseError(s.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_spans() {
+        assert_eq!(merge_spans(&[8..=13, 21..=34]), Some(8..=34));
+        assert_eq!(merge_spans(&[21..=34, 8..=13]), Some(8..=34));
+        assert_eq!(merge_spans(&[8..=34, 13..=21]), Some(8..=34));
+        assert_eq!(merge_spans(&[]), None);
+    }
+
+    #[test]
+    fn test_cut_site() {
+        assert_eq!(cut_site(&(8..=55), false), 12);
+        assert_eq!(cut_site(&(8..=55), true), 50);
+        assert_eq!(cut_site(&(1..=3), true), 1);
+    }
+
+    #[test]
+    fn test_from_str() -> Result<(), ParseError> {
+        assert_eq!(
+            "alignment".parse::<FragmentMode>()?,
+            FragmentMode::Alignment
+        );
+        assert_eq!("span".parse::<FragmentMode>()?, FragmentMode::Span);
+        assert_eq!("cut-sites".parse::<FragmentMode>()?, FragmentMode::CutSites);
+
+        assert!("".parse::<FragmentMode>().is_err());
+        assert!("cut_sites".parse::<FragmentMode>().is_err());
+        assert!("Span".parse::<FragmentMode>().is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+use std::{error, fmt, str::FromStr};
+
+/// Overlap resolution mode
+///
+/// These are the same modes as htseq-count. Given the sets of features that
+/// overlap each aligned position of a record, the mode determines how they are
+/// combined into a single set.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Mode {
+    /// the union of all sets
+    Union,
+    /// the intersection of all sets
+    IntersectionStrict,
+    /// the intersection of all nonempty sets
+    IntersectionNonempty,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid mode: {}", self.0)
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl FromStr for Mode {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "union" => Ok(Self::Union),
+            "intersection-strict" => Ok(Self::IntersectionStrict),
+            "intersection-nonempty" => Ok(Self::IntersectionNonempty),
+            _ => Err(ParseError(s.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str() -> Result<(), ParseError> {
+        assert_eq!("union".parse::<Mode>()?, Mode::Union);
+        assert_eq!(
+            "intersection-strict".parse::<Mode>()?,
+            Mode::IntersectionStrict
+        );
+        assert_eq!(
+            "intersection-nonempty".parse::<Mode>()?,
+            Mode::IntersectionNonempty
+        );
+
+        assert!("".parse::<Mode>().is_err());
+        assert!("intersection".parse::<Mode>().is_err());
+        assert!("Union".parse::<Mode>().is_err());
+
+        Ok(())
+    }
+}
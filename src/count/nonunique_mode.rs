@@ -0,0 +1,53 @@
+use std::{error, fmt, str::FromStr};
+
+/// Nonunique record distribution mode
+///
+/// This determines how a record with multiple alignments (BAM data tag NH > 1)
+/// is distributed across the features hit by each of its alignments.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NonuniqueMode {
+    /// each alignment is weighted 1/NH
+    Uniform,
+    /// alignments are weighted proportionally to the abundance of unique records
+    Em,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid nonunique mode: {}", self.0)
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl FromStr for NonuniqueMode {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uniform" => Ok(Self::Uniform),
+            "em" => Ok(Self::Em),
+            _ => Err(ParseError(s.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str() -> Result<(), ParseError> {
+        assert_eq!("uniform".parse::<NonuniqueMode>()?, NonuniqueMode::Uniform);
+        assert_eq!("em".parse::<NonuniqueMode>()?, NonuniqueMode::Em);
+
+        assert!("".parse::<NonuniqueMode>().is_err());
+        assert!("fraction".parse::<NonuniqueMode>().is_err());
+        assert!("EM".parse::<NonuniqueMode>().is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+use std::{error, fmt, ops::RangeInclusive, str::FromStr};
+
+use noodles_bam as bam;
+
+/// Read position
+///
+/// This is the single position of a fragment used to assign it, e.g., for 3'
+/// tag (QuantSeq) or 5' cap (CAGE) libraries. Positions are relative to the
+/// fragment, oriented as the first read.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ReadPosition {
+    /// the 5' end of the first read
+    FivePrime,
+    /// the 5' end of the second read or, without a mate, the 3' end of the read
+    ThreePrime,
+}
+
+impl ReadPosition {
+    /// Returns the position of a fragment, given its records.
+    ///
+    /// The shift is in the direction of the fragment, i.e., a positive shift
+    /// moves the position toward the 3' end. Positions are clamped to 1.
+    pub fn position(self, records: &[&bam::Record], shift: i64) -> Option<u64> {
+        let record = self.record(records)?;
+
+        let start = i32::from(record.position()) as u64;
+        let reference_len = record.cigar().reference_len() as u64;
+        let span = start..=(start + reference_len.saturating_sub(1));
+
+        let flags = record.flags();
+        let is_reverse = flags.is_reverse_complemented() ^ is_second(record);
+
+        Some(end_position(self, &span, is_reverse, shift))
+    }
+}
+
+impl ReadPosition {
+    /// Returns the record of a fragment that has the position.
+    ///
+    /// This is the first read (`FivePrime`) or the second read (`ThreePrime`)
+    /// or, without a mate, the given record.
+    pub fn record<'r>(self, records: &[&'r bam::Record]) -> Option<&'r bam::Record> {
+        match self {
+            Self::FivePrime => records.iter().find(|r| !is_second(r)),
+            Self::ThreePrime => records.iter().find(|r| is_second(r)),
+        }
+        .or_else(|| records.first())
+        .copied()
+    }
+}
+
+fn is_second(record: &bam::Record) -> bool {
+    let flags = record.flags();
+    flags.is_paired() && flags.is_read_2()
+}
+
+/// Returns the 5' or 3' position of a span on a strand, shifted downstream.
+fn end_position(
+    read_position: ReadPosition,
+    span: &RangeInclusive<u64>,
+    is_reverse: bool,
+    shift: i64,
+) -> u64 {
+    let (position, shift) = match (read_position, is_reverse) {
+        (ReadPosition::FivePrime, false) => (*span.start(), shift),
+        (ReadPosition::FivePrime, true) => (*span.end(), -shift),
+        (ReadPosition::ThreePrime, false) => (*span.end(), shift),
+        (ReadPosition::ThreePrime, true) => (*span.start(), -shift),
+    };
+
+    let position = position as i64 + shift;
+    position.max(1) as u64
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid read position: {}", self.0)
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl FromStr for ReadPosition {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "five-prime" => Ok(Self::FivePrime),
+            "three-prime" => Ok(Self::ThreePrime),
+            _ => Err(ParseError(s.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_end_position() {
+        let span = 8..=55;
+
+        assert_eq!(end_position(ReadPosition::FivePrime, &span, false, 0), 8);
+        assert_eq!(end_position(ReadPosition::FivePrime, &span, true, 0), 55);
+        assert_eq!(end_position(ReadPosition::ThreePrime, &span, false, 0), 55);
+        assert_eq!(end_position(ReadPosition::ThreePrime, &span, true, 0), 8);
+
+        assert_eq!(end_position(ReadPosition::FivePrime, &span, false, 3), 11);
+        assert_eq!(end_position(ReadPosition::FivePrime, &span, true, 3), 52);
+        assert_eq!(end_position(ReadPosition::ThreePrime, &span, false, -5), 50);
+        assert_eq!(end_position(ReadPosition::ThreePrime, &span, true, 13), 1);
+    }
+
+    #[test]
+    fn test_from_str() -> Result<(), ParseError> {
+        assert_eq!(
+            "five-prime".parse::<ReadPosition>()?,
+            ReadPosition::FivePrime
+        );
+        assert_eq!(
+            "three-prime".parse::<ReadPosition>()?,
+            ReadPosition::ThreePrime
+        );
+
+        assert!("".parse::<ReadPosition>().is_err());
+        assert!("5p".parse::<ReadPosition>().is_err());
+        assert!("three_prime".parse::<ReadPosition>().is_err());
+
+        Ok(())
+    }
+}
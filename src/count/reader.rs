@@ -6,6 +6,8 @@ use std::{
 const DELIMITER: char = '\t';
 static HTSEQ_COUNT_META_PREFIX: &str = "__";
 
+type Stats = Vec<(String, f64)>;
+
 pub struct Reader<R> {
     inner: R,
 }
@@ -18,8 +20,18 @@ where
         Self { inner }
     }
 
-    pub fn read_counts(&mut self) -> io::Result<HashMap<String, u64>> {
+    pub fn read_counts(&mut self) -> io::Result<HashMap<String, f64>> {
+        self.read_counts_and_stats().map(|(counts, _)| counts)
+    }
+
+    /// Reads the counts and the statistics in the trailer.
+    ///
+    /// The trailer is all lines starting with the first line whose ID starts
+    /// with `__`, e.g., `__no_feature`. The statistics are kept in the order
+    /// they appear.
+    pub fn read_counts_and_stats(&mut self) -> io::Result<(HashMap<String, f64>, Stats)> {
         let mut counts = HashMap::new();
+        let mut stats = Vec::new();
         let mut buf = String::new();
 
         loop {
@@ -36,17 +48,21 @@ where
             let mut fields = buf.split(DELIMITER);
 
             let id = parse_string(&mut fields)?;
+            let count = parse_f64(&mut fields)?;
 
             if id.starts_with(HTSEQ_COUNT_META_PREFIX) {
-                break;
+                stats.push((id.into(), count));
+            } else if stats.is_empty() {
+                counts.insert(id.into(), count);
+            } else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unexpected count after trailer: {}", id),
+                ));
             }
-
-            let count = parse_u64(&mut fields)?;
-
-            counts.insert(id.into(), count);
         }
 
-        Ok(counts)
+        Ok((counts, stats))
     }
 }
 
@@ -59,7 +75,7 @@ where
         .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))
 }
 
-fn parse_u64<'a, I>(fields: &mut I) -> io::Result<u64>
+fn parse_f64<'a, I>(fields: &mut I) -> io::Result<f64>
 where
     I: Iterator<Item = &'a str>,
 {
@@ -90,9 +106,45 @@ __alignment_not_unique\t0
         let counts = reader.read_counts()?;
 
         assert_eq!(counts.len(), 3);
-        assert_eq!(counts["AADAT"], 302);
-        assert_eq!(counts["CLN3"], 37);
-        assert_eq!(counts["PAK4"], 145);
+        assert_eq!(counts["AADAT"], 302.0);
+        assert_eq!(counts["CLN3"], 37.0);
+        assert_eq!(counts["PAK4"], 145.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_counts_and_stats() -> io::Result<()> {
+        let data = b"\
+AADAT\t302
+CLN3\t37
+__no_feature\t5
+__ambiguous\t8
+";
+
+        let mut reader = Reader::new(&data[..]);
+        let (counts, stats) = reader.read_counts_and_stats()?;
+
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts["AADAT"], 302.0);
+        assert_eq!(counts["CLN3"], 37.0);
+
+        assert_eq!(
+            stats,
+            [
+                (String::from("__no_feature"), 5.0),
+                (String::from("__ambiguous"), 8.0),
+            ]
+        );
+
+        let data = b"\
+AADAT\t302
+__no_feature\t5
+CLN3\t37
+";
+
+        let mut reader = Reader::new(&data[..]);
+        assert!(reader.read_counts_and_stats().is_err());
 
         Ok(())
     }
@@ -0,0 +1,285 @@
+use std::{
+    cmp,
+    collections::{HashMap, HashSet},
+};
+
+use noodles_gff::record::Strand;
+
+use crate::Feature;
+
+pub const KINDS: [Kind; 3] = [Kind::Exonic, Kind::Intronic, Kind::Spanning];
+
+/// Splicing classification of a record
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Kind {
+    /// only intersects exons of a gene
+    Exonic,
+    /// only intersects introns of a gene
+    Intronic,
+    /// intersects both exons and introns of a gene
+    Spanning,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Exonic => "exonic",
+            Self::Intronic => "intronic",
+            Self::Spanning => "spanning",
+        }
+    }
+}
+
+/// A splicing feature ID
+///
+/// Splicing features are counted as other features, which are identified by
+/// strings, so the ID is encoded as the gene ID and kind name separated by a
+/// tab. Feature IDs cannot contain tabs, so encoded IDs cannot be ambiguous.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Id {
+    gene_id: String,
+    kind: Kind,
+}
+
+impl Id {
+    pub fn new(gene_id: String, kind: Kind) -> Self {
+        Self { gene_id, kind }
+    }
+
+    pub fn gene_id(&self) -> &str {
+        &self.gene_id
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    pub fn encode(&self) -> String {
+        format!("{}\t{}", self.gene_id, self.kind.name())
+    }
+
+    pub fn decode(s: &str) -> Option<Self> {
+        let mut components = s.splitn(2, '\t');
+        let gene_id = components.next()?;
+
+        let kind = match components.next()? {
+            "exonic" => Kind::Exonic,
+            "intronic" => Kind::Intronic,
+            "spanning" => Kind::Spanning,
+            _ => return None,
+        };
+
+        Some(Self::new(gene_id.into(), kind))
+    }
+}
+
+/// Builds the exon and intron features of genes.
+///
+/// The introns of a gene are the gaps between its exons, i.e., the span of its
+/// exons on a reference sequence and strand, excluding the exons. The features
+/// are keyed by encoded exonic and intronic IDs (see [`Id`]).
+pub fn build_features<S>(
+    feature_map: &HashMap<String, Vec<Feature>, S>,
+) -> HashMap<String, Vec<Feature>> {
+    let mut features = HashMap::new();
+
+    for (id, exons) in feature_map {
+        features.insert(Id::new(id.clone(), Kind::Exonic).encode(), exons.clone());
+
+        let introns = build_introns(exons);
+
+        if !introns.is_empty() {
+            features.insert(Id::new(id.clone(), Kind::Intronic).encode(), introns);
+        }
+    }
+
+    features
+}
+
+/// Builds the introns of a gene from its exons.
+pub fn build_introns(exons: &[Feature]) -> Vec<Feature> {
+    let mut exons: Vec<_> = exons.iter().collect();
+    exons.sort_by_key(|exon| exon.start());
+
+    let mut introns = Vec::new();
+    let mut ends: HashMap<(&str, Strand), u64> = HashMap::new();
+
+    for exon in exons {
+        let key = (exon.reference_sequence_name(), exon.strand());
+
+        match ends.get_mut(&key) {
+            Some(end) => {
+                if exon.start() > *end + 1 {
+                    introns.push(Feature::new(
+                        exon.reference_sequence_name().into(),
+                        *end + 1,
+                        exon.start() - 1,
+                        exon.strand(),
+                    ));
+                }
+
+                *end = cmp::max(*end, exon.end());
+            }
+            None => {
+                ends.insert(key, exon.end());
+            }
+        }
+    }
+
+    introns
+}
+
+/// Resolves the splicing features intersected by a record to its splicing kind.
+///
+/// When a record intersects both the exons and introns of a single gene, the
+/// set is replaced with the gene's spanning ID. Otherwise, the set is unchanged
+/// and, e.g., is ambiguous when the record intersects multiple genes.
+pub fn resolve(intersections: HashSet<String>) -> HashSet<String> {
+    if intersections.len() != 2 {
+        return intersections;
+    }
+
+    let ids: Vec<_> = intersections.iter().filter_map(|s| Id::decode(s)).collect();
+
+    match &ids[..] {
+        [a, b] if a.gene_id() == b.gene_id() => {
+            let mut set = HashSet::new();
+            set.insert(Id::new(a.gene_id().into(), Kind::Spanning).encode());
+            set
+        }
+        _ => intersections,
+    }
+}
+
+/// Returns the gene IDs of encoded splicing feature IDs, sorted.
+pub fn gene_ids(ids: &[String]) -> Vec<String> {
+    let mut gene_ids: Vec<_> = ids
+        .iter()
+        .filter_map(|s| Id::decode(s))
+        .filter(|id| id.kind() == Kind::Exonic)
+        .map(|id| id.gene_id)
+        .collect();
+
+    gene_ids.sort();
+
+    gene_ids
+}
+
+/// Returns the counts of a splicing kind by gene ID.
+pub fn kind_counts(counts: &HashMap<String, f64>, kind: Kind) -> HashMap<String, f64> {
+    counts
+        .iter()
+        .filter_map(|(s, &count)| Id::decode(s).map(|id| (id, count)))
+        .filter(|(id, _)| id.kind() == kind)
+        .map(|(id, count)| (id.gene_id, count))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_features() {
+        let feature_map: HashMap<_, _> = vec![(
+            String::from("AADAT"),
+            vec![
+                Feature::new(String::from("sq0"), 41, 50, Strand::Forward),
+                Feature::new(String::from("sq0"), 1, 10, Strand::Forward),
+                Feature::new(String::from("sq0"), 5, 20, Strand::Forward),
+                Feature::new(String::from("sq0"), 8, 12, Strand::Forward),
+                Feature::new(String::from("sq0"), 21, 30, Strand::Forward),
+            ],
+        )]
+        .into_iter()
+        .collect();
+
+        let features = build_features(&feature_map);
+
+        assert_eq!(features.len(), 2);
+        assert_eq!(features["AADAT\texonic"], feature_map["AADAT"]);
+        assert_eq!(
+            features["AADAT\tintronic"],
+            vec![Feature::new(String::from("sq0"), 31, 40, Strand::Forward)]
+        );
+    }
+
+    #[test]
+    fn test_resolve() {
+        fn build_set(ids: &[&str]) -> HashSet<String> {
+            ids.iter().map(|&id| id.into()).collect()
+        }
+
+        assert_eq!(
+            resolve(build_set(&["AADAT\texonic"])),
+            build_set(&["AADAT\texonic"])
+        );
+        assert_eq!(
+            resolve(build_set(&["AADAT\tintronic"])),
+            build_set(&["AADAT\tintronic"])
+        );
+        assert_eq!(
+            resolve(build_set(&["AADAT\texonic", "AADAT\tintronic"])),
+            build_set(&["AADAT\tspanning"])
+        );
+        assert_eq!(
+            resolve(build_set(&["AADAT\texonic", "CLN3\tintronic"])),
+            build_set(&["AADAT\texonic", "CLN3\tintronic"])
+        );
+        assert_eq!(
+            resolve(build_set(&["AADAT\texonic", "CLN3\texonic"])),
+            build_set(&["AADAT\texonic", "CLN3\texonic"])
+        );
+    }
+
+    #[test]
+    fn test_id_decode() {
+        assert_eq!(
+            Id::decode("AADAT\tintronic"),
+            Some(Id::new(String::from("AADAT"), Kind::Intronic))
+        );
+        assert_eq!(Id::decode("AADAT"), None);
+        assert_eq!(Id::decode("AADAT\tunspliced"), None);
+    }
+
+    #[test]
+    fn test_gene_ids() {
+        let ids = [
+            String::from("CLN3\texonic"),
+            String::from("AADAT\tintronic"),
+            String::from("AADAT\texonic"),
+        ];
+
+        assert_eq!(
+            gene_ids(&ids),
+            vec![String::from("AADAT"), String::from("CLN3")]
+        );
+    }
+
+    #[test]
+    fn test_kind_counts() {
+        let counts: HashMap<_, _> = vec![
+            (String::from("AADAT\texonic"), 8.0),
+            (String::from("AADAT\tintronic"), 3.0),
+            (String::from("AADAT\tspanning"), 1.0),
+            (String::from("CLN3\tintronic"), 2.0),
+        ]
+        .into_iter()
+        .collect();
+
+        let actual = kind_counts(&counts, Kind::Exonic);
+        let expected: HashMap<_, _> = vec![(String::from("AADAT"), 8.0)].into_iter().collect();
+        assert_eq!(actual, expected);
+
+        let actual = kind_counts(&counts, Kind::Intronic);
+        let expected: HashMap<_, _> =
+            vec![(String::from("AADAT"), 3.0), (String::from("CLN3"), 2.0)]
+                .into_iter()
+                .collect();
+        assert_eq!(actual, expected);
+
+        let actual = kind_counts(&counts, Kind::Spanning);
+        let expected: HashMap<_, _> = vec![(String::from("AADAT"), 1.0)].into_iter().collect();
+        assert_eq!(actual, expected);
+    }
+}
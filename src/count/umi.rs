@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+
+use noodles_bam as bam;
+
+/// The position of a record used to group UMIs
+///
+/// This is the 5' end of the record on its strand, i.e., the start of a forward
+/// record or the end of a reverse record. For a pair, it is the position of the
+/// first record.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct UmiPosition {
+    reference_sequence_id: i32,
+    position: u64,
+    is_reverse: bool,
+}
+
+impl UmiPosition {
+    pub fn new(reference_sequence_id: i32, position: u64, is_reverse: bool) -> Self {
+        Self {
+            reference_sequence_id,
+            position,
+            is_reverse,
+        }
+    }
+
+    pub fn from_record(record: &bam::Record) -> Self {
+        let reference_sequence_id = record.reference_sequence_id().unwrap_or(-1);
+        let start = i32::from(record.position()) as u64;
+        let is_reverse = record.flags().is_reverse_complemented();
+
+        let position = if is_reverse {
+            let reference_len = record.cigar().reference_len() as u64;
+            start + reference_len.saturating_sub(1)
+        } else {
+            start
+        };
+
+        Self::new(reference_sequence_id, position, is_reverse)
+    }
+}
+
+/// Counts the molecules of a set of UMIs at the same position using the
+/// directional adjacency method (UMI-tools).
+///
+/// A UMI is merged into another when they differ by a single base and the
+/// count of the other is at least twice its count minus one, i.e., it is
+/// likely a sequencing or PCR error of the other. UMIs are visited in
+/// descending order of count, and each one not yet merged is a molecule.
+pub fn count_molecules(umis: &HashMap<String, u64>) -> usize {
+    let mut umis: Vec<_> = umis.iter().map(|(umi, &n)| (umi.as_str(), n)).collect();
+    umis.sort_unstable_by(|(a, m), (b, n)| n.cmp(m).then_with(|| a.cmp(b)));
+
+    let mut visited = vec![false; umis.len()];
+    let mut molecule_count = 0;
+    let mut stack = Vec::new();
+
+    for i in 0..umis.len() {
+        if visited[i] {
+            continue;
+        }
+
+        molecule_count += 1;
+        visited[i] = true;
+        stack.push(i);
+
+        while let Some(j) = stack.pop() {
+            for k in 0..umis.len() {
+                if !visited[k] && is_adjacent(umis[j], umis[k]) {
+                    visited[k] = true;
+                    stack.push(k);
+                }
+            }
+        }
+    }
+
+    molecule_count
+}
+
+fn is_adjacent((a, m): (&str, u64), (b, n): (&str, u64)) -> bool {
+    m + 1 >= 2 * n && hamming_distance(a, b) == Some(1)
+}
+
+fn hamming_distance(a: &str, b: &str) -> Option<usize> {
+    if a.len() == b.len() {
+        Some(a.bytes().zip(b.bytes()).filter(|(x, y)| x != y).count())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_umis(umis: &[(&str, u64)]) -> HashMap<String, u64> {
+        umis.iter().map(|&(umi, n)| (umi.into(), n)).collect()
+    }
+
+    #[test]
+    fn test_count_molecules() {
+        assert_eq!(count_molecules(&HashMap::new()), 0);
+        assert_eq!(count_molecules(&build_umis(&[("ACGT", 8)])), 1);
+
+        // ACGA (3) is an error of ACGT (10), but ACGG (6) is too abundant.
+        let umis = build_umis(&[("ACGT", 10), ("ACGA", 3), ("ACGG", 6), ("TTTT", 1)]);
+        assert_eq!(count_molecules(&umis), 3);
+
+        // Errors are merged transitively: AAAA (8) -> AAAT (4) -> AATT (2).
+        let umis = build_umis(&[("AAAA", 8), ("AAAT", 4), ("AATT", 2)]);
+        assert_eq!(count_molecules(&umis), 1);
+
+        // UMIs of different lengths are never merged.
+        let umis = build_umis(&[("ACGT", 10), ("ACG", 1)]);
+        assert_eq!(count_molecules(&umis), 2);
+    }
+
+    #[test]
+    fn test_hamming_distance() {
+        assert_eq!(hamming_distance("ACGT", "ACGT"), Some(0));
+        assert_eq!(hamming_distance("ACGT", "ACGA"), Some(1));
+        assert_eq!(hamming_distance("ACGT", "TGCA"), Some(4));
+        assert_eq!(hamming_distance("ACGT", "ACG"), None);
+    }
+}
@@ -0,0 +1,96 @@
+use std::{error, fmt, io, str::FromStr};
+
+use noodles_bam as bam;
+
+use crate::alignment::data::get_string_field;
+
+const READ_NAME_UMI_DELIMITER: u8 = b'_';
+
+/// Source of the unique molecular identifier (UMI) of a record
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UmiSource {
+    /// the suffix of the read name after the last `_`, e.g., `ACGTAC` in
+    /// `r0_ACGTAC` (UMI-tools `extract`)
+    ReadName,
+    /// a string (`Z`) BAM data field, e.g., `RX`
+    Tag([u8; 2]),
+}
+
+impl UmiSource {
+    /// Returns the UMI of a record.
+    pub fn umi(self, record: &bam::Record) -> io::Result<Option<String>> {
+        match self {
+            Self::ReadName => Ok(umi_from_read_name(record.read_name())
+                .map(|umi| String::from_utf8_lossy(umi).into_owned())),
+            Self::Tag(tag) => get_string_field(record, &tag),
+        }
+    }
+}
+
+fn umi_from_read_name(read_name: &[u8]) -> Option<&[u8]> {
+    let read_name = match read_name.split_last() {
+        Some((0, rest)) => rest,
+        _ => read_name,
+    };
+
+    let i = read_name
+        .iter()
+        .rposition(|&b| b == READ_NAME_UMI_DELIMITER)?;
+
+    match &read_name[i + 1..] {
+        [] => None,
+        umi => Some(umi),
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid UMI source: {}", self.0)
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl FromStr for UmiSource {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.as_bytes() {
+            b"read-name" => Ok(Self::ReadName),
+            &[a, b] if a.is_ascii_alphabetic() && b.is_ascii_alphanumeric() => {
+                Ok(Self::Tag([a, b]))
+            }
+            _ => Err(ParseError(s.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_umi_from_read_name() {
+        assert_eq!(umi_from_read_name(b"r0_ACGTAC"), Some(&b"ACGTAC"[..]));
+        assert_eq!(umi_from_read_name(b"r0_1_ACGTAC\x00"), Some(&b"ACGTAC"[..]));
+        assert_eq!(umi_from_read_name(b"r0"), None);
+        assert_eq!(umi_from_read_name(b"r0_"), None);
+    }
+
+    #[test]
+    fn test_from_str() -> Result<(), ParseError> {
+        assert_eq!("read-name".parse::<UmiSource>()?, UmiSource::ReadName);
+        assert_eq!("RX".parse::<UmiSource>()?, UmiSource::Tag(*b"RX"));
+        assert_eq!("UB".parse::<UmiSource>()?, UmiSource::Tag(*b"UB"));
+
+        assert!("".parse::<UmiSource>().is_err());
+        assert!("R".parse::<UmiSource>().is_err());
+        assert!("1X".parse::<UmiSource>().is_err());
+        assert!("read_name".parse::<UmiSource>().is_err());
+
+        Ok(())
+    }
+}
@@ -3,7 +3,7 @@ use std::{
     io::{self, Write},
 };
 
-use super::Context;
+use super::{Context, DuplicateMode};
 
 pub struct Writer<W> {
     inner: W,
@@ -21,27 +21,133 @@ where
         &self.inner
     }
 
+    /// Writes the header of a count matrix, i.e., an empty cell followed by the
+    /// sample names.
+    pub fn write_header(&mut self, sample_names: &[String]) -> io::Result<()> {
+        for name in sample_names {
+            write!(self.inner, "\t{}", name)?;
+        }
+
+        writeln!(self.inner)
+    }
+
     pub fn write_counts(
         &mut self,
         ids: &[String],
-        counts: &HashMap<String, u64>,
+        counts: &HashMap<String, f64>,
     ) -> io::Result<()> {
         for id in ids {
-            let count = counts.get(id).unwrap_or(&0);
+            let count = counts.get(id).unwrap_or(&0.0);
             writeln!(self.inner, "{}\t{}", id, count)?;
         }
 
         Ok(())
     }
 
-    pub fn write_stats(&mut self, ctx: &Context) -> io::Result<()> {
+    /// Writes the counts of multiple samples, one column per sample.
+    pub fn write_count_matrix(
+        &mut self,
+        ids: &[String],
+        counts: &[&HashMap<String, f64>],
+    ) -> io::Result<()> {
+        for id in ids {
+            write!(self.inner, "{}", id)?;
+
+            for sample_counts in counts {
+                let count = sample_counts.get(id).unwrap_or(&0.0);
+                write!(self.inner, "\t{}", count)?;
+            }
+
+            writeln!(self.inner)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the statistics of a sample.
+    ///
+    /// The records removed by the duplicate mode are written as `__duplicate`
+    /// when skipping duplicates and `__not_duplicate` when only counting
+    /// duplicates. Neither is written when keeping duplicates.
+    pub fn write_stats(&mut self, ctx: &Context, duplicate_mode: DuplicateMode) -> io::Result<()> {
         writeln!(self.inner, "__no_feature\t{}", ctx.no_feature)?;
         writeln!(self.inner, "__ambiguous\t{}", ctx.ambiguous)?;
         writeln!(self.inner, "__too_low_aQual\t{}", ctx.low_quality)?;
         writeln!(self.inner, "__not_aligned\t{}", ctx.unmapped)?;
         writeln!(self.inner, "__alignment_not_unique\t{}", ctx.nonunique)?;
+
+        match duplicate_mode {
+            DuplicateMode::Keep => {}
+            DuplicateMode::Skip => writeln!(self.inner, "__duplicate\t{}", ctx.duplicate)?,
+            DuplicateMode::Only => writeln!(self.inner, "__not_duplicate\t{}", ctx.not_duplicate)?,
+        }
+
+        if let Some(fraction) = ctx.umi_duplicate_fraction() {
+            writeln!(self.inner, "__umi_duplicate_fraction\t{}", fraction)?;
+        }
+
         Ok(())
     }
+
+    /// Writes the statistics of multiple samples, one column per sample.
+    ///
+    /// See [`Writer::write_stats`].
+    pub fn write_stats_matrix(
+        &mut self,
+        ctxs: &[&Context],
+        duplicate_mode: DuplicateMode,
+    ) -> io::Result<()> {
+        self.write_stats_row("__no_feature", ctxs.iter().map(|ctx| ctx.no_feature))?;
+        self.write_stats_row("__ambiguous", ctxs.iter().map(|ctx| ctx.ambiguous))?;
+        self.write_stats_row("__too_low_aQual", ctxs.iter().map(|ctx| ctx.low_quality))?;
+        self.write_stats_row("__not_aligned", ctxs.iter().map(|ctx| ctx.unmapped))?;
+        self.write_stats_row(
+            "__alignment_not_unique",
+            ctxs.iter().map(|ctx| ctx.nonunique),
+        )?;
+
+        match duplicate_mode {
+            DuplicateMode::Keep => {}
+            DuplicateMode::Skip => {
+                self.write_stats_row("__duplicate", ctxs.iter().map(|ctx| ctx.duplicate))?
+            }
+            DuplicateMode::Only => {
+                self.write_stats_row("__not_duplicate", ctxs.iter().map(|ctx| ctx.not_duplicate))?
+            }
+        }
+
+        if ctxs
+            .iter()
+            .any(|ctx| ctx.umi_duplicate_fraction().is_some())
+        {
+            write!(self.inner, "__umi_duplicate_fraction")?;
+
+            for ctx in ctxs {
+                write!(
+                    self.inner,
+                    "\t{}",
+                    ctx.umi_duplicate_fraction().unwrap_or(0.0)
+                )?;
+            }
+
+            writeln!(self.inner)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_stats_row<I>(&mut self, name: &str, values: I) -> io::Result<()>
+    where
+        I: Iterator<Item = u64>,
+    {
+        write!(self.inner, "{}", name)?;
+
+        for value in values {
+            write!(self.inner, "\t{}", value)?;
+        }
+
+        writeln!(self.inner)
+    }
 }
 
 #[cfg(test)]
@@ -50,10 +156,10 @@ mod tests {
 
     #[test]
     fn test_write_counts() -> io::Result<()> {
-        let counts: HashMap<String, u64> = vec![
-            (String::from("AADAT"), 302),
-            (String::from("CLN3"), 37),
-            (String::from("PAK4"), 145),
+        let counts: HashMap<String, f64> = vec![
+            (String::from("AADAT"), 302.0),
+            (String::from("CLN3"), 37.0),
+            (String::from("PAK4"), 145.0),
         ]
         .into_iter()
         .collect();
@@ -81,17 +187,102 @@ PAK4\t145
         Ok(())
     }
 
+    #[test]
+    fn test_write_counts_with_fractional_counts() -> io::Result<()> {
+        let counts: HashMap<String, f64> = vec![
+            (String::from("AADAT"), 302.5),
+            (String::from("CLN3"), 37.25),
+            (String::from("PAK4"), 145.0),
+        ]
+        .into_iter()
+        .collect();
+
+        let ids = vec![
+            String::from("AADAT"),
+            String::from("CLN3"),
+            String::from("PAK4"),
+        ];
+
+        let mut writer = Writer::new(Vec::new());
+        writer.write_counts(&ids, &counts)?;
+
+        let actual = writer.get_ref();
+        let expected = b"\
+AADAT\t302.5
+CLN3\t37.25
+PAK4\t145
+";
+
+        assert_eq!(&actual[..], &expected[..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_header() -> io::Result<()> {
+        let sample_names = [String::from("sample1"), String::from("sample2")];
+
+        let mut writer = Writer::new(Vec::new());
+        writer.write_header(&sample_names)?;
+
+        let actual = writer.get_ref();
+        let expected = b"\tsample1\tsample2\n";
+
+        assert_eq!(&actual[..], &expected[..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_count_matrix() -> io::Result<()> {
+        let counts1: HashMap<String, f64> = vec![
+            (String::from("AADAT"), 302.0),
+            (String::from("PAK4"), 145.0),
+        ]
+        .into_iter()
+        .collect();
+
+        let counts2: HashMap<String, f64> =
+            vec![(String::from("AADAT"), 8.0), (String::from("CLN3"), 37.5)]
+                .into_iter()
+                .collect();
+
+        let ids = vec![
+            String::from("AADAT"),
+            String::from("CLN3"),
+            String::from("PAK4"),
+        ];
+
+        let mut writer = Writer::new(Vec::new());
+        writer.write_count_matrix(&ids, &[&counts1, &counts2])?;
+
+        let actual = writer.get_ref();
+        let expected = b"\
+AADAT\t302\t8
+CLN3\t0\t37.5
+PAK4\t145\t0
+";
+
+        assert_eq!(&actual[..], &expected[..]);
+
+        Ok(())
+    }
+
     #[test]
     fn test_write_stats() -> io::Result<()> {
-        let mut ctx = Context::default();
-        ctx.no_feature = 735;
-        ctx.ambiguous = 5;
-        ctx.low_quality = 60;
-        ctx.unmapped = 8;
-        ctx.nonunique = 13;
+        let ctx = Context {
+            no_feature: 735,
+            ambiguous: 5,
+            low_quality: 60,
+            unmapped: 8,
+            nonunique: 13,
+            duplicate: 21,
+            not_duplicate: 34,
+            ..Default::default()
+        };
 
         let mut writer = Writer::new(Vec::new());
-        writer.write_stats(&ctx)?;
+        writer.write_stats(&ctx, DuplicateMode::Skip)?;
 
         let actual = writer.get_ref();
         let expected = b"\
@@ -100,6 +291,84 @@ __ambiguous\t5
 __too_low_aQual\t60
 __not_aligned\t8
 __alignment_not_unique\t13
+__duplicate\t21
+";
+
+        assert_eq!(&actual[..], &expected[..]);
+
+        let mut writer = Writer::new(Vec::new());
+        writer.write_stats(&ctx, DuplicateMode::Only)?;
+
+        let actual = writer.get_ref();
+        let expected = b"\
+__no_feature\t735
+__ambiguous\t5
+__too_low_aQual\t60
+__not_aligned\t8
+__alignment_not_unique\t13
+__not_duplicate\t34
+";
+
+        assert_eq!(&actual[..], &expected[..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_stats_with_umi_duplicates() -> io::Result<()> {
+        let ctx = Context {
+            umi_records: 8,
+            umi_duplicates: 2,
+            ..Default::default()
+        };
+
+        let mut writer = Writer::new(Vec::new());
+        writer.write_stats(&ctx, DuplicateMode::Keep)?;
+
+        let actual = writer.get_ref();
+        let expected = b"\
+__no_feature\t0
+__ambiguous\t0
+__too_low_aQual\t0
+__not_aligned\t0
+__alignment_not_unique\t0
+__umi_duplicate_fraction\t0.25
+";
+
+        assert_eq!(&actual[..], &expected[..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_stats_matrix() -> io::Result<()> {
+        let ctx1 = Context {
+            no_feature: 735,
+            ambiguous: 5,
+            low_quality: 60,
+            unmapped: 8,
+            nonunique: 13,
+            ..Default::default()
+        };
+
+        let ctx2 = Context {
+            no_feature: 21,
+            unmapped: 3,
+            duplicate: 34,
+            ..Default::default()
+        };
+
+        let mut writer = Writer::new(Vec::new());
+        writer.write_stats_matrix(&[&ctx1, &ctx2], DuplicateMode::Skip)?;
+
+        let actual = writer.get_ref();
+        let expected = b"\
+__no_feature\t735\t21
+__ambiguous\t5\t0
+__too_low_aQual\t60\t0
+__not_aligned\t8\t3
+__alignment_not_unique\t13\t0
+__duplicate\t0\t34
 ";
 
         assert_eq!(&actual[..], &expected[..]);
@@ -0,0 +1,302 @@
+use std::{
+    cmp,
+    io::{self, Write},
+};
+
+use noodles_gff::record::Strand;
+
+use crate::Feature;
+
+/// The number of percentile bins of a transcript.
+pub const BIN_COUNT: usize = 100;
+
+/// The number of bins at each end of a transcript used for the bias score.
+const BIAS_BIN_COUNT: usize = 20;
+
+/// A transcript built from its exons.
+#[derive(Debug)]
+pub struct Transcript {
+    // (start, end, offset), where offset is the transcript position of start
+    exons: Vec<(u64, u64, u64)>,
+    strand: Strand,
+    len: u64,
+}
+
+impl Transcript {
+    /// Builds a transcript from its exons.
+    ///
+    /// Overlapping exons are merged. This returns `None` if there are no
+    /// exons.
+    pub fn new(exons: &[Feature]) -> Option<Self> {
+        let strand = exons.first()?.strand();
+
+        let mut intervals: Vec<_> = exons.iter().map(|e| (e.start(), e.end())).collect();
+        intervals.sort_unstable();
+
+        let mut merged: Vec<(u64, u64, u64)> = Vec::with_capacity(intervals.len());
+        let mut len = 0;
+
+        for (start, end) in intervals {
+            if let Some(last) = merged.last_mut() {
+                if start <= last.1 + 1 {
+                    if end > last.1 {
+                        len += end - last.1;
+                        last.1 = end;
+                    }
+
+                    continue;
+                }
+            }
+
+            merged.push((start, end, len));
+            len += end - start + 1;
+        }
+
+        Some(Self {
+            exons: merged,
+            strand,
+            len,
+        })
+    }
+
+    pub fn strand(&self) -> Strand {
+        self.strand
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the transcript position (0-based, 5' to 3') of a reference
+    /// sequence position.
+    ///
+    /// This returns `None` if the position is not in an exon.
+    pub fn position(&self, position: u64) -> Option<u64> {
+        let offset = self
+            .exons
+            .iter()
+            .find(|(start, end, _)| (*start..=*end).contains(&position))
+            .map(|(start, _, offset)| offset + (position - start))?;
+
+        if self.strand == Strand::Reverse {
+            Some(self.len - offset - 1)
+        } else {
+            Some(offset)
+        }
+    }
+
+    /// Adds the coverage of an aligned interval to the percentile bins.
+    ///
+    /// Bins are ordered 5' to 3', i.e., reversed for transcripts on the reverse
+    /// strand. Each bin accumulates its mean depth, i.e., the number of covered
+    /// bases divided by the bin width.
+    pub fn add_coverage(&self, bins: &mut [f64], start: u64, end: u64) {
+        let bin_width = self.len as f64 / BIN_COUNT as f64;
+
+        for &(exon_start, exon_end, offset) in &self.exons {
+            let overlap_start = cmp::max(start, exon_start);
+            let overlap_end = cmp::min(end, exon_end);
+
+            if overlap_start > overlap_end {
+                continue;
+            }
+
+            // [a, b) in transcript coordinates (0-based, 5' to 3')
+            let (a, b) = {
+                let a = offset + (overlap_start - exon_start);
+                let b = offset + (overlap_end - exon_start) + 1;
+
+                if self.strand == Strand::Reverse {
+                    (self.len - b, self.len - a)
+                } else {
+                    (a, b)
+                }
+            };
+
+            let (a, b) = (a as f64, b as f64);
+            let first_bin = (a / bin_width) as usize;
+            let last_bin = cmp::min(
+                ((b / bin_width).ceil() as usize).saturating_sub(1),
+                BIN_COUNT - 1,
+            );
+
+            for (i, bin) in bins
+                .iter_mut()
+                .enumerate()
+                .take(last_bin + 1)
+                .skip(first_bin)
+            {
+                let bin_start = i as f64 * bin_width;
+                let bin_end = bin_start + bin_width;
+                let covered = b.min(bin_end) - a.max(bin_start);
+
+                if covered > 0.0 {
+                    *bin += covered / bin_width;
+                }
+            }
+        }
+    }
+}
+
+/// Calculates the 3' bias score of a coverage profile.
+///
+/// This is the mean coverage of the last 20 bins (3') divided by the mean
+/// coverage of the first 20 bins (5'). Uniform coverage is 1, and coverage
+/// biased toward the 3' end, e.g., of degraded RNA, is greater than 1. This
+/// returns `None` if the first bins have no coverage.
+pub fn calculate_bias_score(profile: &[f64]) -> Option<f64> {
+    let five_prime: f64 = profile.iter().take(BIAS_BIN_COUNT).sum();
+    let three_prime: f64 = profile.iter().rev().take(BIAS_BIN_COUNT).sum();
+
+    if five_prime > 0.0 {
+        Some(three_prime / five_prime)
+    } else {
+        None
+    }
+}
+
+/// Writes the coverage profiles of samples, one column per sample.
+///
+/// The first row is a header with the sample names, followed by one row per
+/// bin (1-100). Each profile is scaled by its maximum. The trailer is the bias
+/// score of each sample (`__bias`), which is `NA` if undefined.
+pub fn write_profiles<W>(
+    writer: &mut W,
+    sample_names: &[String],
+    profiles: &[Vec<f64>],
+) -> io::Result<()>
+where
+    W: Write,
+{
+    for name in sample_names {
+        write!(writer, "\t{}", name)?;
+    }
+
+    writeln!(writer)?;
+
+    let maxes: Vec<_> = profiles
+        .iter()
+        .map(|profile| profile.iter().cloned().fold(0.0, f64::max))
+        .collect();
+
+    for i in 0..BIN_COUNT {
+        write!(writer, "{}", i + 1)?;
+
+        for (profile, &max) in profiles.iter().zip(&maxes) {
+            let value = if max > 0.0 { profile[i] / max } else { 0.0 };
+            write!(writer, "\t{:.4}", value)?;
+        }
+
+        writeln!(writer)?;
+    }
+
+    write!(writer, "__bias")?;
+
+    for profile in profiles {
+        match calculate_bias_score(profile) {
+            Some(score) => write!(writer, "\t{:.4}", score)?,
+            None => write!(writer, "\tNA")?,
+        }
+    }
+
+    writeln!(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_exons(strand: Strand) -> Vec<Feature> {
+        vec![
+            Feature::new(String::from("sq0"), 201, 250, strand),
+            Feature::new(String::from("sq0"), 1, 50, strand),
+            Feature::new(String::from("sq0"), 41, 100, strand),
+        ]
+    }
+
+    #[test]
+    fn test_new() {
+        let transcript = Transcript::new(&build_exons(Strand::Forward)).unwrap();
+        assert_eq!(transcript.len(), 150);
+        assert_eq!(transcript.exons, [(1, 100, 0), (201, 250, 100)]);
+
+        assert!(Transcript::new(&[]).is_none());
+    }
+
+    #[test]
+    fn test_position() {
+        let transcript = Transcript::new(&build_exons(Strand::Forward)).unwrap();
+        assert_eq!(transcript.position(1), Some(0));
+        assert_eq!(transcript.position(100), Some(99));
+        assert_eq!(transcript.position(150), None);
+        assert_eq!(transcript.position(201), Some(100));
+
+        let transcript = Transcript::new(&build_exons(Strand::Reverse)).unwrap();
+        assert_eq!(transcript.position(250), Some(0));
+        assert_eq!(transcript.position(201), Some(49));
+        assert_eq!(transcript.position(1), Some(149));
+    }
+
+    #[test]
+    fn test_add_coverage() {
+        let exons = [Feature::new(String::from("sq0"), 1, 200, Strand::Forward)];
+        let transcript = Transcript::new(&exons).unwrap();
+
+        let mut bins = vec![0.0; BIN_COUNT];
+        transcript.add_coverage(&mut bins, 1, 4);
+        assert_eq!(&bins[..3], &[1.0, 1.0, 0.0]);
+
+        let exons = [Feature::new(String::from("sq0"), 1, 200, Strand::Reverse)];
+        let transcript = Transcript::new(&exons).unwrap();
+
+        let mut bins = vec![0.0; BIN_COUNT];
+        transcript.add_coverage(&mut bins, 1, 3);
+        assert_eq!(&bins[97..], &[0.0, 0.5, 1.0]);
+
+        let transcript = Transcript::new(&build_exons(Strand::Forward)).unwrap();
+
+        // 101-200 is intronic.
+        let mut bins = vec![0.0; BIN_COUNT];
+        transcript.add_coverage(&mut bins, 99, 202);
+        assert!((bins[65] - 1.0 / 1.5).abs() < 1e-9);
+        assert!((bins[66] - 1.0).abs() < 1e-9);
+        assert!((bins[67] - 1.0).abs() < 1e-9);
+        assert_eq!(bins.iter().filter(|&&n| n > 0.0).count(), 3);
+    }
+
+    #[test]
+    fn test_calculate_bias_score() {
+        let profile = vec![1.0; BIN_COUNT];
+        assert_eq!(calculate_bias_score(&profile), Some(1.0));
+
+        let profile: Vec<_> = (0..BIN_COUNT).map(|i| i as f64).collect();
+        assert_eq!(calculate_bias_score(&profile), Some(1790.0 / 190.0));
+
+        let profile = vec![0.0; BIN_COUNT];
+        assert!(calculate_bias_score(&profile).is_none());
+    }
+
+    #[test]
+    fn test_write_profiles() -> io::Result<()> {
+        let profiles = vec![vec![2.0; BIN_COUNT], vec![0.0; BIN_COUNT]];
+        let sample_names = [String::from("sample1"), String::from("sample2")];
+
+        let mut buf = Vec::new();
+        write_profiles(&mut buf, &sample_names, &profiles)?;
+
+        let s = String::from_utf8(buf).unwrap();
+        let lines: Vec<_> = s.lines().collect();
+
+        assert_eq!(lines.len(), BIN_COUNT + 2);
+        assert_eq!(lines[0], "\tsample1\tsample2");
+        assert_eq!(lines[1], "1\t1.0000\t0.0000");
+        assert_eq!(lines[100], "100\t1.0000\t0.0000");
+        assert_eq!(lines[101], "__bias\t1.0000\tNA");
+
+        Ok(())
+    }
+}
@@ -1,13 +1,17 @@
-use std::{convert::TryFrom, fs::File, io, path::Path};
+use std::{convert::TryFrom, io};
 
 use interval_tree::IntervalTree;
+use log::{info, warn};
 use noodles_bam as bam;
 use noodles_gff as gff;
 use noodles_sam::{self as sam, header::ReferenceSequences};
 
-use crate::{count::get_tree, Context, Entry, Features, PairPosition, StrandSpecification};
+use crate::{
+    count::get_tree, Context, Entry, Features, PairPosition, StrandSpecification,
+    StrandSpecificationOption,
+};
 
-const MAX_RECORDS: usize = 524_288;
+pub const MAX_RECORDS: usize = 524_288;
 const STRANDEDNESS_THRESHOLD: f64 = 0.75;
 
 #[derive(Debug, Default)]
@@ -131,23 +135,86 @@ fn count_single_end_record(
     Ok(())
 }
 
-pub fn detect_specification<P>(
-    src: P,
+/// Reads the records used for detection.
+///
+/// stdin cannot be reopened, so the records used for detection are kept to be
+/// counted afterward, i.e., chained before the remaining records.
+pub fn read_detection_records<I>(records: &mut I) -> io::Result<Vec<bam::Record>>
+where
+    I: Iterator<Item = io::Result<bam::Record>>,
+{
+    records.take(MAX_RECORDS).collect()
+}
+
+/// Detects the library type and resolves the strand specification option.
+///
+/// The detected library layout and strand specification are logged, and an
+/// input strand specification that does not match the detected strandedness is
+/// warned about.
+pub fn detect_library_type<I>(
+    records: I,
     reference_sequences: &ReferenceSequences,
     features: &Features,
-) -> io::Result<(LibraryLayout, StrandSpecification, f64)>
+    strand_specification_option: StrandSpecificationOption,
+) -> io::Result<(LibraryLayout, StrandSpecification)>
 where
-    P: AsRef<Path>,
+    I: Iterator<Item = io::Result<bam::Record>>,
 {
-    let file = File::open(src)?;
-    let mut reader = bam::Reader::new(file);
-    reader.read_header()?;
-    reader.read_reference_sequences()?;
+    info!("detecting library type");
+
+    let (library_layout, detected_strand_specification, strandedness_confidence) =
+        detect_specification(records, reference_sequences, features)?;
+
+    match library_layout {
+        LibraryLayout::SingleEnd => info!("library layout: single end"),
+        LibraryLayout::PairedEnd => info!("library layout: paired end"),
+    }
+
+    info!(
+        "strand specification: {:?} (confidence: {:.2})",
+        detected_strand_specification, strandedness_confidence
+    );
+
+    let strand_specification =
+        resolve_strand_specification(strand_specification_option, detected_strand_specification);
+
+    if strand_specification != detected_strand_specification {
+        warn!(
+            "input strand specification ({:?}) does not match detected strandedness ({:?})",
+            strand_specification, detected_strand_specification,
+        );
+    }
+
+    Ok((library_layout, strand_specification))
+}
+
+/// Resolves a strand specification option to a strand specification.
+///
+/// `auto` is resolved to the detected strand specification.
+pub fn resolve_strand_specification(
+    strand_specification_option: StrandSpecificationOption,
+    detected_strand_specification: StrandSpecification,
+) -> StrandSpecification {
+    match strand_specification_option {
+        StrandSpecificationOption::None => StrandSpecification::None,
+        StrandSpecificationOption::Forward => StrandSpecification::Forward,
+        StrandSpecificationOption::Reverse => StrandSpecification::Reverse,
+        StrandSpecificationOption::Auto => detected_strand_specification,
+    }
+}
 
+pub fn detect_specification<I>(
+    records: I,
+    reference_sequences: &ReferenceSequences,
+    features: &Features,
+) -> io::Result<(LibraryLayout, StrandSpecification, f64)>
+where
+    I: Iterator<Item = io::Result<bam::Record>>,
+{
     let mut counts = Counts::default();
     let mut _ctx = Context::default();
 
-    for result in reader.records().take(MAX_RECORDS) {
+    for result in records.take(MAX_RECORDS) {
         let record = result?;
         let flags = record.flags();
 
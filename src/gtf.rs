@@ -0,0 +1,410 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead},
+    str::FromStr,
+};
+
+use log::info;
+use noodles_gff as gff;
+
+use crate::{
+    hierarchy::{self, Hierarchy},
+    Feature,
+};
+
+const DELIMITER: char = '\t';
+const COMMENT_PREFIX: char = '#';
+const FIELD_COUNT: usize = 9;
+
+/// A GTF record.
+///
+/// GTF shares the first eight columns with GFF3 but uses a different attributes
+/// syntax, e.g., `gene_id "g0"; transcript_id "t0";`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Record {
+    reference_sequence_name: String,
+    ty: String,
+    start: u64,
+    end: u64,
+    strand: gff::record::Strand,
+    phase: Option<u8>,
+    attributes: Vec<(String, String)>,
+}
+
+impl Record {
+    pub fn reference_sequence_name(&self) -> &str {
+        &self.reference_sequence_name
+    }
+
+    pub fn ty(&self) -> &str {
+        &self.ty
+    }
+
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    pub fn strand(&self) -> gff::record::Strand {
+        self.strand
+    }
+
+    /// Returns the phase of a CDS record, i.e., the number of bases to remove
+    /// from its 5' end to reach the first codon.
+    pub fn phase(&self) -> Option<u8> {
+        self.phase
+    }
+
+    /// Returns the value of the first attribute with the given key.
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl FromStr for Record {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<_> = s.split(DELIMITER).collect();
+
+        if fields.len() != FIELD_COUNT {
+            return Err(invalid_data(format!(
+                "expected {} fields, got {}",
+                FIELD_COUNT,
+                fields.len()
+            )));
+        }
+
+        let start = fields[3]
+            .parse()
+            .map_err(|_| invalid_data(format!("invalid start: {}", fields[3])))?;
+
+        let end = fields[4]
+            .parse()
+            .map_err(|_| invalid_data(format!("invalid end: {}", fields[4])))?;
+
+        let strand = fields[6]
+            .parse()
+            .map_err(|_| invalid_data(format!("invalid strand: {}", fields[6])))?;
+
+        let phase = match fields[7] {
+            "." => None,
+            "0" => Some(0),
+            "1" => Some(1),
+            "2" => Some(2),
+            s => return Err(invalid_data(format!("invalid phase: {}", s))),
+        };
+
+        let attributes = parse_attributes(fields[8])?;
+
+        Ok(Self {
+            reference_sequence_name: fields[0].into(),
+            ty: fields[2].into(),
+            start,
+            end,
+            strand,
+            phase,
+            attributes,
+        })
+    }
+}
+
+/// Parses a GTF attributes field, e.g., `gene_id "g0"; level 2;`.
+///
+/// Values may or may not be quoted. Quoted values may contain `;`.
+fn parse_attributes(s: &str) -> io::Result<Vec<(String, String)>> {
+    split_attributes(s)
+        .into_iter()
+        .map(|entry| entry.trim())
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let mut components = entry.splitn(2, char::is_whitespace);
+
+            let key = components.next().unwrap_or_default();
+            let value = components
+                .next()
+                .map(|v| v.trim().trim_matches('"'))
+                .ok_or_else(|| invalid_data(format!("invalid attribute: {}", entry)))?;
+
+            Ok((key.into(), value.into()))
+        })
+        .collect()
+}
+
+/// Splits a GTF attributes field on `;` outside of quoted values.
+fn split_attributes(s: &str) -> Vec<&str> {
+    let mut entries = Vec::new();
+    let mut start = 0;
+    let mut is_quoted = false;
+
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' => is_quoted = !is_quoted,
+            ';' if !is_quoted => {
+                entries.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    entries.push(&s[start..]);
+
+    entries
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub struct Reader<R> {
+    inner: R,
+}
+
+impl<R> Reader<R>
+where
+    R: BufRead,
+{
+    pub fn new(inner: R) -> Self {
+        Self { inner }
+    }
+
+    pub fn read_record(&mut self) -> io::Result<Option<Record>> {
+        let mut buf = String::new();
+
+        loop {
+            buf.clear();
+
+            if self.inner.read_line(&mut buf)? == 0 {
+                return Ok(None);
+            }
+
+            let line = buf.trim_end_matches(&['\n', '\r'][..]);
+
+            if line.is_empty() || line.starts_with(COMMENT_PREFIX) {
+                continue;
+            }
+
+            return line.parse().map(Some);
+        }
+    }
+}
+
+pub fn read_features<R>(
+    reader: &mut Reader<R>,
+    feature_type: &str,
+    feature_id: &str,
+) -> io::Result<HashMap<String, Vec<Feature>>>
+where
+    R: BufRead,
+{
+    let mut features: HashMap<String, Vec<Feature>> = HashMap::new();
+
+    info!("reading features");
+
+    while let Some(record) = reader.read_record()? {
+        if record.ty() != feature_type {
+            continue;
+        }
+
+        let id = record
+            .attribute(feature_id)
+            .ok_or_else(|| invalid_data(format!("missing attribute '{}'", feature_id)))?;
+
+        let list = features.entry(id.into()).or_default();
+
+        let feature = Feature::new(
+            record.reference_sequence_name().into(),
+            record.start(),
+            record.end(),
+            record.strand(),
+        );
+
+        list.push(feature);
+    }
+
+    info!("read {} unique features", features.len());
+
+    Ok(features)
+}
+
+/// Reads the exon hierarchy of GTF records.
+///
+/// The transcript and gene of an exon are its `transcript_id` and `gene_id`
+/// attributes. The exon ID is its `exon_id` attribute or, otherwise, its
+/// position.
+pub fn read_hierarchy<R>(reader: &mut Reader<R>, feature_type: &str) -> io::Result<Hierarchy>
+where
+    R: BufRead,
+{
+    let mut hierarchies = read_hierarchies(reader, &[feature_type])?;
+    Ok(hierarchies.remove(feature_type).unwrap_or_default())
+}
+
+/// Reads the hierarchies of multiple feature types of GTF records in a single
+/// pass.
+///
+/// Each feature type is read as exons (see [`read_hierarchy`]), and the
+/// hierarchies are keyed by feature type.
+pub fn read_hierarchies<R>(
+    reader: &mut Reader<R>,
+    feature_types: &[&str],
+) -> io::Result<HashMap<String, Hierarchy>>
+where
+    R: BufRead,
+{
+    let mut hierarchies: HashMap<_, _> = feature_types
+        .iter()
+        .map(|&ty| (String::from(ty), Hierarchy::default()))
+        .collect();
+
+    info!("reading feature hierarchy");
+
+    while let Some(record) = reader.read_record()? {
+        let hierarchy = match hierarchies.get_mut(record.ty()) {
+            Some(h) => h,
+            None => continue,
+        };
+
+        let feature = Feature::new(
+            record.reference_sequence_name().into(),
+            record.start(),
+            record.end(),
+            record.strand(),
+        );
+
+        let id = record
+            .attribute("exon_id")
+            .map(|id| id.into())
+            .unwrap_or_else(|| hierarchy::position_id(&feature));
+
+        let transcript_id = record.attribute("transcript_id").map(String::from);
+        let gene_id = record.attribute("gene_id").map(String::from);
+
+        if let Some(phase) = record.phase() {
+            hierarchy.set_phase(&feature, phase);
+        }
+
+        hierarchy.add_exon(id, feature, transcript_id, gene_id);
+    }
+
+    Ok(hierarchies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str() -> io::Result<()> {
+        let s =
+            "sq0\tNOODLES\texon\t8\t13\t.\t-\t.\tgene_id \"g0\"; transcript_id \"t0\"; level 2;";
+        let record: Record = s.parse()?;
+
+        assert_eq!(record.reference_sequence_name(), "sq0");
+        assert_eq!(record.ty(), "exon");
+        assert_eq!(record.start(), 8);
+        assert_eq!(record.end(), 13);
+        assert_eq!(record.strand(), gff::record::Strand::Reverse);
+        assert_eq!(record.phase(), None);
+        assert_eq!(record.attribute("gene_id"), Some("g0"));
+        assert_eq!(record.attribute("transcript_id"), Some("t0"));
+        assert_eq!(record.attribute("level"), Some("2"));
+        assert_eq!(record.attribute("gene_name"), None);
+
+        let s = "sq0\tNOODLES\texon\t8\t13\t.\t-\t.\tgene_id \"g0\"; note \"a; b\"; level 2;";
+        let record: Record = s.parse()?;
+        assert_eq!(record.attribute("gene_id"), Some("g0"));
+        assert_eq!(record.attribute("note"), Some("a; b"));
+        assert_eq!(record.attribute("level"), Some("2"));
+
+        assert!("sq0\tNOODLES\texon\t8\t13".parse::<Record>().is_err());
+        assert!("sq0\tNOODLES\texon\tx\t13\t.\t-\t.\tgene_id \"g0\";"
+            .parse::<Record>()
+            .is_err());
+        assert!("sq0\tNOODLES\texon\t8\t13\t.\t-\t.\tgene_id;"
+            .parse::<Record>()
+            .is_err());
+
+        let record: Record = "sq0\tNOODLES\tCDS\t8\t13\t.\t-\t1\tgene_id \"g0\";".parse()?;
+        assert_eq!(record.phase(), Some(1));
+
+        assert!("sq0\tNOODLES\tCDS\t8\t13\t.\t-\t3\tgene_id \"g0\";"
+            .parse::<Record>()
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_features() -> io::Result<()> {
+        use noodles_gff::record::Strand;
+
+        let data = b"#!genome-build NDLS
+sq0\t.\tgene\t1\t30\t.\t+\t.\tgene_id \"gene0\";
+sq0\t.\texon\t1\t10\t.\t+\t.\tgene_id \"gene0\"; transcript_id \"tx0\";
+sq0\t.\texon\t21\t30\t.\t+\t.\tgene_id \"gene0\"; transcript_id \"tx0\";
+sq1\t.\texon\t41\t50\t.\t-\t.\tgene_id \"gene1\"; transcript_id \"tx1\";
+";
+        let mut reader = Reader::new(&data[..]);
+
+        let features = read_features(&mut reader, "exon", "gene_id")?;
+
+        assert_eq!(features.len(), 2);
+        assert_eq!(
+            features["gene0"],
+            [
+                Feature::new(String::from("sq0"), 1, 10, Strand::Forward),
+                Feature::new(String::from("sq0"), 21, 30, Strand::Forward),
+            ]
+        );
+        assert_eq!(
+            features["gene1"],
+            [Feature::new(String::from("sq1"), 41, 50, Strand::Reverse)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_hierarchy() -> io::Result<()> {
+        use noodles_gff::record::Strand;
+
+        use crate::hierarchy::Level;
+
+        let data = b"\
+sq0\t.\texon\t1\t10\t.\t+\t.\tgene_id \"gene0\"; transcript_id \"tx0\"; exon_id \"exon0\";
+sq0\t.\texon\t1\t10\t.\t+\t.\tgene_id \"gene0\"; transcript_id \"tx1\"; exon_id \"exon0\";
+sq0\t.\texon\t21\t30\t.\t+\t.\tgene_id \"gene0\"; transcript_id \"tx1\";
+";
+        let mut reader = Reader::new(&data[..]);
+        let hierarchy = read_hierarchy(&mut reader, "exon")?;
+
+        let exon0 = Feature::new(String::from("sq0"), 1, 10, Strand::Forward);
+        let exon1 = Feature::new(String::from("sq0"), 21, 30, Strand::Forward);
+
+        let features = hierarchy.features(Level::Exon);
+        assert_eq!(features.len(), 2);
+        assert_eq!(features["exon0"], vec![exon0.clone()]);
+        assert_eq!(features["sq0:21-30"], vec![exon1.clone()]);
+
+        let features = hierarchy.features(Level::Transcript);
+        assert_eq!(features.len(), 2);
+        assert_eq!(features["tx0"], vec![exon0.clone()]);
+
+        let mut features = hierarchy.features(Level::Gene);
+        assert_eq!(features.len(), 1);
+
+        let gene0 = features.get_mut("gene0").unwrap();
+        gene0.sort_by_key(|f| f.start());
+        assert_eq!(gene0, &[exon0, exon1]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,381 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error, fmt,
+    io::{self, BufRead},
+    str::FromStr,
+};
+
+use log::info;
+use noodles_gff::record::Phase;
+
+use crate::Feature;
+
+const PARENT_DELIMITER: char = ',';
+
+/// Feature hierarchy level
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Level {
+    /// gene, i.e., the root of the parent chain
+    Gene,
+    /// transcript, i.e., the parent of an exon
+    Transcript,
+    /// exon
+    Exon,
+}
+
+impl Level {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Gene => "gene",
+            Self::Transcript => "transcript",
+            Self::Exon => "exon",
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid level: {}", self.0)
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl FromStr for Level {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gene" => Ok(Self::Gene),
+            "transcript" => Ok(Self::Transcript),
+            "exon" => Ok(Self::Exon),
+            _ => Err(ParseError(s.into())),
+        }
+    }
+}
+
+/// Exons and the transcripts and genes they belong to.
+#[derive(Debug, Default)]
+pub struct Hierarchy {
+    exons: HashMap<String, Vec<Feature>>,
+    transcripts: HashMap<String, HashSet<String>>,
+    genes: HashMap<String, HashSet<String>>,
+    phases: HashMap<String, u8>,
+}
+
+impl Hierarchy {
+    /// Adds an exon.
+    ///
+    /// An exon can be added multiple times, e.g., when it is shared by
+    /// transcripts. Duplicate intervals are only kept once.
+    pub fn add_exon<I, J>(&mut self, id: String, feature: Feature, transcript_ids: I, gene_ids: J)
+    where
+        I: IntoIterator<Item = String>,
+        J: IntoIterator<Item = String>,
+    {
+        self.transcripts
+            .entry(id.clone())
+            .or_default()
+            .extend(transcript_ids);
+
+        self.genes.entry(id.clone()).or_default().extend(gene_ids);
+
+        let features = self.exons.entry(id).or_default();
+
+        if !features.contains(&feature) {
+            features.push(feature);
+        }
+    }
+
+    /// Sets the phase of a feature, i.e., the number of bases to remove from
+    /// its 5' end to reach the first codon.
+    ///
+    /// Phases are keyed by position, so a feature has the same phase in every
+    /// transcript.
+    pub fn set_phase(&mut self, feature: &Feature, phase: u8) {
+        self.phases.insert(position_id(feature), phase);
+    }
+
+    /// Returns the phase of a feature, if known.
+    pub fn phase(&self, feature: &Feature) -> Option<u8> {
+        self.phases.get(&position_id(feature)).copied()
+    }
+
+    /// Returns the features of each ID at the given level.
+    ///
+    /// The features of a transcript or gene are the exons that belong to it.
+    /// Exons that do not belong to a transcript or gene are not included at
+    /// that level.
+    pub fn features(&self, level: Level) -> HashMap<String, Vec<Feature>> {
+        let parents = match level {
+            Level::Gene => &self.genes,
+            Level::Transcript => &self.transcripts,
+            Level::Exon => return self.exons.clone(),
+        };
+
+        let mut features: HashMap<String, Vec<Feature>> = HashMap::new();
+
+        for (exon_id, exon_features) in &self.exons {
+            let ids = match parents.get(exon_id) {
+                Some(ids) => ids,
+                None => continue,
+            };
+
+            for id in ids {
+                let list = features.entry(id.clone()).or_default();
+
+                for feature in exon_features {
+                    if !list.contains(feature) {
+                        list.push(feature.clone());
+                    }
+                }
+            }
+        }
+
+        features
+    }
+}
+
+/// Reads the exon hierarchy of GFF3 records using the `Parent` attribute.
+///
+/// The transcripts of an exon are its parents, and its genes are the roots of
+/// the parent chain, e.g., exon → mRNA → gene. The exon ID is its `ID`
+/// attribute, its `exon_id` attribute, or, otherwise, its position.
+pub fn read_gff_hierarchy<R>(
+    reader: &mut noodles_gff::Reader<R>,
+    feature_type: &str,
+) -> io::Result<Hierarchy>
+where
+    R: BufRead,
+{
+    let mut hierarchies = read_gff_hierarchies(reader, &[feature_type])?;
+    Ok(hierarchies.remove(feature_type).unwrap_or_default())
+}
+
+/// Reads the hierarchies of multiple feature types of GFF3 records in a single
+/// pass.
+///
+/// Each feature type is read as exons (see [`read_gff_hierarchy`]), and the
+/// hierarchies are keyed by feature type.
+pub fn read_gff_hierarchies<R>(
+    reader: &mut noodles_gff::Reader<R>,
+    feature_types: &[&str],
+) -> io::Result<HashMap<String, Hierarchy>>
+where
+    R: BufRead,
+{
+    let mut parents: HashMap<String, Vec<String>> = HashMap::new();
+    let mut exons = Vec::new();
+
+    info!("reading feature hierarchy");
+
+    for result in reader.records() {
+        let record = result?;
+
+        let attributes = record.attributes();
+        let attribute = |key: &str| {
+            attributes
+                .iter()
+                .find(|e| e.key() == key)
+                .map(|e| e.value())
+        };
+
+        let record_parents: Vec<String> = attribute("Parent")
+            .map(|s| s.split(PARENT_DELIMITER).map(|id| id.into()).collect())
+            .unwrap_or_default();
+
+        if feature_types.contains(&record.ty()) {
+            let feature = Feature::new(
+                record.reference_sequence_name().into(),
+                record.start() as u64,
+                record.end() as u64,
+                record.strand(),
+            );
+
+            let id = attribute("ID")
+                .or_else(|| attribute("exon_id"))
+                .map(|id| id.into())
+                .unwrap_or_else(|| position_id(&feature));
+
+            let phase = match record.phase() {
+                Phase::None => None,
+                Phase::Zero => Some(0),
+                Phase::One => Some(1),
+                Phase::Two => Some(2),
+            };
+
+            exons.push((record.ty().to_string(), id, feature, phase, record_parents));
+        } else if let Some(id) = attribute("ID") {
+            parents.insert(id.into(), record_parents);
+        }
+    }
+
+    let mut hierarchies: HashMap<_, _> = feature_types
+        .iter()
+        .map(|&ty| (String::from(ty), Hierarchy::default()))
+        .collect();
+
+    for (ty, id, feature, phase, transcript_ids) in exons {
+        let gene_ids: HashSet<_> = transcript_ids
+            .iter()
+            .flat_map(|transcript_id| roots(&parents, transcript_id))
+            .collect();
+
+        if let Some(hierarchy) = hierarchies.get_mut(&ty) {
+            if let Some(phase) = phase {
+                hierarchy.set_phase(&feature, phase);
+            }
+
+            hierarchy.add_exon(id, feature, transcript_ids, gene_ids);
+        }
+    }
+
+    Ok(hierarchies)
+}
+
+/// Returns the ID of a feature using its position, e.g., `sq0:1-10`.
+pub fn position_id(feature: &Feature) -> String {
+    format!(
+        "{}:{}-{}",
+        feature.reference_sequence_name(),
+        feature.start(),
+        feature.end()
+    )
+}
+
+/// Returns the roots of the parent chain of an ID.
+///
+/// An ID without parents is its own root. Cycles are not followed.
+fn roots(parents: &HashMap<String, Vec<String>>, id: &str) -> Vec<String> {
+    let mut roots = Vec::new();
+    let mut visited = HashSet::new();
+    let mut stack = vec![id.to_string()];
+
+    while let Some(id) = stack.pop() {
+        if !visited.insert(id.clone()) {
+            continue;
+        }
+
+        match parents.get(&id) {
+            Some(ids) if !ids.is_empty() => stack.extend(ids.iter().cloned()),
+            _ => roots.push(id),
+        }
+    }
+
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles_gff::record::Strand;
+
+    use super::*;
+
+    #[test]
+    fn test_from_str() -> Result<(), ParseError> {
+        assert_eq!("gene".parse::<Level>()?, Level::Gene);
+        assert_eq!("transcript".parse::<Level>()?, Level::Transcript);
+        assert_eq!("exon".parse::<Level>()?, Level::Exon);
+
+        assert!("".parse::<Level>().is_err());
+        assert!("mrna".parse::<Level>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_gff_hierarchy() -> io::Result<()> {
+        let data = b"##gff-version 3
+sq0\t.\tgene\t1\t50\t.\t+\t.\tID=gene0
+sq0\t.\tmRNA\t1\t50\t.\t+\t.\tID=tx0;Parent=gene0
+sq0\t.\tmRNA\t1\t30\t.\t+\t.\tID=tx1;Parent=gene0
+sq0\t.\texon\t1\t10\t.\t+\t.\tID=exon0;Parent=tx0,tx1
+sq0\t.\texon\t21\t30\t.\t+\t.\tParent=tx1
+sq0\t.\texon\t41\t50\t.\t+\t.\tID=exon2;Parent=tx0
+";
+        let mut reader = noodles_gff::Reader::new(&data[..]);
+        let hierarchy = read_gff_hierarchy(&mut reader, "exon")?;
+
+        let exon0 = Feature::new(String::from("sq0"), 1, 10, Strand::Forward);
+        let exon1 = Feature::new(String::from("sq0"), 21, 30, Strand::Forward);
+        let exon2 = Feature::new(String::from("sq0"), 41, 50, Strand::Forward);
+
+        let features = hierarchy.features(Level::Exon);
+        assert_eq!(features.len(), 3);
+        assert_eq!(features["exon0"], vec![exon0.clone()]);
+        assert_eq!(features["sq0:21-30"], vec![exon1.clone()]);
+        assert_eq!(features["exon2"], vec![exon2.clone()]);
+
+        let mut features = hierarchy.features(Level::Transcript);
+        assert_eq!(features.len(), 2);
+
+        let tx0 = features.get_mut("tx0").unwrap();
+        tx0.sort_by_key(|f| f.start());
+        assert_eq!(tx0, &[exon0.clone(), exon2.clone()]);
+
+        let tx1 = features.get_mut("tx1").unwrap();
+        tx1.sort_by_key(|f| f.start());
+        assert_eq!(tx1, &[exon0.clone(), exon1.clone()]);
+
+        let mut features = hierarchy.features(Level::Gene);
+        assert_eq!(features.len(), 1);
+
+        let gene0 = features.get_mut("gene0").unwrap();
+        gene0.sort_by_key(|f| f.start());
+        assert_eq!(gene0, &[exon0, exon1, exon2]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_gff_hierarchies() -> io::Result<()> {
+        let data = b"##gff-version 3
+sq0\t.\tgene\t1\t50\t.\t+\t.\tID=gene0
+sq0\t.\tmRNA\t1\t50\t.\t+\t.\tID=tx0;Parent=gene0
+sq0\t.\texon\t1\t20\t.\t+\t.\tID=exon0;Parent=tx0
+sq0\t.\tCDS\t11\t20\t.\t+\t2\tID=cds0;Parent=tx0
+sq0\t.\texon\t41\t50\t.\t+\t.\tID=exon1;Parent=tx0
+";
+        let mut reader = noodles_gff::Reader::new(&data[..]);
+        let hierarchies = read_gff_hierarchies(&mut reader, &["exon", "CDS", "five_prime_UTR"])?;
+
+        assert_eq!(hierarchies.len(), 3);
+
+        let features = hierarchies["exon"].features(Level::Gene);
+        assert_eq!(features["gene0"].len(), 2);
+
+        let cds0 = Feature::new(String::from("sq0"), 11, 20, Strand::Forward);
+        let features = hierarchies["CDS"].features(Level::Exon);
+        assert_eq!(features["cds0"], vec![cds0.clone()]);
+        assert_eq!(hierarchies["CDS"].phase(&cds0), Some(2));
+
+        let exon0 = Feature::new(String::from("sq0"), 1, 20, Strand::Forward);
+        assert_eq!(hierarchies["exon"].phase(&exon0), None);
+
+        assert!(hierarchies["five_prime_UTR"]
+            .features(Level::Exon)
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_roots() {
+        let parents: HashMap<String, Vec<String>> = vec![
+            (String::from("tx0"), vec![String::from("gene0")]),
+            (String::from("gene0"), Vec::new()),
+            (String::from("a"), vec![String::from("b")]),
+            (String::from("b"), vec![String::from("a")]),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(roots(&parents, "tx0"), [String::from("gene0")]);
+        assert_eq!(roots(&parents, "gene0"), [String::from("gene0")]);
+        assert_eq!(roots(&parents, "gene1"), [String::from("gene1")]);
+        assert!(roots(&parents, "a").is_empty());
+    }
+}
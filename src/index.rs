@@ -0,0 +1,146 @@
+pub mod csi;
+
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{self, Read, Seek},
+    iter,
+    path::{Path, PathBuf},
+};
+
+use noodles::Region;
+use noodles_bam::{self as bam, bai};
+use noodles_sam::header::ReferenceSequences;
+
+const BAI_MAGIC_NUMBER: &[u8] = b"BAI\x01";
+const GZIP_MAGIC_NUMBER: &[u8] = &[0x1f, 0x8b];
+
+pub type Query<'a> = Box<dyn Iterator<Item = io::Result<bam::Record>> + 'a>;
+
+/// A BAM index
+pub enum Index {
+    /// BAM index (BAI)
+    Bai(bai::Index),
+    /// coordinate-sorted index (CSI)
+    Csi(csi::Index),
+}
+
+/// Finds the index of a BAM file.
+///
+/// The candidates are, in order, `<src>.bai`, `<src>` with its extension
+/// replaced by `.bai`, and `<src>.csi`, e.g., `sample.bam.bai`, `sample.bai`,
+/// and `sample.bam.csi`.
+pub fn find<P>(src: P) -> Option<PathBuf>
+where
+    P: AsRef<Path>,
+{
+    let src = src.as_ref();
+
+    let candidates = [
+        push_extension(src, "bai"),
+        src.with_extension("bai"),
+        push_extension(src, "csi"),
+    ];
+
+    candidates.iter().find(|path| path.is_file()).cloned()
+}
+
+fn push_extension(src: &Path, extension: &str) -> PathBuf {
+    let mut s = OsString::from(src);
+    s.push(".");
+    s.push(extension);
+    PathBuf::from(s)
+}
+
+/// Reads a BAI or CSI file.
+///
+/// The index format is detected by its magic number: BAI files are
+/// uncompressed, and CSI files are BGZF-compressed.
+pub fn read<P>(src: P) -> io::Result<Index>
+where
+    P: AsRef<Path>,
+{
+    let src = src.as_ref();
+
+    let mut magic = [0; 4];
+    File::open(src)?.read_exact(&mut magic)?;
+
+    if magic == BAI_MAGIC_NUMBER {
+        bai::read(src).map(Index::Bai)
+    } else if magic.starts_with(GZIP_MAGIC_NUMBER) {
+        csi::read(src).map(Index::Csi)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid index format: expected BAI or CSI",
+        ))
+    }
+}
+
+/// Returns an iterator over all records of a reference sequence.
+pub fn query<'a, R>(
+    reader: &'a mut bam::Reader<R>,
+    reference_sequences: &ReferenceSequences,
+    index: &Index,
+    reference_sequence_name: &str,
+) -> io::Result<Query<'a>>
+where
+    R: Read + Seek,
+{
+    let (reference_sequence_id, _, reference_sequence) = reference_sequences
+        .get_full(reference_sequence_name)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid reference sequence name",
+            )
+        })?;
+
+    match index {
+        Index::Bai(index) => {
+            let region =
+                Region::mapped(reference_sequence_name, 1, reference_sequence.len() as u64);
+
+            let query = reader.query(reference_sequences, index, &region)?;
+
+            Ok(Box::new(query))
+        }
+        Index::Csi(index) => {
+            let pos = match index.first_record_position(reference_sequence_id) {
+                Some(pos) => pos,
+                None => return Ok(Box::new(iter::empty())),
+            };
+
+            reader.seek(pos)?;
+
+            // Records are coordinate-sorted, so all records of the reference
+            // sequence follow the first one.
+            let id = reference_sequence_id as i32;
+
+            let records = reader.records().take_while(move |result| match result {
+                Ok(record) => *record.reference_sequence_id() == Some(id),
+                Err(_) => true,
+            });
+
+            Ok(Box::new(records))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_extension() {
+        assert_eq!(
+            push_extension(Path::new("sample.bam"), "bai"),
+            PathBuf::from("sample.bam.bai")
+        );
+
+        assert_eq!(
+            push_extension(Path::new("sample.bam"), "csi"),
+            PathBuf::from("sample.bam.csi")
+        );
+    }
+}
@@ -0,0 +1,308 @@
+use std::{
+    convert::TryFrom,
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+use flate2::read::MultiGzDecoder;
+use noodles_bgzf as bgzf;
+
+const MAGIC_NUMBER: &[u8] = b"CSI\x01";
+
+/// A coordinate-sorted index (CSI).
+#[derive(Debug)]
+pub struct Index {
+    min_shift: i32,
+    depth: i32,
+    references: Vec<Reference>,
+}
+
+impl Index {
+    pub fn min_shift(&self) -> i32 {
+        self.min_shift
+    }
+
+    pub fn depth(&self) -> i32 {
+        self.depth
+    }
+
+    pub fn references(&self) -> &[Reference] {
+        &self.references
+    }
+
+    /// Returns the start position of the first record of a reference sequence.
+    ///
+    /// This is the smallest chunk start of all bins of the reference sequence,
+    /// excluding the metadata pseudo-bin. `None` is returned if the reference
+    /// sequence has no records.
+    pub fn first_record_position(
+        &self,
+        reference_sequence_id: usize,
+    ) -> Option<bgzf::VirtualPosition> {
+        let bin_count = self.bin_count();
+
+        self.references
+            .get(reference_sequence_id)?
+            .bins
+            .iter()
+            .filter(|bin| u64::from(bin.id) < bin_count)
+            .flat_map(|bin| bin.chunks.iter())
+            .map(|chunk| chunk.start)
+            .min()
+            .map(bgzf::VirtualPosition::from)
+    }
+
+    /// Returns the number of bins in the binning scheme.
+    ///
+    /// The metadata pseudo-bin is the bin with the ID `bin_count + 1`.
+    fn bin_count(&self) -> u64 {
+        ((1 << ((self.depth as u64 + 1) * 3)) - 1) / 7
+    }
+}
+
+#[derive(Debug)]
+pub struct Reference {
+    bins: Vec<Bin>,
+}
+
+impl Reference {
+    pub fn bins(&self) -> &[Bin] {
+        &self.bins
+    }
+}
+
+#[derive(Debug)]
+pub struct Bin {
+    id: u32,
+    chunks: Vec<Chunk>,
+}
+
+impl Bin {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+}
+
+/// A range of virtual positions.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Chunk {
+    start: u64,
+    end: u64,
+}
+
+impl Chunk {
+    pub fn start(&self) -> bgzf::VirtualPosition {
+        bgzf::VirtualPosition::from(self.start)
+    }
+
+    pub fn end(&self) -> bgzf::VirtualPosition {
+        bgzf::VirtualPosition::from(self.end)
+    }
+}
+
+/// Reads a BGZF-compressed CSI file.
+pub fn read<P>(src: P) -> io::Result<Index>
+where
+    P: AsRef<Path>,
+{
+    let mut reader = File::open(src).map(MultiGzDecoder::new)?;
+    read_index(&mut reader)
+}
+
+fn read_index<R>(reader: &mut R) -> io::Result<Index>
+where
+    R: Read,
+{
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+
+    if magic != MAGIC_NUMBER {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid CSI header",
+        ));
+    }
+
+    let min_shift = read_i32(reader)?;
+    let depth = read_i32(reader)?;
+
+    if !(0..=10).contains(&depth) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid CSI depth: {}", depth),
+        ));
+    }
+
+    let l_aux = read_len(reader)?;
+    io::copy(&mut reader.take(l_aux as u64), &mut io::sink())?;
+
+    let n_ref = read_len(reader)?;
+    let mut references = Vec::with_capacity(n_ref);
+
+    for _ in 0..n_ref {
+        let reference = read_reference(reader)?;
+        references.push(reference);
+    }
+
+    // The trailing number of unplaced unmapped records (n_no_coor) is optional
+    // and unused.
+
+    Ok(Index {
+        min_shift,
+        depth,
+        references,
+    })
+}
+
+fn read_reference<R>(reader: &mut R) -> io::Result<Reference>
+where
+    R: Read,
+{
+    let n_bin = read_len(reader)?;
+    let mut bins = Vec::with_capacity(n_bin);
+
+    for _ in 0..n_bin {
+        let id = read_u32(reader)?;
+        // loffset
+        read_u64(reader)?;
+
+        let n_chunk = read_len(reader)?;
+        let mut chunks = Vec::with_capacity(n_chunk);
+
+        for _ in 0..n_chunk {
+            let start = read_u64(reader)?;
+            let end = read_u64(reader)?;
+            chunks.push(Chunk { start, end });
+        }
+
+        bins.push(Bin { id, chunks });
+    }
+
+    Ok(Reference { bins })
+}
+
+fn read_len<R>(reader: &mut R) -> io::Result<usize>
+where
+    R: Read,
+{
+    read_i32(reader)
+        .and_then(|n| usize::try_from(n).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)))
+}
+
+fn read_i32<R>(reader: &mut R) -> io::Result<i32>
+where
+    R: Read,
+{
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(i32::from_le_bytes(buf))
+}
+
+fn read_u32<R>(reader: &mut R) -> io::Result<u32>
+where
+    R: Read,
+{
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R>(reader: &mut R) -> io::Result<u64>
+where
+    R: Read,
+{
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_data() -> Vec<u8> {
+        let mut data = Vec::new();
+
+        data.extend_from_slice(MAGIC_NUMBER);
+        data.extend_from_slice(&14i32.to_le_bytes()); // min_shift
+        data.extend_from_slice(&5i32.to_le_bytes()); // depth
+        data.extend_from_slice(&2i32.to_le_bytes()); // l_aux
+        data.extend_from_slice(&[0, 0]); // aux
+        data.extend_from_slice(&2i32.to_le_bytes()); // n_ref
+
+        // reference 0
+        data.extend_from_slice(&2i32.to_le_bytes()); // n_bin
+
+        data.extend_from_slice(&4681u32.to_le_bytes()); // bin
+        data.extend_from_slice(&0u64.to_le_bytes()); // loffset
+        data.extend_from_slice(&1i32.to_le_bytes()); // n_chunk
+        data.extend_from_slice(&(89u64 << 16).to_le_bytes()); // chunk_beg
+        data.extend_from_slice(&(144u64 << 16).to_le_bytes()); // chunk_end
+
+        data.extend_from_slice(&37450u32.to_le_bytes()); // bin (pseudo-bin)
+        data.extend_from_slice(&0u64.to_le_bytes()); // loffset
+        data.extend_from_slice(&2i32.to_le_bytes()); // n_chunk
+        data.extend_from_slice(&8u64.to_le_bytes()); // ref_beg
+        data.extend_from_slice(&(144u64 << 16).to_le_bytes()); // ref_end
+        data.extend_from_slice(&5u64.to_le_bytes()); // n_mapped
+        data.extend_from_slice(&0u64.to_le_bytes()); // n_unmapped
+
+        // reference 1
+        data.extend_from_slice(&0i32.to_le_bytes()); // n_bin
+
+        data.extend_from_slice(&0u64.to_le_bytes()); // n_no_coor
+
+        data
+    }
+
+    #[test]
+    fn test_read_index() -> io::Result<()> {
+        let data = build_data();
+        let index = read_index(&mut &data[..])?;
+
+        assert_eq!(index.min_shift(), 14);
+        assert_eq!(index.depth(), 5);
+        assert_eq!(index.references().len(), 2);
+
+        let bins = index.references()[0].bins();
+        assert_eq!(bins.len(), 2);
+        assert_eq!(bins[0].id(), 4681);
+        assert_eq!(
+            bins[0].chunks(),
+            &[Chunk {
+                start: 89 << 16,
+                end: 144 << 16
+            }]
+        );
+
+        assert!(index.references()[1].bins().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_index_with_invalid_magic_number() {
+        let data = b"BAI\x01";
+        assert!(read_index(&mut &data[..]).is_err());
+    }
+
+    #[test]
+    fn test_first_record_position() -> io::Result<()> {
+        let data = build_data();
+        let index = read_index(&mut &data[..])?;
+
+        assert_eq!(
+            index.first_record_position(0),
+            Some(bgzf::VirtualPosition::from(89 << 16))
+        );
+        assert_eq!(index.first_record_position(1), None);
+        assert_eq!(index.first_record_position(2), None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+mod writer;
+
+pub use self::writer::Writer;
+
+use std::{cmp, collections::HashMap, ops::RangeInclusive};
+
+use noodles_bam as bam;
+use noodles_gff as gff;
+use noodles_sam as sam;
+
+use crate::Feature;
+
+/// Splice junction strand
+///
+/// These are the same as STAR's strand values: 0 (undefined), 1 (+), and 2
+/// (-).
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Strand {
+    Undefined,
+    Forward,
+    Reverse,
+}
+
+impl From<gff::record::Strand> for Strand {
+    fn from(strand: gff::record::Strand) -> Self {
+        match strand {
+            gff::record::Strand::Forward => Self::Forward,
+            gff::record::Strand::Reverse => Self::Reverse,
+            _ => Self::Undefined,
+        }
+    }
+}
+
+impl From<Strand> for u8 {
+    fn from(strand: Strand) -> Self {
+        match strand {
+            Strand::Undefined => 0,
+            Strand::Forward => 1,
+            Strand::Reverse => 2,
+        }
+    }
+}
+
+/// An intron spanned by a spliced alignment.
+///
+/// The start and end are the first and last bases of the intron (1-based).
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Junction {
+    reference_sequence_id: usize,
+    start: u64,
+    end: u64,
+    strand: Strand,
+}
+
+impl Junction {
+    pub fn new(reference_sequence_id: usize, start: u64, end: u64, strand: Strand) -> Self {
+        Self {
+            reference_sequence_id,
+            start,
+            end,
+            strand,
+        }
+    }
+
+    pub fn reference_sequence_id(&self) -> usize {
+        self.reference_sequence_id
+    }
+
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    pub fn strand(&self) -> Strand {
+        self.strand
+    }
+}
+
+/// Read counts of a junction
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Counts {
+    /// number of uniquely mapped reads that span the junction
+    pub unique: u64,
+    /// number of multimapped reads (BAM data tag NH > 1) that span the junction
+    pub multi: u64,
+    /// maximum overhang of the spanning reads
+    pub max_overhang: u64,
+}
+
+impl Counts {
+    pub fn add(&mut self, is_nonunique: bool, overhang: u64) {
+        if is_nonunique {
+            self.multi += 1;
+        } else {
+            self.unique += 1;
+        }
+
+        self.max_overhang = cmp::max(self.max_overhang, overhang);
+    }
+}
+
+/// Returns the introns spanned by an alignment and their overhangs.
+///
+/// An intron is a skipped region (CIGAR `N`) of the reference. Its overhang is
+/// the smaller of the number of aligned bases in the blocks on either side of
+/// it, where blocks are separated by introns.
+pub fn find_introns(
+    cigar: &bam::record::Cigar,
+    initial_start: u64,
+) -> Vec<(RangeInclusive<u64>, u64)> {
+    use sam::record::cigar::op::Kind;
+
+    let mut introns = Vec::new();
+    let mut block_lens = vec![0];
+    let mut position = initial_start;
+
+    for op in cigar.ops() {
+        let len = u64::from(op.len());
+
+        match op.kind() {
+            Kind::Match | Kind::SeqMatch | Kind::SeqMismatch => {
+                if let Some(block_len) = block_lens.last_mut() {
+                    *block_len += len;
+                }
+
+                position += len;
+            }
+            Kind::Deletion => position += len,
+            Kind::Skip => {
+                introns.push(position..=position + len - 1);
+                block_lens.push(0);
+                position += len;
+            }
+            _ => {}
+        }
+    }
+
+    introns
+        .into_iter()
+        .zip(block_lens.windows(2))
+        .map(|(intron, lens)| (intron, cmp::min(lens[0], lens[1])))
+        .collect()
+}
+
+/// Builds the set of annotated introns from transcripts.
+///
+/// An annotated intron is the gap between consecutive exons of a transcript.
+pub fn build_annotated_introns<S>(
+    transcripts: &HashMap<String, Vec<Feature>, S>,
+) -> HashMap<(String, u64, u64), Strand> {
+    let mut introns = HashMap::new();
+
+    for exons in transcripts.values() {
+        let mut exons: Vec<_> = exons.iter().collect();
+        exons.sort_by(|a, b| {
+            a.reference_sequence_name()
+                .cmp(b.reference_sequence_name())
+                .then_with(|| a.start().cmp(&b.start()))
+        });
+
+        for pair in exons.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+
+            if a.reference_sequence_name() != b.reference_sequence_name()
+                || b.start() <= a.end() + 1
+            {
+                continue;
+            }
+
+            let key = (
+                a.reference_sequence_name().into(),
+                a.end() + 1,
+                b.start() - 1,
+            );
+            introns.insert(key, Strand::from(a.strand()));
+        }
+    }
+
+    introns
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles_bam::record::cigar;
+    use noodles_sam::record::cigar::op;
+
+    use super::*;
+
+    #[test]
+    fn test_find_introns() {
+        let ops = [
+            u32::from(cigar::Op::new(op::Kind::SoftClip, 2)).to_le_bytes(),
+            u32::from(cigar::Op::new(op::Kind::Match, 5)).to_le_bytes(),
+            u32::from(cigar::Op::new(op::Kind::Skip, 100)).to_le_bytes(),
+            u32::from(cigar::Op::new(op::Kind::Match, 3)).to_le_bytes(),
+            u32::from(cigar::Op::new(op::Kind::Deletion, 1)).to_le_bytes(),
+            u32::from(cigar::Op::new(op::Kind::Match, 4)).to_le_bytes(),
+            u32::from(cigar::Op::new(op::Kind::Skip, 50)).to_le_bytes(),
+            u32::from(cigar::Op::new(op::Kind::Match, 2)).to_le_bytes(),
+        ];
+        let raw_cigar: Vec<u8> = ops.iter().flatten().copied().collect();
+        let cigar = bam::record::Cigar::new(&raw_cigar);
+
+        assert_eq!(find_introns(&cigar, 1), [(6..=105, 5), (114..=163, 2)]);
+
+        let raw_cigar = u32::from(cigar::Op::new(op::Kind::Match, 8)).to_le_bytes();
+        let cigar = bam::record::Cigar::new(&raw_cigar);
+        assert!(find_introns(&cigar, 1).is_empty());
+    }
+
+    #[test]
+    fn test_build_annotated_introns() {
+        use gff::record::Strand as GffStrand;
+
+        let transcripts: HashMap<_, _> = vec![
+            (
+                String::from("tx0"),
+                vec![
+                    Feature::new(String::from("sq0"), 41, 50, GffStrand::Reverse),
+                    Feature::new(String::from("sq0"), 1, 10, GffStrand::Reverse),
+                    Feature::new(String::from("sq0"), 21, 30, GffStrand::Reverse),
+                ],
+            ),
+            (
+                String::from("tx1"),
+                vec![
+                    Feature::new(String::from("sq0"), 1, 10, GffStrand::Reverse),
+                    Feature::new(String::from("sq0"), 11, 20, GffStrand::Reverse),
+                ],
+            ),
+        ]
+        .into_iter()
+        .collect();
+
+        let introns = build_annotated_introns(&transcripts);
+
+        assert_eq!(introns.len(), 2);
+        assert_eq!(
+            introns.get(&(String::from("sq0"), 11, 20)),
+            Some(&Strand::Reverse)
+        );
+        assert_eq!(
+            introns.get(&(String::from("sq0"), 31, 40)),
+            Some(&Strand::Reverse)
+        );
+    }
+
+    #[test]
+    fn test_add() {
+        let mut counts = Counts::default();
+
+        counts.add(false, 5);
+        counts.add(true, 8);
+        counts.add(false, 3);
+
+        assert_eq!(
+            counts,
+            Counts {
+                unique: 2,
+                multi: 1,
+                max_overhang: 8,
+            }
+        );
+    }
+}
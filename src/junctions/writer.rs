@@ -0,0 +1,91 @@
+use std::io::{self, Write};
+
+use super::{Counts, Junction};
+
+// STAR does not detect the intron motif without the reference sequence, so it
+// is always written as non-canonical.
+const UNKNOWN_MOTIF: u8 = 0;
+
+/// A splice junction writer.
+///
+/// The output is compatible with STAR's `SJ.out.tab`.
+pub struct Writer<W> {
+    inner: W,
+}
+
+impl<W> Writer<W>
+where
+    W: Write,
+{
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Writes a junction.
+    ///
+    /// The columns are the reference sequence name, the first and last bases of
+    /// the intron, the strand (0: undefined, 1: +, 2: -), the intron motif,
+    /// whether the junction is annotated (0 or 1), the number of uniquely and
+    /// multimapped reads that span the junction, and the maximum overhang.
+    pub fn write_junction(
+        &mut self,
+        reference_sequence_name: &str,
+        junction: &Junction,
+        is_annotated: bool,
+        counts: &Counts,
+    ) -> io::Result<()> {
+        writeln!(
+            self.inner,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            reference_sequence_name,
+            junction.start(),
+            junction.end(),
+            u8::from(junction.strand()),
+            UNKNOWN_MOTIF,
+            u8::from(is_annotated),
+            counts.unique,
+            counts.multi,
+            counts.max_overhang,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::junctions::Strand;
+
+    #[test]
+    fn test_write_junction() -> io::Result<()> {
+        let mut writer = Writer::new(Vec::new());
+
+        let junction = Junction::new(0, 11, 20, Strand::Reverse);
+        let counts = Counts {
+            unique: 8,
+            multi: 2,
+            max_overhang: 13,
+        };
+        writer.write_junction("sq0", &junction, true, &counts)?;
+
+        let junction = Junction::new(0, 31, 40, Strand::Undefined);
+        let counts = Counts {
+            unique: 1,
+            multi: 0,
+            max_overhang: 5,
+        };
+        writer.write_junction("sq0", &junction, false, &counts)?;
+
+        let expected = b"\
+sq0\t11\t20\t2\t0\t1\t8\t2\t13
+sq0\t31\t40\t0\t0\t0\t1\t0\t5
+";
+
+        assert_eq!(&writer.get_ref()[..], &expected[..]);
+
+        Ok(())
+    }
+}
@@ -6,14 +6,27 @@ pub use self::{
     record_pairs::{PairPosition, RecordPairs},
 };
 
+pub mod alignment;
+pub mod annotations;
+pub mod ase;
+mod bed;
+pub mod bins;
 pub mod commands;
 pub mod count;
+pub mod coverage;
 pub mod detect;
 pub mod feature;
-mod gff;
+mod gtf;
+pub mod hierarchy;
+pub mod index;
+pub mod junctions;
 mod match_intervals;
 pub mod normalization;
+pub mod qc;
 pub mod record_pairs;
+pub mod ribo;
+mod saf;
+pub mod single_cell;
 
 use std::{
     collections::{HashMap, HashSet},
@@ -34,6 +47,16 @@ pub enum StrandSpecification {
     Reverse,
 }
 
+/// Returns whether a record is on the reverse strand of the transcript it
+/// originates from.
+///
+/// The second read of a pair is on the opposite strand of the first.
+pub fn is_reverse(record: &noodles_bam::Record, strand_specification: StrandSpecification) -> bool {
+    let flags = record.flags();
+    let is_reverse = flags.is_reverse_complemented() ^ (flags.is_paired() && flags.is_read_2());
+    is_reverse ^ (strand_specification == StrandSpecification::Reverse)
+}
+
 pub fn read_features<R>(
     reader: &mut noodles_gff::Reader<R>,
     feature_type: &str,
@@ -1,7 +1,14 @@
-use clap::{crate_name, value_t, App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::path::Path;
+
+use clap::{crate_name, value_t, values_t, App, AppSettings, Arg, ArgMatches, SubCommand};
 use git_testament::{git_testament, render_testament};
 use log::LevelFilter;
-use noodles_squab::{commands, count::Filter, normalization, StrandSpecificationOption};
+use noodles_squab::{
+    annotations, commands,
+    count::{self, Filter},
+    hierarchy::Level,
+    normalization, StrandSpecificationOption,
+};
 
 git_testament!(TESTAMENT);
 
@@ -23,6 +30,543 @@ fn match_args_from_env() -> clap::ArgMatches<'static> {
                 .long("with-nonunique-records")
                 .help("Count nonunique records (BAM data tag NH > 1)"),
         )
+        .arg(
+            Arg::with_name("nonunique-mode")
+                .long("nonunique-mode")
+                .value_name("str")
+                .help("Distribute nonunique records (BAM data tag NH > 1) across their alignments. Implies --with-secondary-records.")
+                .possible_values(&["uniform", "em"])
+                .conflicts_with("with-nonunique-records"),
+        )
+        .arg(
+            Arg::with_name("umi")
+                .long("umi")
+                .value_name("str")
+                .help("Deduplicate records by UMI, read from the read name (read-name) or a BAM data tag, e.g., RX"),
+        )
+        .arg(
+            Arg::with_name("mode")
+                .long("mode")
+                .value_name("str")
+                .help("Overlap resolution mode")
+                .possible_values(&["union", "intersection-strict", "intersection-nonempty"])
+                .default_value("union"),
+        )
+        .arg(
+            Arg::with_name("ambiguous-mode")
+                .long("ambiguous-mode")
+                .value_name("str")
+                .help("Assign records that intersect multiple features to each feature")
+                .possible_values(&["fractional", "all"]),
+        )
+        .arg(
+            Arg::with_name("strand-specification")
+                .long("strand-specification")
+                .value_name("str")
+                .help("Strand specification")
+                .possible_values(&["none", "forward", "reverse", "auto"])
+                .default_value("auto"),
+        )
+        .arg(
+            Arg::with_name("feature-type")
+                .short("t")
+                .long("feature-type")
+                .value_name("str")
+                .help("Feature type to count")
+                .default_value("exon"),
+        )
+        .arg(
+            Arg::with_name("id")
+                .short("i")
+                .long("id")
+                .value_name("str")
+                .help("Feature attribute to use as the feature identity")
+                .default_value("gene_id"),
+        )
+        .arg(
+            Arg::with_name("fragment-mode")
+                .long("fragment-mode")
+                .value_name("str")
+                .help("Positions of a record or pair of records to intersect with features")
+                .possible_values(&["alignment", "span", "cut-sites"])
+                .default_value("alignment"),
+        )
+        .arg(
+            Arg::with_name("read-position")
+                .long("read-position")
+                .value_name("str")
+                .help("Assign records by a single position of the fragment instead of its fragment mode")
+                .possible_values(&["five-prime", "three-prime"]),
+        )
+        .arg(
+            Arg::with_name("read-shift")
+                .long("read-shift")
+                .value_name("int")
+                .help("Shift the read position toward the 3' end of the fragment")
+                .allow_hyphen_values(true)
+                .requires("read-position"),
+        )
+        .arg(
+            Arg::with_name("levels")
+                .long("levels")
+                .value_name("str")
+                .help("Count features at each level of the feature hierarchy")
+                .possible_values(&["gene", "transcript", "exon"])
+                .multiple(true)
+                .use_delimiter(true)
+                .conflicts_with("id"),
+        )
+        .arg(
+            Arg::with_name("min-mapping-quality")
+                .long("min-mapping-quality")
+                .value_name("u8")
+                .help("Minimum mapping quality to consider an alignment")
+                .default_value("10"),
+        )
+        .arg(
+            Arg::with_name("normalize")
+                .long("normalize")
+                .value_name("str")
+                .help("Quantification normalization method")
+                .possible_values(&["fpkm", "tpm"]),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("file")
+                .help("Output destination for feature counts")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("annotation-format")
+                .long("annotation-format")
+                .value_name("str")
+                .help("Format of the annotations file, detected from the extension if not given")
+                .possible_values(&["gff3", "gtf", "bed", "saf"]),
+        )
+        .arg(
+            Arg::with_name("annotations")
+                .short("a")
+                .long("annotations")
+                .value_name("file")
+                .help("Input annotations file (GFF3, GTF, BED, or SAF)")
+                .required_unless("bin-size"),
+        )
+        .arg(
+            Arg::with_name("bin-size")
+                .long("bin-size")
+                .value_name("uint")
+                .help("Count records in fixed-width bins of the reference sequences instead of features")
+                .conflicts_with_all(&["annotations", "levels", "splicing-output"]),
+        )
+        .arg(
+            Arg::with_name("annotated-output")
+                .long("annotated-output")
+                .value_name("file")
+                .help("Output destination for alignments annotated with their assignments (BAM data tag XF)"),
+        )
+        .arg(
+            Arg::with_name("splicing-output")
+                .long("splicing-output")
+                .value_name("file")
+                .help("Output destination for exonic, intronic, and spanning counts of each gene"),
+        )
+        .arg(
+            Arg::with_name("group-by")
+                .long("group-by")
+                .value_name("str")
+                .help("Split counts into a column for each value of a BAM data tag, e.g., RG")
+                .conflicts_with("annotated-output"),
+        )
+        .arg(
+            Arg::with_name("duplicates")
+                .long("duplicates")
+                .value_name("str")
+                .help("Count records marked as duplicates (BAM flag 0x400)")
+                .possible_values(&["keep", "skip", "only"])
+                .default_value("keep"),
+        )
+        .arg(
+            Arg::with_name("duplication-output")
+                .long("duplication-output")
+                .value_name("file")
+                .help("Output destination for the duplication rate of each feature"),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .long("threads")
+                .value_name("uint")
+                .help("Force a specific number of threads"),
+        )
+        .arg(
+            Arg::with_name("reference")
+                .short("r")
+                .long("reference")
+                .value_name("file")
+                .help("Input reference sequences file (FASTA), used to decode CRAM"),
+        )
+        .arg(
+            Arg::with_name("index")
+                .long("index")
+                .value_name("file")
+                .help("Input alignment index file (BAI or CSI)"),
+        )
+        .arg(
+            Arg::with_name("src")
+                .help("Input alignment files (SAM, BAM, or CRAM) or \"-\" for stdin")
+                .multiple(true)
+                .required(true)
+                .index(1),
+        );
+
+    let normalize_cmd = SubCommand::with_name("normalize")
+        .about("Normalize counts")
+        .arg(
+            Arg::with_name("feature-type")
+                .short("t")
+                .long("feature-type")
+                .value_name("str")
+                .help("Feature type to count")
+                .default_value("exon"),
+        )
+        .arg(
+            Arg::with_name("id")
+                .short("i")
+                .long("id")
+                .value_name("str")
+                .help("Feature attribute to use as the feature identity")
+                .default_value("gene_id"),
+        )
+        .arg(
+            Arg::with_name("annotation-format")
+                .long("annotation-format")
+                .value_name("str")
+                .help("Format of the annotations file, detected from the extension if not given")
+                .possible_values(&["gff3", "gtf", "bed", "saf"]),
+        )
+        .arg(
+            Arg::with_name("annotations")
+                .short("a")
+                .long("annotations")
+                .value_name("file")
+                .help("Input annotations file (GFF3, GTF, BED, or SAF)")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("method")
+                .long("method")
+                .value_name("str")
+                .help("Quantification normalization method")
+                .possible_values(&["fpkm", "tpm"])
+                .default_value("tpm"),
+        )
+        .arg(
+            Arg::with_name("counts")
+                .help("Input counts file")
+                .required(true)
+                .index(1),
+        );
+
+    let merge_cmd = SubCommand::with_name("merge")
+        .about("Merge counts files into a matrix")
+        .arg(
+            Arg::with_name("sample-sheet")
+                .long("sample-sheet")
+                .value_name("file")
+                .help("Input sample sheet (tab-delimited sample name and counts file)")
+                .conflicts_with("counts"),
+        )
+        .arg(
+            Arg::with_name("summary")
+                .long("summary")
+                .value_name("file")
+                .help("Output destination for trailer statistics"),
+        )
+        .arg(
+            Arg::with_name("counts")
+                .help("Input counts files")
+                .multiple(true)
+                .required_unless("sample-sheet")
+                .index(1),
+        );
+
+    let junctions_cmd = SubCommand::with_name("junctions")
+        .about("Count splice junctions")
+        .arg(
+            Arg::with_name("with-secondary-records")
+                .long("with-secondary-records")
+                .help("Count secondary records (BAM flag 0x100)"),
+        )
+        .arg(
+            Arg::with_name("with-supplementary-records")
+                .long("with-supplementary-records")
+                .help("Count supplementary records (BAM flag 0x800)"),
+        )
+        .arg(
+            Arg::with_name("strand-specification")
+                .long("strand-specification")
+                .value_name("str")
+                .help("Strand specification")
+                .possible_values(&["none", "forward", "reverse", "auto"])
+                .default_value("auto"),
+        )
+        .arg(
+            Arg::with_name("feature-type")
+                .short("t")
+                .long("feature-type")
+                .value_name("str")
+                .help("Feature type of exons")
+                .default_value("exon"),
+        )
+        .arg(
+            Arg::with_name("min-mapping-quality")
+                .long("min-mapping-quality")
+                .value_name("u8")
+                .help("Minimum mapping quality to consider an alignment")
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("annotations")
+                .short("a")
+                .long("annotations")
+                .value_name("file")
+                .help("Input annotations file (GFF3 or GTF)")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("reference")
+                .short("r")
+                .long("reference")
+                .value_name("file")
+                .help("Input reference sequences file (FASTA), used to decode CRAM"),
+        )
+        .arg(
+            Arg::with_name("src")
+                .help("Input alignment file (SAM, BAM, or CRAM) or \"-\" for stdin")
+                .required(true)
+                .index(1),
+        );
+
+    let qc_cmd = SubCommand::with_name("qc")
+        .about("Read distribution quality control")
+        .arg(
+            Arg::with_name("with-secondary-records")
+                .long("with-secondary-records")
+                .help("Count secondary records (BAM flag 0x100)"),
+        )
+        .arg(
+            Arg::with_name("with-supplementary-records")
+                .long("with-supplementary-records")
+                .help("Count supplementary records (BAM flag 0x800)"),
+        )
+        .arg(
+            Arg::with_name("with-nonunique-records")
+                .long("with-nonunique-records")
+                .help("Count nonunique records (BAM data tag NH > 1)"),
+        )
+        .arg(
+            Arg::with_name("feature-type")
+                .short("t")
+                .long("feature-type")
+                .value_name("str")
+                .help("Feature type of exons")
+                .default_value("exon"),
+        )
+        .arg(
+            Arg::with_name("min-mapping-quality")
+                .long("min-mapping-quality")
+                .value_name("u8")
+                .help("Minimum mapping quality to consider an alignment")
+                .default_value("10"),
+        )
+        .arg(
+            Arg::with_name("annotations")
+                .short("a")
+                .long("annotations")
+                .value_name("file")
+                .help("Input annotations file (GFF3 or GTF)")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("reference")
+                .short("r")
+                .long("reference")
+                .value_name("file")
+                .help("Input reference sequences file (FASTA), used to decode CRAM"),
+        )
+        .arg(
+            Arg::with_name("src")
+                .help("Input alignment file (SAM, BAM, or CRAM) or \"-\" for stdin")
+                .required(true)
+                .index(1),
+        );
+
+    let coverage_cmd = SubCommand::with_name("coverage")
+        .about("Gene body coverage")
+        .arg(
+            Arg::with_name("with-secondary-records")
+                .long("with-secondary-records")
+                .help("Count secondary records (BAM flag 0x100)"),
+        )
+        .arg(
+            Arg::with_name("with-supplementary-records")
+                .long("with-supplementary-records")
+                .help("Count supplementary records (BAM flag 0x800)"),
+        )
+        .arg(
+            Arg::with_name("with-nonunique-records")
+                .long("with-nonunique-records")
+                .help("Count nonunique records (BAM data tag NH > 1)"),
+        )
+        .arg(
+            Arg::with_name("strand-specification")
+                .long("strand-specification")
+                .value_name("str")
+                .help("Strand specification")
+                .possible_values(&["none", "forward", "reverse", "auto"])
+                .default_value("auto"),
+        )
+        .arg(
+            Arg::with_name("feature-type")
+                .short("t")
+                .long("feature-type")
+                .value_name("str")
+                .help("Feature type of exons")
+                .default_value("exon"),
+        )
+        .arg(
+            Arg::with_name("min-mapping-quality")
+                .long("min-mapping-quality")
+                .value_name("u8")
+                .help("Minimum mapping quality to consider an alignment")
+                .default_value("10"),
+        )
+        .arg(
+            Arg::with_name("annotations")
+                .short("a")
+                .long("annotations")
+                .value_name("file")
+                .help("Input annotations file (GFF3 or GTF)")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("reference")
+                .short("r")
+                .long("reference")
+                .value_name("file")
+                .help("Input reference sequences file (FASTA), used to decode CRAM"),
+        )
+        .arg(
+            Arg::with_name("src")
+                .help("Input alignment files (SAM, BAM, or CRAM) or \"-\" for stdin")
+                .multiple(true)
+                .required(true)
+                .index(1),
+        );
+
+    let ribo_cmd = SubCommand::with_name("ribo")
+        .about("Ribosome profiling P-site counting")
+        .arg(
+            Arg::with_name("with-secondary-records")
+                .long("with-secondary-records")
+                .help("Count secondary records (BAM flag 0x100)"),
+        )
+        .arg(
+            Arg::with_name("with-supplementary-records")
+                .long("with-supplementary-records")
+                .help("Count supplementary records (BAM flag 0x800)"),
+        )
+        .arg(
+            Arg::with_name("with-nonunique-records")
+                .long("with-nonunique-records")
+                .help("Count nonunique records (BAM data tag NH > 1)"),
+        )
+        .arg(
+            Arg::with_name("offsets")
+                .long("offsets")
+                .value_name("file")
+                .help("Input P-site offsets file (tab-delimited read length and offset)")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("level")
+                .long("level")
+                .value_name("str")
+                .help("Feature hierarchy level to count")
+                .possible_values(&["gene", "transcript"])
+                .default_value("gene"),
+        )
+        .arg(
+            Arg::with_name("strand-specification")
+                .long("strand-specification")
+                .value_name("str")
+                .help("Strand specification")
+                .possible_values(&["none", "forward", "reverse", "auto"])
+                .default_value("auto"),
+        )
+        .arg(
+            Arg::with_name("feature-type")
+                .short("t")
+                .long("feature-type")
+                .value_name("str")
+                .help("Feature type of coding sequences")
+                .default_value("CDS"),
+        )
+        .arg(
+            Arg::with_name("min-mapping-quality")
+                .long("min-mapping-quality")
+                .value_name("u8")
+                .help("Minimum mapping quality to consider an alignment")
+                .default_value("10"),
+        )
+        .arg(
+            Arg::with_name("annotations")
+                .short("a")
+                .long("annotations")
+                .value_name("file")
+                .help("Input annotations file (GFF3 or GTF)")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("reference")
+                .short("r")
+                .long("reference")
+                .value_name("file")
+                .help("Input reference sequences file (FASTA), used to decode CRAM"),
+        )
+        .arg(
+            Arg::with_name("src")
+                .help("Input alignment file (SAM, BAM, or CRAM) or \"-\" for stdin")
+                .required(true)
+                .index(1),
+        );
+
+    let single_cell_cmd = SubCommand::with_name("single-cell")
+        .about("Count features by cell barcode and UMI")
+        .arg(
+            Arg::with_name("with-secondary-records")
+                .long("with-secondary-records")
+                .help("Count secondary records (BAM flag 0x100)"),
+        )
+        .arg(
+            Arg::with_name("with-supplementary-records")
+                .long("with-supplementary-records")
+                .help("Count supplementary records (BAM flag 0x800)"),
+        )
+        .arg(
+            Arg::with_name("with-nonunique-records")
+                .long("with-nonunique-records")
+                .help("Count nonunique records (BAM data tag NH > 1)"),
+        )
+        .arg(
+            Arg::with_name("mode")
+                .long("mode")
+                .value_name("str")
+                .help("Overlap resolution mode")
+                .possible_values(&["union", "intersection-strict", "intersection-nonempty"])
+                .default_value("union"),
+        )
         .arg(
             Arg::with_name("strand-specification")
                 .long("strand-specification")
@@ -55,43 +599,87 @@ fn match_args_from_env() -> clap::ArgMatches<'static> {
                 .default_value("10"),
         )
         .arg(
-            Arg::with_name("normalize")
-                .long("normalize")
+            Arg::with_name("cell-barcode-tag")
+                .long("cell-barcode-tag")
                 .value_name("str")
-                .help("Quantification normalization method")
-                .possible_values(&["fpkm", "tpm"]),
+                .help("BAM data tag of the cell barcode")
+                .default_value("CB"),
+        )
+        .arg(
+            Arg::with_name("umi-tag")
+                .long("umi-tag")
+                .value_name("str")
+                .help("BAM data tag of the UMI")
+                .default_value("UB"),
+        )
+        .arg(
+            Arg::with_name("whitelist")
+                .long("whitelist")
+                .value_name("file")
+                .help("Input cell barcode whitelist, one barcode per line"),
         )
         .arg(
             Arg::with_name("output")
                 .short("o")
                 .long("output")
-                .value_name("file")
-                .help("Output destination for feature counts")
+                .value_name("dir")
+                .help("Output directory for the count matrix")
                 .required(true),
         )
+        .arg(
+            Arg::with_name("annotation-format")
+                .long("annotation-format")
+                .value_name("str")
+                .help("Format of the annotations file, detected from the extension if not given")
+                .possible_values(&["gff3", "gtf", "bed", "saf"]),
+        )
         .arg(
             Arg::with_name("annotations")
                 .short("a")
                 .long("annotations")
                 .value_name("file")
-                .help("Input annotations file (GFF3)")
+                .help("Input annotations file (GFF3, GTF, BED, or SAF)")
                 .required(true),
         )
         .arg(
-            Arg::with_name("threads")
-                .long("threads")
-                .value_name("uint")
-                .help("Force a specific number of threads"),
+            Arg::with_name("reference")
+                .short("r")
+                .long("reference")
+                .value_name("file")
+                .help("Input reference sequences file (FASTA), used to decode CRAM"),
         )
         .arg(
-            Arg::with_name("bam")
-                .help("Input alignment file")
+            Arg::with_name("src")
+                .help("Input alignment file (SAM, BAM, or CRAM) or \"-\" for stdin")
                 .required(true)
                 .index(1),
         );
 
-    let normalize_cmd = SubCommand::with_name("normalize")
-        .about("Normalize counts")
+    let ase_cmd = SubCommand::with_name("ase")
+        .about("Count reference and alternate alleles at heterozygous SNPs")
+        .arg(
+            Arg::with_name("with-secondary-records")
+                .long("with-secondary-records")
+                .help("Count secondary records (BAM flag 0x100)"),
+        )
+        .arg(
+            Arg::with_name("with-supplementary-records")
+                .long("with-supplementary-records")
+                .help("Count supplementary records (BAM flag 0x800)"),
+        )
+        .arg(
+            Arg::with_name("with-nonunique-records")
+                .long("with-nonunique-records")
+                .help("Count nonunique records (BAM data tag NH > 1)"),
+        )
+        .arg(
+            Arg::with_name("strand-specification")
+                .long("strand-specification")
+                .value_name("str")
+                .help("Strand specification")
+                .possible_values(&["none", "forward", "reverse", "auto"])
+                .default_value("auto"),
+        )
         .arg(
             Arg::with_name("feature-type")
                 .short("t")
@@ -108,25 +696,51 @@ fn match_args_from_env() -> clap::ArgMatches<'static> {
                 .help("Feature attribute to use as the feature identity")
                 .default_value("gene_id"),
         )
+        .arg(
+            Arg::with_name("min-mapping-quality")
+                .long("min-mapping-quality")
+                .value_name("u8")
+                .help("Minimum mapping quality to consider an alignment")
+                .default_value("10"),
+        )
+        .arg(
+            Arg::with_name("snps")
+                .long("snps")
+                .value_name("file")
+                .help("Input heterozygous SNPs (VCF), optionally gzip-compressed")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("feature-output")
+                .long("feature-output")
+                .value_name("file")
+                .help("Output destination for allele counts per feature"),
+        )
+        .arg(
+            Arg::with_name("annotation-format")
+                .long("annotation-format")
+                .value_name("str")
+                .help("Format of the annotations file, detected from the extension if not given")
+                .possible_values(&["gff3", "gtf", "bed", "saf"]),
+        )
         .arg(
             Arg::with_name("annotations")
                 .short("a")
                 .long("annotations")
                 .value_name("file")
-                .help("Input annotations file (GFF3)")
+                .help("Input annotations file (GFF3, GTF, BED, or SAF)")
                 .required(true),
         )
         .arg(
-            Arg::with_name("method")
-                .long("method")
-                .value_name("str")
-                .help("Quantification normalization method")
-                .possible_values(&["fpkm", "tpm"])
-                .default_value("tpm"),
+            Arg::with_name("reference")
+                .short("r")
+                .long("reference")
+                .value_name("file")
+                .help("Input reference sequences file (FASTA), used to decode CRAM"),
         )
         .arg(
-            Arg::with_name("counts")
-                .help("Input counts file")
+            Arg::with_name("src")
+                .help("Input alignment file (SAM, BAM, or CRAM) or \"-\" for stdin")
                 .required(true)
                 .index(1),
         );
@@ -142,29 +756,91 @@ fn match_args_from_env() -> clap::ArgMatches<'static> {
         )
         .subcommand(quantify_cmd)
         .subcommand(normalize_cmd)
+        .subcommand(merge_cmd)
+        .subcommand(junctions_cmd)
+        .subcommand(qc_cmd)
+        .subcommand(coverage_cmd)
+        .subcommand(ribo_cmd)
+        .subcommand(single_cell_cmd)
+        .subcommand(ase_cmd)
         .get_matches()
 }
 
 fn quantify(matches: &ArgMatches<'_>) -> anyhow::Result<()> {
-    let bam_src = matches.value_of("bam").unwrap();
-    let annotations_src = matches.value_of("annotations").unwrap();
+    let srcs: Vec<_> = matches.values_of("src").unwrap().collect();
+    let reference_src = matches.value_of("reference").map(Path::new);
+    let index_src = matches.value_of("index").map(Path::new);
+    let annotations_src = matches.value_of("annotations").map(Path::new);
+    let annotations_format = matches.value_of("annotation-format").map(|_| {
+        value_t!(matches, "annotation-format", annotations::Format).unwrap_or_else(|e| e.exit())
+    });
+    let bin_size = matches
+        .value_of("bin-size")
+        .map(|_| value_t!(matches, "bin-size", u64).unwrap_or_else(|e| e.exit()));
 
     let normalize = matches.value_of("normalize").map(|_| {
         value_t!(matches, "normalize", normalization::Method).unwrap_or_else(|e| e.exit())
     });
 
     let results_dst = matches.value_of("output").unwrap();
+    let annotated_dst = matches.value_of("annotated-output").map(Path::new);
+    let splicing_dst = matches.value_of("splicing-output").map(Path::new);
+    let duplication_dst = matches.value_of("duplication-output").map(Path::new);
+
+    let group_tag = match matches.value_of("group-by") {
+        Some(s) => Some(parse_tag(s)?),
+        None => None,
+    };
 
     let feature_type = matches.value_of("feature-type").unwrap();
     let id = matches.value_of("id").unwrap();
+    let levels = if matches.is_present("levels") {
+        values_t!(matches, "levels", Level).unwrap_or_else(|e| e.exit())
+    } else {
+        Vec::new()
+    };
+
     let min_mapping_quality =
         value_t!(matches, "min-mapping-quality", u8).unwrap_or_else(|e| e.exit());
 
-    let with_secondary_records = matches.is_present("with-secondary-records");
+    let nonunique_mode = matches.value_of("nonunique-mode").map(|_| {
+        value_t!(matches, "nonunique-mode", count::NonuniqueMode).unwrap_or_else(|e| e.exit())
+    });
+    // A nonunique mode implies counting nonunique records, including their
+    // other alignments, which are secondary records.
+    let with_secondary_records =
+        matches.is_present("with-secondary-records") || nonunique_mode.is_some();
     let with_supplementary_records = matches.is_present("with-supplementary-records");
-    let with_nonunique_records = matches.is_present("with-nonunique-records");
+    let with_nonunique_records =
+        matches.is_present("with-nonunique-records") || nonunique_mode.is_some();
+    let duplicate_mode =
+        value_t!(matches, "duplicates", count::DuplicateMode).unwrap_or_else(|e| e.exit());
+    let umi_source = matches
+        .value_of("umi")
+        .map(|_| value_t!(matches, "umi", count::UmiSource).unwrap_or_else(|e| e.exit()));
+
+    let threads = value_t!(matches, "threads", usize).ok();
 
-    let threads = value_t!(matches, "threads", usize).unwrap_or_else(|_| num_cpus::get());
+    let mode = value_t!(matches, "mode", count::Mode).unwrap_or_else(|e| e.exit());
+    let ambiguous_mode = matches.value_of("ambiguous-mode").map(|_| {
+        value_t!(matches, "ambiguous-mode", count::AmbiguousMode).unwrap_or_else(|e| e.exit())
+    });
+    let fragment_mode = if matches.is_present("read-position") {
+        if matches.occurrences_of("fragment-mode") > 0 {
+            anyhow::bail!("--read-position cannot be used with --fragment-mode");
+        }
+
+        let read_position =
+            value_t!(matches, "read-position", count::ReadPosition).unwrap_or_else(|e| e.exit());
+        let shift = matches
+            .value_of("read-shift")
+            .map(|_| value_t!(matches, "read-shift", i64).unwrap_or_else(|e| e.exit()))
+            .unwrap_or(0);
+
+        count::FragmentMode::ReadPosition(read_position, shift)
+    } else {
+        value_t!(matches, "fragment-mode", count::FragmentMode).unwrap_or_else(|e| e.exit())
+    };
 
     let strand_specification_option =
         value_t!(matches, "strand-specification", StrandSpecificationOption)
@@ -175,17 +851,32 @@ fn quantify(matches: &ArgMatches<'_>) -> anyhow::Result<()> {
         with_secondary_records,
         with_supplementary_records,
         with_nonunique_records,
+        nonunique_mode,
+        umi_source,
+        duplicate_mode,
     );
 
     commands::quantify(
-        bam_src,
+        &srcs,
+        reference_src,
+        index_src,
         annotations_src,
+        annotations_format,
+        bin_size,
         feature_type,
         id,
         filter,
+        mode,
+        ambiguous_mode,
+        fragment_mode,
         strand_specification_option,
+        group_tag.as_ref(),
         threads,
         normalize,
+        annotated_dst,
+        &levels,
+        splicing_dst,
+        duplication_dst,
         results_dst,
     )
 }
@@ -193,13 +884,286 @@ fn quantify(matches: &ArgMatches<'_>) -> anyhow::Result<()> {
 fn normalize(matches: &ArgMatches<'_>) -> anyhow::Result<()> {
     let counts_src = matches.value_of("counts").unwrap();
     let annotations_src = matches.value_of("annotations").unwrap();
+    let annotations_format = matches.value_of("annotation-format").map(|_| {
+        value_t!(matches, "annotation-format", annotations::Format).unwrap_or_else(|e| e.exit())
+    });
 
     let feature_type = matches.value_of("feature-type").unwrap();
     let id = matches.value_of("id").unwrap();
 
     let method = value_t!(matches, "method", normalization::Method).unwrap_or_else(|e| e.exit());
 
-    commands::normalize(counts_src, annotations_src, feature_type, id, method)
+    commands::normalize(
+        counts_src,
+        annotations_src,
+        annotations_format,
+        feature_type,
+        id,
+        method,
+    )
+}
+
+fn merge(matches: &ArgMatches<'_>) -> anyhow::Result<()> {
+    let srcs: Vec<_> = matches
+        .values_of("counts")
+        .map(|values| values.collect())
+        .unwrap_or_default();
+
+    let sample_sheet_src = matches.value_of("sample-sheet");
+    let summary_dst = matches.value_of("summary");
+
+    commands::merge(&srcs, sample_sheet_src, summary_dst)
+}
+
+fn junctions(matches: &ArgMatches<'_>) -> anyhow::Result<()> {
+    let src = matches.value_of("src").unwrap();
+    let reference_src = matches.value_of("reference").map(Path::new);
+    let annotations_src = matches.value_of("annotations").unwrap();
+
+    let feature_type = matches.value_of("feature-type").unwrap();
+    let min_mapping_quality =
+        value_t!(matches, "min-mapping-quality", u8).unwrap_or_else(|e| e.exit());
+
+    let with_secondary_records = matches.is_present("with-secondary-records");
+    let with_supplementary_records = matches.is_present("with-supplementary-records");
+
+    let strand_specification_option =
+        value_t!(matches, "strand-specification", StrandSpecificationOption)
+            .unwrap_or_else(|e| e.exit());
+
+    // Nonunique records are counted separately rather than filtered.
+    let filter = Filter::new(
+        min_mapping_quality,
+        with_secondary_records,
+        with_supplementary_records,
+        true,
+        None,
+        None,
+        count::DuplicateMode::Keep,
+    );
+
+    commands::junctions(
+        src,
+        reference_src,
+        annotations_src,
+        feature_type,
+        &filter,
+        strand_specification_option,
+    )
+}
+
+fn qc(matches: &ArgMatches<'_>) -> anyhow::Result<()> {
+    let src = matches.value_of("src").unwrap();
+    let reference_src = matches.value_of("reference").map(Path::new);
+    let annotations_src = matches.value_of("annotations").unwrap();
+
+    let feature_type = matches.value_of("feature-type").unwrap();
+    let min_mapping_quality =
+        value_t!(matches, "min-mapping-quality", u8).unwrap_or_else(|e| e.exit());
+
+    let with_secondary_records = matches.is_present("with-secondary-records");
+    let with_supplementary_records = matches.is_present("with-supplementary-records");
+    let with_nonunique_records = matches.is_present("with-nonunique-records");
+
+    let filter = Filter::new(
+        min_mapping_quality,
+        with_secondary_records,
+        with_supplementary_records,
+        with_nonunique_records,
+        None,
+        None,
+        count::DuplicateMode::Keep,
+    );
+
+    commands::qc(src, reference_src, annotations_src, feature_type, &filter)
+}
+
+fn coverage(matches: &ArgMatches<'_>) -> anyhow::Result<()> {
+    let srcs: Vec<_> = matches.values_of("src").unwrap().collect();
+    let reference_src = matches.value_of("reference").map(Path::new);
+    let annotations_src = matches.value_of("annotations").unwrap();
+
+    let feature_type = matches.value_of("feature-type").unwrap();
+    let min_mapping_quality =
+        value_t!(matches, "min-mapping-quality", u8).unwrap_or_else(|e| e.exit());
+
+    let with_secondary_records = matches.is_present("with-secondary-records");
+    let with_supplementary_records = matches.is_present("with-supplementary-records");
+    let with_nonunique_records = matches.is_present("with-nonunique-records");
+
+    let strand_specification_option =
+        value_t!(matches, "strand-specification", StrandSpecificationOption)
+            .unwrap_or_else(|e| e.exit());
+
+    let filter = Filter::new(
+        min_mapping_quality,
+        with_secondary_records,
+        with_supplementary_records,
+        with_nonunique_records,
+        None,
+        None,
+        count::DuplicateMode::Keep,
+    );
+
+    commands::coverage(
+        &srcs,
+        reference_src,
+        annotations_src,
+        feature_type,
+        &filter,
+        strand_specification_option,
+    )
+}
+
+fn ribo(matches: &ArgMatches<'_>) -> anyhow::Result<()> {
+    let src = matches.value_of("src").unwrap();
+    let reference_src = matches.value_of("reference").map(Path::new);
+    let annotations_src = matches.value_of("annotations").unwrap();
+    let offsets_src = matches.value_of("offsets").unwrap();
+
+    let feature_type = matches.value_of("feature-type").unwrap();
+    let level = value_t!(matches, "level", Level).unwrap_or_else(|e| e.exit());
+    let min_mapping_quality =
+        value_t!(matches, "min-mapping-quality", u8).unwrap_or_else(|e| e.exit());
+
+    let with_secondary_records = matches.is_present("with-secondary-records");
+    let with_supplementary_records = matches.is_present("with-supplementary-records");
+    let with_nonunique_records = matches.is_present("with-nonunique-records");
+
+    let strand_specification_option =
+        value_t!(matches, "strand-specification", StrandSpecificationOption)
+            .unwrap_or_else(|e| e.exit());
+
+    let filter = Filter::new(
+        min_mapping_quality,
+        with_secondary_records,
+        with_supplementary_records,
+        with_nonunique_records,
+        None,
+        None,
+        count::DuplicateMode::Keep,
+    );
+
+    commands::ribo(
+        src,
+        reference_src,
+        annotations_src,
+        offsets_src,
+        feature_type,
+        level,
+        &filter,
+        strand_specification_option,
+    )
+}
+
+fn single_cell(matches: &ArgMatches<'_>) -> anyhow::Result<()> {
+    let src = matches.value_of("src").unwrap();
+    let reference_src = matches.value_of("reference").map(Path::new);
+    let annotations_src = matches.value_of("annotations").unwrap();
+    let annotations_format = matches.value_of("annotation-format").map(|_| {
+        value_t!(matches, "annotation-format", annotations::Format).unwrap_or_else(|e| e.exit())
+    });
+    let whitelist_src = matches.value_of("whitelist").map(Path::new);
+    let dst = matches.value_of("output").unwrap();
+
+    let feature_type = matches.value_of("feature-type").unwrap();
+    let id = matches.value_of("id").unwrap();
+    let barcode_tag = parse_tag(matches.value_of("cell-barcode-tag").unwrap())?;
+    let umi_tag = parse_tag(matches.value_of("umi-tag").unwrap())?;
+
+    let mode = value_t!(matches, "mode", count::Mode).unwrap_or_else(|e| e.exit());
+    let min_mapping_quality =
+        value_t!(matches, "min-mapping-quality", u8).unwrap_or_else(|e| e.exit());
+
+    let with_secondary_records = matches.is_present("with-secondary-records");
+    let with_supplementary_records = matches.is_present("with-supplementary-records");
+    let with_nonunique_records = matches.is_present("with-nonunique-records");
+
+    let strand_specification_option =
+        value_t!(matches, "strand-specification", StrandSpecificationOption)
+            .unwrap_or_else(|e| e.exit());
+
+    let filter = Filter::new(
+        min_mapping_quality,
+        with_secondary_records,
+        with_supplementary_records,
+        with_nonunique_records,
+        None,
+        None,
+        count::DuplicateMode::Keep,
+    );
+
+    commands::single_cell(
+        src,
+        reference_src,
+        annotations_src,
+        annotations_format,
+        whitelist_src,
+        feature_type,
+        id,
+        &barcode_tag,
+        &umi_tag,
+        &filter,
+        mode,
+        strand_specification_option,
+        dst,
+    )
+}
+
+fn ase(matches: &ArgMatches<'_>) -> anyhow::Result<()> {
+    let src = matches.value_of("src").unwrap();
+    let reference_src = matches.value_of("reference").map(Path::new);
+    let annotations_src = matches.value_of("annotations").unwrap();
+    let annotations_format = matches.value_of("annotation-format").map(|_| {
+        value_t!(matches, "annotation-format", annotations::Format).unwrap_or_else(|e| e.exit())
+    });
+    let snps_src = matches.value_of("snps").unwrap();
+    let feature_dst = matches.value_of("feature-output").map(Path::new);
+
+    let feature_type = matches.value_of("feature-type").unwrap();
+    let id = matches.value_of("id").unwrap();
+
+    let min_mapping_quality =
+        value_t!(matches, "min-mapping-quality", u8).unwrap_or_else(|e| e.exit());
+
+    let with_secondary_records = matches.is_present("with-secondary-records");
+    let with_supplementary_records = matches.is_present("with-supplementary-records");
+    let with_nonunique_records = matches.is_present("with-nonunique-records");
+
+    let strand_specification_option =
+        value_t!(matches, "strand-specification", StrandSpecificationOption)
+            .unwrap_or_else(|e| e.exit());
+
+    let filter = Filter::new(
+        min_mapping_quality,
+        with_secondary_records,
+        with_supplementary_records,
+        with_nonunique_records,
+        None,
+        None,
+        count::DuplicateMode::Keep,
+    );
+
+    commands::ase(
+        src,
+        reference_src,
+        annotations_src,
+        annotations_format,
+        snps_src,
+        feature_type,
+        id,
+        &filter,
+        strand_specification_option,
+        feature_dst,
+    )
+}
+
+/// Parses a BAM data tag, e.g., `CB`.
+fn parse_tag(s: &str) -> anyhow::Result<[u8; 2]> {
+    match s.as_bytes() {
+        &[a, b] => Ok([a, b]),
+        _ => anyhow::bail!("invalid tag: {}", s),
+    }
 }
 
 fn main() -> anyhow::Result<()> {
@@ -217,6 +1181,20 @@ fn main() -> anyhow::Result<()> {
         quantify(submatches)
     } else if let Some(submatches) = matches.subcommand_matches("normalize") {
         normalize(submatches)
+    } else if let Some(submatches) = matches.subcommand_matches("merge") {
+        merge(submatches)
+    } else if let Some(submatches) = matches.subcommand_matches("junctions") {
+        junctions(submatches)
+    } else if let Some(submatches) = matches.subcommand_matches("qc") {
+        qc(submatches)
+    } else if let Some(submatches) = matches.subcommand_matches("coverage") {
+        coverage(submatches)
+    } else if let Some(submatches) = matches.subcommand_matches("ribo") {
+        ribo(submatches)
+    } else if let Some(submatches) = matches.subcommand_matches("single-cell") {
+        single_cell(submatches)
+    } else if let Some(submatches) = matches.subcommand_matches("ase") {
+        ase(submatches)
     } else {
         unreachable!()
     }
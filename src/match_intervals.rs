@@ -6,6 +6,7 @@ use noodles_sam as sam;
 pub struct MatchIntervals<'a> {
     ops: cigar::Ops<'a>,
     prev_start: u64,
+    read_position: usize,
 }
 
 impl<'a> MatchIntervals<'a> {
@@ -13,14 +14,17 @@ impl<'a> MatchIntervals<'a> {
         Self {
             ops: cigar.ops(),
             prev_start: initial_start,
+            read_position: 0,
         }
     }
-}
-
-impl<'a> Iterator for MatchIntervals<'a> {
-    type Item = RangeInclusive<u64>;
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Returns the next match interval and the 0-based position in the read
+    /// sequence of its start.
+    ///
+    /// The bases of the interval are the read sequence from this position,
+    /// e.g., the base at reference position `p` is at read position
+    /// `read_start + (p - interval.start())`.
+    pub fn next_with_read_position(&mut self) -> Option<(RangeInclusive<u64>, usize)> {
         use sam::record::cigar::op::Kind;
 
         loop {
@@ -31,18 +35,31 @@ impl<'a> Iterator for MatchIntervals<'a> {
                 Kind::Match | Kind::SeqMatch | Kind::SeqMismatch => {
                     let start = self.prev_start;
                     let end = start + len - 1;
+                    let read_start = self.read_position;
                     self.prev_start += len;
-                    return Some(start..=end);
+                    self.read_position += op.len() as usize;
+                    return Some((start..=end, read_start));
                 }
                 Kind::Deletion | Kind::Skip => {
                     self.prev_start += len;
                 }
+                Kind::Insertion | Kind::SoftClip => {
+                    self.read_position += op.len() as usize;
+                }
                 _ => continue,
             }
         }
     }
 }
 
+impl<'a> Iterator for MatchIntervals<'a> {
+    type Item = RangeInclusive<u64>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_with_read_position().map(|(interval, _)| interval)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use noodles_bam::{self as bam, record::cigar};
@@ -79,4 +96,18 @@ mod tests {
         assert_eq!(it.next(), Some(17..=25));
         assert!(it.next().is_none());
     }
+
+    #[test]
+    fn test_next_with_read_position() {
+        let raw_cigar = build_raw_cigar();
+        let cigar = bam::record::Cigar::new(&raw_cigar);
+
+        let start = 1;
+        let mut it = MatchIntervals::new(&cigar, start);
+
+        assert_eq!(it.next_with_read_position(), Some((1..=1, 0)));
+        assert_eq!(it.next_with_read_position(), Some((9..=16, 8)));
+        assert_eq!(it.next_with_read_position(), Some((17..=25, 16)));
+        assert!(it.next_with_read_position().is_none());
+    }
 }
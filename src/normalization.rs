@@ -9,7 +9,7 @@ use std::{collections::HashMap, error, fmt};
 
 use crate::Feature;
 
-type Counts = HashMap<String, u64>;
+type Counts = HashMap<String, f64>;
 type FeatureMap = HashMap<String, Vec<Feature>>;
 
 #[derive(Debug)]
@@ -23,12 +23,12 @@ pub fn calculate_fpkms(
         .collect()
 }
 
-fn sum_counts(counts: &Counts) -> u64 {
+fn sum_counts(counts: &Counts) -> f64 {
     counts.values().sum()
 }
 
-fn calculate_fpkm(count: u64, len: u64, counts_sum: u64) -> f64 {
-    (count as f64 * 1e9) / (len as f64 * counts_sum as f64)
+fn calculate_fpkm(count: f64, len: u64, counts_sum: f64) -> f64 {
+    (count * 1e9) / (len as f64 * counts_sum)
 }
 
 #[cfg(test)]
@@ -39,11 +39,11 @@ mod tests {
 
     use super::*;
 
-    fn build_counts() -> HashMap<String, u64> {
+    fn build_counts() -> HashMap<String, f64> {
         let counts = [
-            (String::from("AAAS"), 645),
-            (String::from("AC009952.3"), 1),
-            (String::from("RPL37AP1"), 5714),
+            (String::from("AAAS"), 645.0),
+            (String::from("AC009952.3"), 1.0),
+            (String::from("RPL37AP1"), 5714.0),
         ];
 
         counts.iter().cloned().collect()
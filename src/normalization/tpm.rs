@@ -13,7 +13,7 @@ pub fn calculate_tpms(
                 .get(name)
                 .map(|features| {
                     let len = sum_nonoverlapping_feature_lengths(features);
-                    let cpb = count as f64 / len as f64;
+                    let cpb = count / len as f64;
                     (name.clone(), cpb)
                 })
                 .ok_or_else(|| Error::MissingFeature(name.clone()))
@@ -42,11 +42,11 @@ mod tests {
 
     use super::*;
 
-    fn build_counts() -> HashMap<String, u64> {
+    fn build_counts() -> HashMap<String, f64> {
         let counts = [
-            (String::from("AAAS"), 645),
-            (String::from("AC009952.3"), 1),
-            (String::from("RPL37AP1"), 5714),
+            (String::from("AAAS"), 645.0),
+            (String::from("AC009952.3"), 1.0),
+            (String::from("RPL37AP1"), 5714.0),
         ];
 
         counts.iter().cloned().collect()
@@ -19,6 +19,16 @@ where
         &self.inner
     }
 
+    /// Writes the header of a value matrix, i.e., an empty cell followed by the
+    /// sample names.
+    pub fn write_header(&mut self, sample_names: &[String]) -> io::Result<()> {
+        for name in sample_names {
+            write!(self.inner, "\t{}", name)?;
+        }
+
+        writeln!(self.inner)
+    }
+
     pub fn write_values(&mut self, ids: &[String], values: &HashMap<String, f64>) -> io::Result<()>
     where
         W: Write,
@@ -30,6 +40,26 @@ where
 
         Ok(())
     }
+
+    /// Writes the values of multiple samples, one column per sample.
+    pub fn write_value_matrix(
+        &mut self,
+        ids: &[String],
+        values: &[HashMap<String, f64>],
+    ) -> io::Result<()> {
+        for id in ids {
+            write!(self.inner, "{}", id)?;
+
+            for sample_values in values {
+                let value = sample_values.get(id).unwrap_or(&0.0);
+                write!(self.inner, "\t{}", value)?;
+            }
+
+            writeln!(self.inner)?;
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -68,4 +98,29 @@ PAK4\t14.5
 
         Ok(())
     }
+
+    #[test]
+    fn test_write_value_matrix() -> io::Result<()> {
+        let values1: HashMap<String, f64> =
+            vec![(String::from("AADAT"), 30.2)].into_iter().collect();
+
+        let values2: HashMap<String, f64> = vec![(String::from("CLN3"), 3.7)].into_iter().collect();
+
+        let ids = [String::from("AADAT"), String::from("CLN3")];
+
+        let mut writer = Writer::new(Vec::new());
+        writer.write_header(&[String::from("sample1"), String::from("sample2")])?;
+        writer.write_value_matrix(&ids, &[values1, values2])?;
+
+        let actual = writer.get_ref();
+        let expected = b"\
+\tsample1\tsample2
+AADAT\t30.2\t0
+CLN3\t0\t3.7
+";
+
+        assert_eq!(&actual[..], &expected[..]);
+
+        Ok(())
+    }
 }
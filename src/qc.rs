@@ -0,0 +1,248 @@
+use std::{
+    cmp,
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use crate::Feature;
+
+/// Read distribution class
+///
+/// Classes are listed in order of priority. A read that intersects features of
+/// multiple classes is assigned the class with the highest priority.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Class {
+    /// coding sequence
+    Cds,
+    /// 5' untranslated region
+    FivePrimeUtr,
+    /// 3' untranslated region
+    ThreePrimeUtr,
+    /// exon that is not a coding sequence or an untranslated region, e.g., of a
+    /// noncoding transcript
+    Exon,
+    /// gap between the exons of a gene
+    Intron,
+    /// outside of any gene
+    Intergenic,
+}
+
+pub const CLASSES: [Class; 6] = [
+    Class::Cds,
+    Class::FivePrimeUtr,
+    Class::ThreePrimeUtr,
+    Class::Exon,
+    Class::Intron,
+    Class::Intergenic,
+];
+
+impl Class {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Cds => "cds",
+            Self::FivePrimeUtr => "five_prime_utr",
+            Self::ThreePrimeUtr => "three_prime_utr",
+            Self::Exon => "exon",
+            Self::Intron => "intron",
+            Self::Intergenic => "intergenic",
+        }
+    }
+
+    fn priority(self) -> usize {
+        CLASSES
+            .iter()
+            .position(|&c| c == self)
+            .unwrap_or(CLASSES.len())
+    }
+
+    /// Returns the class with the highest priority of the given class names.
+    ///
+    /// This is `Intergenic` if there are no names.
+    pub fn from_names<'a, I>(names: I) -> Self
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        names
+            .into_iter()
+            .filter_map(|name| CLASSES.iter().find(|c| c.name() == name).copied())
+            .min_by_key(|c| c.priority())
+            .unwrap_or(Self::Intergenic)
+    }
+}
+
+/// Counts the bases of each class.
+///
+/// Each base is assigned the class with the highest priority of the features
+/// that contain it, so that classes do not overlap. Bases of a reference
+/// sequence that are not in any feature are intergenic.
+pub fn count_bases(
+    features: &[(Class, Vec<Feature>)],
+    reference_sequence_lengths: &[(String, u64)],
+) -> HashMap<&'static str, u64> {
+    let mut events: HashMap<&str, Vec<(u64, usize, i64)>> = HashMap::new();
+
+    for (class, class_features) in features {
+        for feature in class_features {
+            let reference_sequence_events =
+                events.entry(feature.reference_sequence_name()).or_default();
+
+            reference_sequence_events.push((feature.start(), class.priority(), 1));
+            reference_sequence_events.push((feature.end() + 1, class.priority(), -1));
+        }
+    }
+
+    let mut bases: HashMap<&'static str, u64> = CLASSES.iter().map(|c| (c.name(), 0)).collect();
+
+    for (name, len) in reference_sequence_lengths {
+        let mut genic_len = 0;
+
+        if let Some(reference_sequence_events) = events.get_mut(name.as_str()) {
+            reference_sequence_events.sort_unstable();
+
+            let mut active = [0; CLASSES.len()];
+            let mut prev_position = 1;
+
+            for &(position, priority, delta) in reference_sequence_events.iter() {
+                let position = cmp::min(position, len + 1);
+
+                if position > prev_position {
+                    if let Some(i) = active.iter().position(|&n| n > 0) {
+                        *bases.entry(CLASSES[i].name()).or_insert(0) += position - prev_position;
+                        genic_len += position - prev_position;
+                    }
+                }
+
+                active[priority] += delta;
+                prev_position = cmp::max(prev_position, position);
+            }
+        }
+
+        *bases.entry(Class::Intergenic.name()).or_insert(0) += len - genic_len;
+    }
+
+    bases
+}
+
+/// Writes a read distribution table.
+///
+/// The columns are the class, the number of bases in the class, the number of
+/// reads assigned to the class, and the number of reads per kilobase. The
+/// last row is the total.
+pub fn write_distribution<W>(
+    writer: &mut W,
+    bases: &HashMap<&'static str, u64>,
+    reads: &HashMap<&'static str, u64>,
+) -> io::Result<()>
+where
+    W: Write,
+{
+    writeln!(writer, "class\tbases\treads\treads_per_kb")?;
+
+    let mut total_bases = 0;
+    let mut total_reads = 0;
+
+    for class in &CLASSES {
+        let class_bases = bases.get(class.name()).copied().unwrap_or(0);
+        let class_reads = reads.get(class.name()).copied().unwrap_or(0);
+
+        write_row(writer, class.name(), class_bases, class_reads)?;
+
+        total_bases += class_bases;
+        total_reads += class_reads;
+    }
+
+    write_row(writer, "total", total_bases, total_reads)
+}
+
+fn write_row<W>(writer: &mut W, name: &str, bases: u64, reads: u64) -> io::Result<()>
+where
+    W: Write,
+{
+    let reads_per_kb = if bases > 0 {
+        reads as f64 / (bases as f64 / 1000.0)
+    } else {
+        0.0
+    };
+
+    writeln!(
+        writer,
+        "{}\t{}\t{}\t{:.2}",
+        name, bases, reads, reads_per_kb
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles_gff::record::Strand;
+
+    use super::*;
+
+    #[test]
+    fn test_from_names() {
+        assert_eq!(Class::from_names(vec!["cds", "exon"]), Class::Cds);
+        assert_eq!(Class::from_names(vec!["intron", "exon"]), Class::Exon);
+        assert_eq!(
+            Class::from_names(vec!["three_prime_utr", "five_prime_utr"]),
+            Class::FivePrimeUtr
+        );
+        assert_eq!(Class::from_names(Vec::new()), Class::Intergenic);
+    }
+
+    #[test]
+    fn test_count_bases() {
+        let features = vec![
+            (
+                Class::Cds,
+                vec![Feature::new(String::from("sq0"), 11, 20, Strand::Forward)],
+            ),
+            (
+                Class::Exon,
+                vec![
+                    Feature::new(String::from("sq0"), 1, 20, Strand::Forward),
+                    Feature::new(String::from("sq0"), 31, 40, Strand::Forward),
+                ],
+            ),
+            (
+                Class::Intron,
+                vec![Feature::new(String::from("sq0"), 21, 30, Strand::Forward)],
+            ),
+        ];
+
+        let reference_sequence_lengths = [(String::from("sq0"), 100), (String::from("sq1"), 50)];
+
+        let bases = count_bases(&features, &reference_sequence_lengths);
+
+        assert_eq!(bases["cds"], 10);
+        assert_eq!(bases["five_prime_utr"], 0);
+        assert_eq!(bases["three_prime_utr"], 0);
+        assert_eq!(bases["exon"], 20);
+        assert_eq!(bases["intron"], 10);
+        assert_eq!(bases["intergenic"], 110);
+    }
+
+    #[test]
+    fn test_write_distribution() -> io::Result<()> {
+        let bases: HashMap<_, _> = vec![("cds", 2000), ("intron", 500), ("intergenic", 1000)]
+            .into_iter()
+            .collect();
+        let reads: HashMap<_, _> = vec![("cds", 8), ("intron", 1)].into_iter().collect();
+
+        let mut buf = Vec::new();
+        write_distribution(&mut buf, &bases, &reads)?;
+
+        let expected = b"\
+class\tbases\treads\treads_per_kb
+cds\t2000\t8\t4.00
+five_prime_utr\t0\t0\t0.00
+three_prime_utr\t0\t0\t0.00
+exon\t0\t0\t0.00
+intron\t500\t1\t2.00
+intergenic\t1000\t0\t0.00
+total\t3500\t9\t2.57
+";
+
+        assert_eq!(&buf[..], &expected[..]);
+
+        Ok(())
+    }
+}
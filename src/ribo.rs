@@ -0,0 +1,353 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    ops::RangeInclusive,
+};
+
+use noodles_bam::record::{cigar, Cigar};
+use noodles_sam as sam;
+
+const COMMENT_PREFIX: char = '#';
+
+/// The number of reading frames.
+pub const FRAME_COUNT: usize = 3;
+
+/// Reads a P-site offset table.
+///
+/// Each line is a read length and the offset of the P-site from the 5' end of
+/// the read, tab-delimited, e.g., `28\t12`. Empty lines and lines starting with
+/// `#` are skipped.
+pub fn read_offsets<R>(reader: &mut R) -> io::Result<HashMap<u32, u64>>
+where
+    R: BufRead,
+{
+    let mut offsets = HashMap::new();
+    let mut buf = String::new();
+
+    loop {
+        buf.clear();
+
+        if reader.read_line(&mut buf)? == 0 {
+            break;
+        }
+
+        let line = buf.trim_end_matches(&['\n', '\r'][..]);
+
+        if line.is_empty() || line.starts_with(COMMENT_PREFIX) {
+            continue;
+        }
+
+        let mut fields = line.split('\t');
+
+        let (len, offset) = match (fields.next(), fields.next()) {
+            (Some(len), Some(offset)) => (len, offset),
+            _ => return Err(invalid_data(format!("invalid offset: {}", line))),
+        };
+
+        let len = len
+            .parse()
+            .map_err(|_| invalid_data(format!("invalid read length: {}", len)))?;
+
+        let offset = offset
+            .parse()
+            .map_err(|_| invalid_data(format!("invalid offset: {}", offset)))?;
+
+        if offsets.insert(len, offset).is_some() {
+            return Err(invalid_data(format!("duplicate read length: {}", len)));
+        }
+    }
+
+    Ok(offsets)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Returns the read length of an alignment, i.e., the number of bases consumed
+/// in the read, including soft clips.
+pub fn read_len(cigar: &Cigar) -> u32 {
+    use sam::record::cigar::op::Kind;
+
+    cigar
+        .ops()
+        .filter(|op| {
+            matches!(
+                op.kind(),
+                Kind::Match | Kind::Insertion | Kind::SoftClip | Kind::SeqMatch | Kind::SeqMismatch
+            )
+        })
+        .map(|op| op.len())
+        .sum()
+}
+
+/// Returns the number of soft clipped bases at the 5' end of an alignment, i.e.,
+/// the start of a forward alignment or the end of a reverse alignment.
+///
+/// Hard clips are not in the read and are skipped.
+pub fn five_prime_soft_clip_len(cigar: &Cigar, is_reverse: bool) -> u32 {
+    use sam::record::cigar::op::Kind;
+
+    let ops: Vec<_> = cigar.ops().collect();
+
+    let is_clip = |kind: &Kind| matches!(kind, Kind::SoftClip | Kind::HardClip);
+    let soft_clip_len = |op: &cigar::Op| match op.kind() {
+        Kind::SoftClip => op.len(),
+        _ => 0,
+    };
+
+    if is_reverse {
+        ops.iter()
+            .rev()
+            .take_while(|op| is_clip(&op.kind()))
+            .map(soft_clip_len)
+            .sum()
+    } else {
+        ops.iter()
+            .take_while(|op| is_clip(&op.kind()))
+            .map(soft_clip_len)
+            .sum()
+    }
+}
+
+/// Returns the P-site of an alignment, given its match intervals.
+///
+/// The offset is the number of aligned bases from the 5' end, i.e., the start
+/// of a forward alignment or the end of a reverse alignment, excluding soft
+/// clips (see [`five_prime_soft_clip_len`]). Skipped regions, e.g., introns,
+/// are not counted. This returns `None` if the offset is past
+/// the end of the alignment.
+pub fn p_site(intervals: &[RangeInclusive<u64>], is_reverse: bool, offset: u64) -> Option<u64> {
+    let mut remaining = offset;
+
+    if is_reverse {
+        for interval in intervals.iter().rev() {
+            let len = interval.end() - interval.start() + 1;
+
+            if remaining < len {
+                return Some(interval.end() - remaining);
+            }
+
+            remaining -= len;
+        }
+    } else {
+        for interval in intervals {
+            let len = interval.end() - interval.start() + 1;
+
+            if remaining < len {
+                return Some(interval.start() + remaining);
+            }
+
+            remaining -= len;
+        }
+    }
+
+    None
+}
+
+/// Returns the frame of a transcript position in a coding sequence.
+///
+/// The phase is the number of bases before the first complete codon, e.g.,
+/// when the coding sequence is incomplete at its 5' end.
+pub fn frame(position: u64, phase: u8) -> usize {
+    let n = FRAME_COUNT as u64;
+    ((position + n - u64::from(phase) % n) % n) as usize
+}
+
+/// P-site counts of a feature
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Counts {
+    total: u64,
+    frames: [u64; FRAME_COUNT],
+}
+
+impl Counts {
+    /// Adds a P-site.
+    ///
+    /// A P-site without a frame, e.g., when the transcripts of a gene disagree,
+    /// is only added to the total.
+    pub fn add(&mut self, frame: Option<usize>) {
+        self.total += 1;
+
+        if let Some(frame) = frame {
+            self.frames[frame % FRAME_COUNT] += 1;
+        }
+    }
+
+    fn add_counts(&mut self, other: &Self) {
+        self.total += other.total;
+
+        for (a, b) in self.frames.iter_mut().zip(&other.frames) {
+            *a += b;
+        }
+    }
+}
+
+/// A summary of P-site counts
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub counts: HashMap<String, Counts>,
+    pub no_feature: u64,
+    pub ambiguous: u64,
+    pub no_offset: u64,
+}
+
+/// Writes P-site counts.
+///
+/// The first row is a header, followed by one row per feature with the total
+/// count and the count in each frame. The trailer is the total of each column
+/// (`__total`), followed by the number of P-sites that do not intersect a
+/// feature (`__no_feature`) or intersect multiple features (`__ambiguous`) and
+/// the number of records without an offset for their read length
+/// (`__no_offset`).
+pub fn write_counts<W>(writer: &mut W, ids: &[String], summary: &Summary) -> io::Result<()>
+where
+    W: Write,
+{
+    writeln!(writer, "id\tcount\tframe_0\tframe_1\tframe_2")?;
+
+    let default_counts = Counts::default();
+    let mut total = Counts::default();
+
+    for id in ids {
+        let counts = summary.counts.get(id).unwrap_or(&default_counts);
+        write_row(writer, id, counts)?;
+        total.add_counts(counts);
+    }
+
+    write_row(writer, "__total", &total)?;
+
+    writeln!(writer, "__no_feature\t{}", summary.no_feature)?;
+    writeln!(writer, "__ambiguous\t{}", summary.ambiguous)?;
+    writeln!(writer, "__no_offset\t{}", summary.no_offset)?;
+
+    Ok(())
+}
+
+fn write_row<W>(writer: &mut W, id: &str, counts: &Counts) -> io::Result<()>
+where
+    W: Write,
+{
+    writeln!(
+        writer,
+        "{}\t{}\t{}\t{}\t{}",
+        id, counts.total, counts.frames[0], counts.frames[1], counts.frames[2]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles_sam::record::cigar::op;
+
+    use super::*;
+
+    #[test]
+    fn test_read_offsets() -> io::Result<()> {
+        let data = b"# length\toffset\n28\t12\n29\t12\n\n30\t13\n";
+        let offsets = read_offsets(&mut &data[..])?;
+
+        let expected: HashMap<_, _> = vec![(28, 12), (29, 12), (30, 13)].into_iter().collect();
+        assert_eq!(offsets, expected);
+
+        assert!(read_offsets(&mut &b"28\n"[..]).is_err());
+        assert!(read_offsets(&mut &b"28\tx\n"[..]).is_err());
+        assert!(read_offsets(&mut &b"28\t12\n28\t13\n"[..]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_len() {
+        let ops = [
+            u32::from(cigar::Op::new(op::Kind::SoftClip, 2)).to_le_bytes(),
+            u32::from(cigar::Op::new(op::Kind::Match, 10)).to_le_bytes(),
+            u32::from(cigar::Op::new(op::Kind::Skip, 100)).to_le_bytes(),
+            u32::from(cigar::Op::new(op::Kind::Match, 15)).to_le_bytes(),
+            u32::from(cigar::Op::new(op::Kind::Insertion, 1)).to_le_bytes(),
+            u32::from(cigar::Op::new(op::Kind::Deletion, 3)).to_le_bytes(),
+        ];
+        let raw_cigar: Vec<_> = ops.iter().flatten().copied().collect();
+        let cigar = Cigar::new(&raw_cigar);
+
+        assert_eq!(read_len(&cigar), 28);
+    }
+
+    #[test]
+    fn test_five_prime_soft_clip_len() {
+        let ops = [
+            u32::from(cigar::Op::new(op::Kind::HardClip, 5)).to_le_bytes(),
+            u32::from(cigar::Op::new(op::Kind::SoftClip, 2)).to_le_bytes(),
+            u32::from(cigar::Op::new(op::Kind::Match, 25)).to_le_bytes(),
+            u32::from(cigar::Op::new(op::Kind::SoftClip, 3)).to_le_bytes(),
+        ];
+        let raw_cigar: Vec<_> = ops.iter().flatten().copied().collect();
+        let cigar = Cigar::new(&raw_cigar);
+
+        assert_eq!(five_prime_soft_clip_len(&cigar, false), 2);
+        assert_eq!(five_prime_soft_clip_len(&cigar, true), 3);
+
+        let raw_cigar = u32::from(cigar::Op::new(op::Kind::Match, 28)).to_le_bytes();
+        let cigar = Cigar::new(&raw_cigar);
+
+        assert_eq!(five_prime_soft_clip_len(&cigar, false), 0);
+        assert_eq!(five_prime_soft_clip_len(&cigar, true), 0);
+    }
+
+    #[test]
+    fn test_frame() {
+        assert_eq!(frame(0, 0), 0);
+        assert_eq!(frame(4, 0), 1);
+        assert_eq!(frame(0, 1), 2);
+        assert_eq!(frame(1, 1), 0);
+        assert_eq!(frame(3, 2), 1);
+    }
+
+    #[test]
+    fn test_p_site() {
+        let intervals = [1..=10, 111..=125];
+
+        assert_eq!(p_site(&intervals, false, 0), Some(1));
+        assert_eq!(p_site(&intervals, false, 9), Some(10));
+        assert_eq!(p_site(&intervals, false, 12), Some(113));
+        assert_eq!(p_site(&intervals, false, 25), None);
+
+        assert_eq!(p_site(&intervals, true, 0), Some(125));
+        assert_eq!(p_site(&intervals, true, 12), Some(113));
+        assert_eq!(p_site(&intervals, true, 16), Some(9));
+        assert_eq!(p_site(&intervals, true, 25), None);
+    }
+
+    #[test]
+    fn test_write_counts() -> io::Result<()> {
+        let mut counts = Counts::default();
+        counts.add(Some(0));
+        counts.add(Some(0));
+        counts.add(Some(2));
+        counts.add(None);
+
+        let mut summary = Summary::default();
+        summary.counts.insert(String::from("gene0"), counts);
+        summary.no_feature = 5;
+        summary.ambiguous = 1;
+        summary.no_offset = 2;
+
+        let ids = [String::from("gene0"), String::from("gene1")];
+
+        let mut buf = Vec::new();
+        write_counts(&mut buf, &ids, &summary)?;
+
+        let expected = b"\
+id\tcount\tframe_0\tframe_1\tframe_2
+gene0\t4\t2\t0\t1
+gene1\t0\t0\t0\t0
+__total\t4\t2\t0\t1
+__no_feature\t5
+__ambiguous\t1
+__no_offset\t2
+";
+
+        assert_eq!(&buf[..], &expected[..]);
+
+        Ok(())
+    }
+}
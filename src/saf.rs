@@ -0,0 +1,160 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead},
+    str::FromStr,
+};
+
+use log::info;
+use noodles_gff as gff;
+
+use crate::Feature;
+
+const DELIMITER: char = '\t';
+const COMMENT_PREFIX: char = '#';
+const HEADER_PREFIX: &str = "GeneID\t";
+const FIELD_COUNT: usize = 5;
+
+/// A SAF (simplified annotation format) record.
+///
+/// SAF is featureCounts' annotation format with the columns `GeneID`, `Chr`,
+/// `Start`, `End`, and `Strand`. Positions are 1-based and inclusive.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Record {
+    id: String,
+    feature: Feature,
+}
+
+impl Record {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn feature(&self) -> &Feature {
+        &self.feature
+    }
+}
+
+impl FromStr for Record {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<_> = s.split(DELIMITER).collect();
+
+        if fields.len() < FIELD_COUNT {
+            return Err(invalid_data(format!(
+                "expected {} fields, got {}",
+                FIELD_COUNT,
+                fields.len()
+            )));
+        }
+
+        let start = fields[2]
+            .parse()
+            .map_err(|_| invalid_data(format!("invalid start: {}", fields[2])))?;
+
+        let end = fields[3]
+            .parse()
+            .map_err(|_| invalid_data(format!("invalid end: {}", fields[3])))?;
+
+        let strand: gff::record::Strand = fields[4]
+            .parse()
+            .map_err(|_| invalid_data(format!("invalid strand: {}", fields[4])))?;
+
+        Ok(Self {
+            id: fields[0].into(),
+            feature: Feature::new(fields[1].into(), start, end, strand),
+        })
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads features from SAF records.
+///
+/// The feature ID is the `GeneID` column. Records with the same ID are
+/// combined.
+pub fn read_features<R>(reader: &mut R) -> io::Result<HashMap<String, Vec<Feature>>>
+where
+    R: BufRead,
+{
+    let mut features: HashMap<String, Vec<Feature>> = HashMap::new();
+    let mut buf = String::new();
+
+    info!("reading features");
+
+    loop {
+        buf.clear();
+
+        if reader.read_line(&mut buf)? == 0 {
+            break;
+        }
+
+        let line = buf.trim_end_matches(&['\n', '\r'][..]);
+
+        if line.is_empty() || line.starts_with(COMMENT_PREFIX) || line.starts_with(HEADER_PREFIX) {
+            continue;
+        }
+
+        let record: Record = line.parse()?;
+
+        features
+            .entry(record.id().into())
+            .or_default()
+            .push(record.feature().clone());
+    }
+
+    info!("read {} unique features", features.len());
+
+    Ok(features)
+}
+
+#[cfg(test)]
+mod tests {
+    use noodles_gff::record::Strand;
+
+    use super::*;
+
+    #[test]
+    fn test_from_str() -> io::Result<()> {
+        let record: Record = "gene0\tsq0\t8\t13\t-".parse()?;
+        assert_eq!(record.id(), "gene0");
+        assert_eq!(
+            record.feature(),
+            &Feature::new(String::from("sq0"), 8, 13, Strand::Reverse)
+        );
+
+        assert!("gene0\tsq0\t8\t13".parse::<Record>().is_err());
+        assert!("gene0\tsq0\tx\t13\t-".parse::<Record>().is_err());
+        assert!("gene0\tsq0\t8\t13\tx".parse::<Record>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_features() -> io::Result<()> {
+        let data = b"GeneID\tChr\tStart\tEnd\tStrand
+gene0\tsq0\t1\t10\t+
+gene0\tsq0\t21\t30\t+
+gene1\tsq1\t41\t50\t.
+";
+
+        let features = read_features(&mut &data[..])?;
+
+        assert_eq!(features.len(), 2);
+        assert_eq!(
+            features["gene0"],
+            [
+                Feature::new(String::from("sq0"), 1, 10, Strand::Forward),
+                Feature::new(String::from("sq0"), 21, 30, Strand::Forward),
+            ]
+        );
+        assert_eq!(
+            features["gene1"],
+            [Feature::new(String::from("sq1"), 41, 50, Strand::None)]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, BufRead, Write},
+};
+
+/// Reads a cell barcode whitelist.
+///
+/// The whitelist has one barcode per line. Empty lines are skipped.
+pub fn read_whitelist<R>(reader: &mut R) -> io::Result<HashSet<String>>
+where
+    R: BufRead,
+{
+    let mut barcodes = HashSet::new();
+
+    for result in reader.lines() {
+        let line = result?;
+        let barcode = line.trim();
+
+        if !barcode.is_empty() {
+            barcodes.insert(barcode.into());
+        }
+    }
+
+    Ok(barcodes)
+}
+
+/// Unique molecular identifiers (UMIs) by cell barcode and feature
+#[derive(Debug, Default)]
+pub struct Matrix {
+    umis: HashMap<String, HashMap<String, HashSet<String>>>,
+}
+
+impl Matrix {
+    /// Adds a UMI of a feature in a cell.
+    ///
+    /// Duplicate UMIs of the same cell and feature are collapsed into a single
+    /// count.
+    pub fn add(&mut self, barcode: &str, id: &str, umi: &str) {
+        let features = match self.umis.get_mut(barcode) {
+            Some(f) => f,
+            None => self.umis.entry(barcode.into()).or_default(),
+        };
+
+        let umis = match features.get_mut(id) {
+            Some(u) => u,
+            None => features.entry(id.into()).or_default(),
+        };
+
+        if !umis.contains(umi) {
+            umis.insert(umi.into());
+        }
+    }
+
+    /// Returns the sorted cell barcodes with at least one count.
+    pub fn barcodes(&self) -> Vec<&str> {
+        let mut barcodes: Vec<_> = self.umis.keys().map(|b| b.as_str()).collect();
+        barcodes.sort_unstable();
+        barcodes
+    }
+
+    /// Returns the number of UMIs of a feature in a cell.
+    pub fn get(&self, barcode: &str, id: &str) -> usize {
+        self.umis
+            .get(barcode)
+            .and_then(|features| features.get(id))
+            .map(|umis| umis.len())
+            .unwrap_or(0)
+    }
+
+    /// Returns the total number of UMIs.
+    pub fn umi_count(&self) -> usize {
+        self.umis
+            .values()
+            .flat_map(|features| features.values())
+            .map(|umis| umis.len())
+            .sum()
+    }
+}
+
+/// Writes a matrix as a Matrix Market coordinate file.
+///
+/// Rows are features and columns are cell barcodes, in the given orders. Both
+/// are 1-based, and only nonzero entries are written.
+pub fn write_matrix<W>(
+    writer: &mut W,
+    ids: &[String],
+    barcodes: &[&str],
+    matrix: &Matrix,
+) -> io::Result<()>
+where
+    W: Write,
+{
+    let rows: HashMap<_, _> = ids
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (id.as_str(), i + 1))
+        .collect();
+
+    let mut entries = Vec::new();
+
+    for (j, barcode) in barcodes.iter().enumerate() {
+        let features = match matrix.umis.get(*barcode) {
+            Some(f) => f,
+            None => continue,
+        };
+
+        let start = entries.len();
+
+        for (id, umis) in features {
+            if let Some(&i) = rows.get(id.as_str()) {
+                entries.push((i, j + 1, umis.len()));
+            }
+        }
+
+        entries[start..].sort_unstable();
+    }
+
+    writeln!(writer, "%%MatrixMarket matrix coordinate integer general")?;
+    writeln!(writer, "{} {} {}", ids.len(), barcodes.len(), entries.len())?;
+
+    for (i, j, count) in entries {
+        writeln!(writer, "{} {} {}", i, j, count)?;
+    }
+
+    Ok(())
+}
+
+/// Writes a list of names, one per line, e.g., the features or cell barcodes
+/// of a matrix.
+pub fn write_names<W, S>(writer: &mut W, names: &[S]) -> io::Result<()>
+where
+    W: Write,
+    S: AsRef<str>,
+{
+    for name in names {
+        writeln!(writer, "{}", name.as_ref())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_whitelist() -> io::Result<()> {
+        let data = b"AAACCTGA\nAAACCTGC\n\nAAACCTGG\n";
+        let barcodes = read_whitelist(&mut &data[..])?;
+
+        assert_eq!(barcodes.len(), 3);
+        assert!(barcodes.contains("AAACCTGA"));
+        assert!(barcodes.contains("AAACCTGC"));
+        assert!(barcodes.contains("AAACCTGG"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add() {
+        let mut matrix = Matrix::default();
+        matrix.add("AAACCTGA", "AADAT", "TTGCA");
+        matrix.add("AAACCTGA", "AADAT", "TTGCA");
+        matrix.add("AAACCTGA", "AADAT", "TTGCC");
+        matrix.add("AAACCTGA", "CLN3", "TTGCA");
+        matrix.add("AAACCTGC", "AADAT", "TTGCA");
+
+        assert_eq!(matrix.get("AAACCTGA", "AADAT"), 2);
+        assert_eq!(matrix.get("AAACCTGA", "CLN3"), 1);
+        assert_eq!(matrix.get("AAACCTGC", "AADAT"), 1);
+        assert_eq!(matrix.get("AAACCTGC", "CLN3"), 0);
+
+        assert_eq!(matrix.barcodes(), ["AAACCTGA", "AAACCTGC"]);
+        assert_eq!(matrix.umi_count(), 4);
+    }
+
+    #[test]
+    fn test_write_matrix() -> io::Result<()> {
+        let mut matrix = Matrix::default();
+        matrix.add("AAACCTGA", "AADAT", "TTGCA");
+        matrix.add("AAACCTGA", "AADAT", "TTGCC");
+        matrix.add("AAACCTGC", "NEO1", "TTGCA");
+
+        let ids = [
+            String::from("AADAT"),
+            String::from("CLN3"),
+            String::from("NEO1"),
+        ];
+        let barcodes = matrix.barcodes();
+
+        let mut buf = Vec::new();
+        write_matrix(&mut buf, &ids, &barcodes, &matrix)?;
+
+        let expected = b"\
+%%MatrixMarket matrix coordinate integer general
+3 2 2
+1 1 2
+3 2 1
+";
+
+        assert_eq!(&buf[..], &expected[..]);
+
+        Ok(())
+    }
+}